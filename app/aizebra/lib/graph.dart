@@ -2,7 +2,6 @@ import 'dart:ui';
 
 import 'package:flutter/gestures.dart';
 import 'package:flutter/material.dart';
-import 'package:flutter/foundation.dart';
 import 'dart:math';
 import 'rust_lib.dart';
 import "package:intl/intl.dart" hide TextDirection;
@@ -24,10 +23,6 @@ class CurvePainter extends CustomPainter {
   final List<int> expressions;
   final double scale;
   final Offset focus;
-  static final double samplingRate = switch (defaultTargetPlatform) {
-  TargetPlatform.android => 250,
-  _ => 1000,
-};
 
   CurvePainter(this.expressions, this.scale, this.focus);
 
@@ -53,10 +48,6 @@ class CurvePainter extends CustomPainter {
     final rangeYMin = -(size.height-focus.dy-size.height*0.5)/scale;
     final rangeYMax = -(0-focus.dy-size.height*0.5)/scale;
 
-    final yMinOutScreen = rangeYMin-rangeYMin.abs()*10.0;
-    final yMaxOutScreen = rangeYMax+rangeYMax.abs()*10.0;
-
-    final stepSampling = (rangeXMax - rangeXMin)/samplingRate;
     final stepGrid = pow(10.0, log10((rangeXMax - rangeXMin)/10.0).round()) as double;
 
     Paint painterBack = Paint();
@@ -76,16 +67,41 @@ class CurvePainter extends CustomPainter {
       painterCurve.style = PaintingStyle.stroke;
       painterCurve.color = colors[iExpression % colors.length];
       painterCurve.strokeWidth = 3.0;
-      Offset? lastPoint;
-      for (double i = rangeXMin; i <= rangeXMax; i += stepSampling) {
-        final y = Parser().evaluate(iExpression, i)!;
-        final Offset point = toScene(Offset(i, y.isNaN ? double.nan : -y.clamp(yMinOutScreen, yMaxOutScreen)), size);
-
-        if (lastPoint != null && !lastPoint.dy.isNaN && !y.isNaN) {
-          canvas.drawLine(lastPoint, point, painterCurve);
+      // Within half a pixel of the curve
+      final polylines = Parser().sampleCurve(iExpression, rangeXMin, rangeXMax, rangeYMin, rangeYMax, 0.5 / size.height) ?? [];
+      for (final polyline in polylines) {
+        final path = Path();
+        for (final (i, (x, y)) in polyline.indexed) {
+          final point = toScene(Offset(x, -y), size);
+          if (i == 0) {
+            path.moveTo(point.dx, point.dy);
+          } else {
+            path.lineTo(point.dx, point.dy);
+          }
+        }
+        canvas.drawPath(path, painterCurve);
+      }
+      // Minima and maxima are marked, inflection points are left out
+      final painterPoint = Paint()..color = painterCurve.color;
+      for (final point in Parser().criticalPoints(iExpression, rangeXMin, rangeXMax) ?? <({PointKind kind, double x, double y})>[]) {
+        if (point.kind != PointKind.inflection && point.y >= rangeYMin && point.y <= rangeYMax) {
+          canvas.drawCircle(toScene(Offset(point.x, -point.y), size), 5.0, painterPoint);
         }
+      }
+    }
 
-        lastPoint = point;
+    // Where two curves meet, like an operating point
+    Paint painterCrossing = Paint();
+    painterCrossing.style = PaintingStyle.stroke;
+    painterCrossing.color = Colors.black87;
+    painterCrossing.strokeWidth = 2.0;
+    for (final (i, iA) in expressions.indexed) {
+      for (final iB in expressions.skip(i + 1)) {
+        for (final point in Parser().intersections(iA, iB, rangeXMin, rangeXMax) ?? <({double x, double y, int multiplicity, int iterations, bool converged})>[]) {
+          if (point.y >= rangeYMin && point.y <= rangeYMax) {
+            canvas.drawCircle(toScene(Offset(point.x, -point.y), size), 6.0, painterCrossing);
+          }
+        }
       }
     }
 
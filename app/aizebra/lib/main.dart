@@ -1,4 +1,5 @@
 import 'package:flutter/material.dart';
+import 'package:flutter/services.dart';
 
 import 'rust_lib.dart';
 import 'graph.dart';
@@ -13,6 +14,18 @@ class MyApp extends StatelessWidget {
   const MyApp({super.key});
 
   // This widget is the root of your application.
+  // Fractions come with their decimal value, integers keep all their digits
+  String formatResult() {
+    if (exact == null) return formatNumber(res!);
+    return exact!.contains('/') ? "${exact!} ≈ ${formatNumber(res!)}" : exact!;
+  }
+
+  String formatComplex(double re, double im) {
+    final imaginary = "${im.abs() == 1 ? "" : formatNumber(im.abs())}i";
+    if (re == 0) return im < 0 ? "-$imaginary" : imaginary;
+    return "${formatNumber(re)} ${im < 0 ? "-" : "+"} $imaginary";
+  }
+
   @override
   Widget build(BuildContext context) {
     return MaterialApp(
@@ -47,6 +60,8 @@ class _MyHomePageState extends State<MyHomePage> {
   TextEditingController controllerX = TextEditingController(text: "20");
   TextEditingController controllerNewParam = TextEditingController(text: "a");
   double? res;
+  // Exact result like 7/2 when the expression only used exact operations, or complex one like 1 + 2i
+  String? exact;
   String? error;
   int indexE = -1;
   List<LastExpression> last = [];
@@ -57,18 +72,29 @@ class _MyHomePageState extends State<MyHomePage> {
 
   void _incrementCounter() {
     setState(() {
-      if (!Parser().parse(controllerExpr.text)) {
+      // Commands like \frac mean the input is LaTeX
+      final isLatex = controllerExpr.text.contains('\\');
+      if (!(isLatex ? Parser().parseLatex(controllerExpr.text) : Parser().parse(controllerExpr.text))) {
         error = Parser().lastError;
         res = null;
         return;
       }
       final x = double.tryParse(controllerX.text) ?? 0;
-      res = Parser().evaluate(++indexE, x);
+      final result = Parser().evaluateExact(++indexE, x);
+      res = result?.value;
+      exact = result?.fraction;
+      // Without real value like sqrt(-1), the complex one is shown
+      if (res != null && res!.isNaN) {
+        final z = Parser().evaluateComplex(indexE, x);
+        if (z != null && z.im != 0) exact = formatComplex(z.re, z.im);
+      }
       if (res == null) {
         error = Parser().lastError;
       } else {
+        // Definitions keep their text, the parsed expression is only the call f(x)
+        final isDefinition = !isLatex && RegExp(r'^\s*\w+\s*\([^)]*\)\s*=(?!=)').hasMatch(controllerExpr.text);
         last.insert(0, (
-          expression: controllerExpr.text,
+          expression: isDefinition ? controllerExpr.text : Parser().expressionString(indexE) ?? controllerExpr.text,
           x: x,
           res: res!,
           iExpression: indexE,
@@ -102,7 +128,7 @@ class _MyHomePageState extends State<MyHomePage> {
                         child: Center(
                           child: Text(
                             style: TextStyle(fontSize: 16),
-                            res == null ? (error ?? "") : formatNumber(res!),
+                            res == null ? (error ?? "") : formatResult(),
                           ),
                         ),
                       ),
@@ -369,6 +395,49 @@ class _MyHomePageState extends State<MyHomePage> {
                                                                 .expression,
                                                           ),
                                                           Spacer(),
+                                                          IconButton(
+                                                            onPressed: () => setState(() {
+                                                              final iDerivative = Parser().derive(
+                                                                last[index]
+                                                                    .iExpression,
+                                                              );
+                                                              if (iDerivative == null) {
+                                                                return;
+                                                              }
+                                                              indexE = iDerivative;
+                                                              last.insert(0, (
+                                                                expression:
+                                                                    "d(${last[index].expression}, x)",
+                                                                iExpression:
+                                                                    iDerivative,
+                                                                res: 0,
+                                                                x: 0,
+                                                                visible: true,
+                                                              ));
+                                                            }),
+                                                            icon: Icon(
+                                                              Icons.trending_up,
+                                                            ),
+                                                          ),
+                                                          IconButton(
+                                                            onPressed: () {
+                                                              final latex = Parser().expressionLatex(
+                                                                last[index]
+                                                                    .iExpression,
+                                                              );
+                                                              if (latex == null) {
+                                                                return;
+                                                              }
+                                                              Clipboard.setData(
+                                                                ClipboardData(
+                                                                  text: latex,
+                                                                ),
+                                                              );
+                                                            },
+                                                            icon: Icon(
+                                                              Icons.content_copy,
+                                                            ),
+                                                          ),
                                                           IconButton(
                                                             onPressed: () => setState(() {
                                                               last[index] = (
@@ -16,15 +16,60 @@ typedef GetLastErrorFuncDart = ffi.Pointer<Utf8> Function();
 typedef FreeLastErrorFunc     = ffi.Void Function(ffi.Pointer<Utf8>);
 typedef FreeLastErrorFuncDart = void Function(ffi.Pointer<Utf8>);
 
+typedef GetLastErrorSpanFunc     = ffi.Int32 Function(ffi.Pointer<ffi.Int32>, ffi.Pointer<ffi.Int32>);
+typedef GetLastErrorSpanFuncDart = int Function(ffi.Pointer<ffi.Int32>, ffi.Pointer<ffi.Int32>);
+
 typedef ParseFunc = ffi.Int32 Function(ffi.Pointer<Utf8>);
 typedef ParseFuncDart = int Function(ffi.Pointer<Utf8>);
 
 typedef EvaluateFunc = ffi.Float Function(ffi.Int32, ffi.Float);
 typedef EvaluateFuncDart = double Function(int, double);
 
+typedef EvaluateF64Func = ffi.Double Function(ffi.Int32, ffi.Double);
+
+typedef EvaluateComplexFunc = ffi.Int32 Function(ffi.Int32, ffi.Double, ffi.Pointer<ffi.Double>, ffi.Pointer<ffi.Double>);
+typedef EvaluateComplexFuncDart = int Function(int, double, ffi.Pointer<ffi.Double>, ffi.Pointer<ffi.Double>);
+typedef EvaluateWithDerivativeFunc = ffi.Int32 Function(ffi.Int32, ffi.Double, ffi.Pointer<ffi.Double>, ffi.Pointer<ffi.Double>);
+typedef EvaluateWithDerivativeFuncDart = int Function(int, double, ffi.Pointer<ffi.Double>, ffi.Pointer<ffi.Double>);
+typedef EvaluateIntervalFunc = ffi.Int32 Function(ffi.Int32, ffi.Double, ffi.Double, ffi.Pointer<ffi.Double>, ffi.Pointer<ffi.Double>);
+typedef EvaluateIntervalFuncDart = int Function(int, double, double, ffi.Pointer<ffi.Double>, ffi.Pointer<ffi.Double>);
+
+typedef EvaluateExactFunc = ffi.Int32 Function(ffi.Int32, ffi.Double, ffi.Pointer<ffi.Double>, ffi.Pointer<Utf8>, ffi.Int32);
+typedef EvaluateExactFuncDart = int Function(int, double, ffi.Pointer<ffi.Double>, ffi.Pointer<Utf8>, int);
+
+typedef DeriveFunc = ffi.Int32 Function(ffi.Int32, ffi.Pointer<Utf8>);
+typedef DeriveFuncDart = int Function(int, ffi.Pointer<Utf8>);
+
+typedef EvaluateRangeFunc = ffi.Int32 Function(ffi.Int32, ffi.Float, ffi.Float, ffi.Int32, ffi.Pointer<ffi.Float>, ffi.Pointer<ffi.Uint8>);
+typedef EvaluateRangeFuncDart = int Function(int, double, double, int, ffi.Pointer<ffi.Float>, ffi.Pointer<ffi.Uint8>);
+
+typedef SampleCurveFunc = ffi.Int32 Function(ffi.Int32, ffi.Double, ffi.Double, ffi.Double, ffi.Double, ffi.Double, ffi.Pointer<ffi.Double>, ffi.Int32);
+typedef SampleCurveFuncDart = int Function(int, double, double, double, double, double, ffi.Pointer<ffi.Double>, int);
+
+typedef RootsFunc = ffi.Int32 Function(ffi.Int32, ffi.Double, ffi.Double, ffi.Pointer<ffi.Double>, ffi.Int32);
+typedef RootsFuncDart = int Function(int, double, double, ffi.Pointer<ffi.Double>, int);
+
+typedef IntersectionsFunc = ffi.Int32 Function(ffi.Int32, ffi.Int32, ffi.Double, ffi.Double, ffi.Pointer<ffi.Double>, ffi.Int32);
+typedef IntersectionsFuncDart = int Function(int, int, double, double, ffi.Pointer<ffi.Double>, int);
+
+typedef CriticalPointsFunc = ffi.Int32 Function(ffi.Int32, ffi.Double, ffi.Double, ffi.Pointer<ffi.Double>, ffi.Int32);
+typedef CriticalPointsFuncDart = int Function(int, double, double, ffi.Pointer<ffi.Double>, int);
+
+typedef ExpressionToStringFunc = ffi.Int32 Function(ffi.Int32, ffi.Pointer<Utf8>, ffi.Int32);
+typedef ExpressionToStringFuncDart = int Function(int, ffi.Pointer<Utf8>, int);
+
+typedef SetSimplifyFunc = ffi.Void Function(ffi.Int32);
+typedef SetSimplifyFuncDart = void Function(int);
+
+// In the order of the codes written by critical_points
+enum PointKind { minimum, maximum, inflection }
+
 class Parser {
   ffi.DynamicLibrary dylib;
   String? lastError;
+  // UTF-16 offsets, the indexes of Dart strings, of the part of the input the last parse error points at
+  int? lastErrorStart;
+  int? lastErrorEnd;
 
   static final Parser _singleton = Parser._internal();
 
@@ -34,21 +79,119 @@ class Parser {
 
   Parser._internal() : dylib =_openRustLib();
 
-  bool parse(String s){
-      final parseFunc = dylib.lookupFunction<ParseFunc, ParseFuncDart>('parse');
+  bool parse(String s) => _parse(s, 'parse');
+
+  // Reads a LaTeX formula like \frac{1}{2}\rho v^{2}
+  bool parseLatex(String s) => _parse(s, 'parse_latex');
+
+  bool _parse(String s, String symbol){
+      final parseFunc = dylib.lookupFunction<ParseFunc, ParseFuncDart>(symbol);
       final res = parseFunc(s.toNativeUtf8());
       if (res < 0) {
         lastError = _getLastError();
+        _getLastErrorSpan();
         return false;
       }
       lastError = null;
+      lastErrorStart = null;
+      lastErrorEnd = null;
       return true;
   }
 
+  // Simplifies the expressions parsed or derived afterwards
+  void setSimplify(bool enabled){
+    final setSimplifyFunc = dylib.lookupFunction<SetSimplifyFunc, SetSimplifyFuncDart>('set_simplify');
+    setSimplifyFunc(enabled ? 1 : 0);
+  }
+
   double? evaluate(int index, double x){
     final evaluateFunc = dylib.lookupFunction<EvaluateFunc, EvaluateFuncDart>('evaluate');
-    final res = evaluateFunc(index, x);
+    return _checked(evaluateFunc(index, x));
+  }
+
+  // Same as evaluate in double precision
+  double? evaluateF64(int index, double x){
+    final evaluateFunc = dylib.lookupFunction<EvaluateF64Func, EvaluateFuncDart>('evaluate_f64');
+    return _checked(evaluateFunc(index, x));
+  }
+
+  // Evaluates once with exact fractions, fraction is null when the result is a float or too long to show
+  ({double value, String? fraction})? evaluateExact(int index, double x){
+    final evaluateExactFunc = dylib.lookupFunction<EvaluateExactFunc, EvaluateExactFuncDart>('evaluate_exact');
+    // Not retried with a larger buffer, evaluating again would repeat the assignments
+    const size = 1024;
+    final value = calloc<ffi.Double>();
+    final buf = calloc<ffi.Uint8>(size);
+    try {
+      final len = evaluateExactFunc(index, x, value, buf.cast<Utf8>(), size);
+      if (len < 0) {
+        lastError = _getLastError();
+        return null;
+      }
+      lastError = null;
+      return (value: value.value, fraction: len > 0 && len < size ? buf.cast<Utf8>().toDartString(length: len) : null);
+    } finally {
+      calloc.free(value);
+      calloc.free(buf);
+    }
+  }
+
+  // Evaluates with complex numbers where i is the imaginary unit, assignments are not kept
+  ({double re, double im})? evaluateComplex(int index, double x){
+    final evaluateComplexFunc = dylib.lookupFunction<EvaluateComplexFunc, EvaluateComplexFuncDart>('evaluate_complex');
+    final re = calloc<ffi.Double>();
+    final im = calloc<ffi.Double>();
+    try {
+      if (evaluateComplexFunc(index, x, re, im) < 0) {
+        lastError = _getLastError();
+        return null;
+      }
+      lastError = null;
+      return (re: re.value, im: im.value);
+    } finally {
+      calloc.free(re);
+      calloc.free(im);
+    }
+  }
+
+  // Value and slope at x, like for a tangent line or a Newton step
+  ({double value, double derivative})? evaluateWithDerivative(int index, double x){
+    final evaluateWithDerivativeFunc = dylib.lookupFunction<EvaluateWithDerivativeFunc, EvaluateWithDerivativeFuncDart>('evaluate_with_derivative');
+    final value = calloc<ffi.Double>();
+    final derivative = calloc<ffi.Double>();
+    try {
+      if (evaluateWithDerivativeFunc(index, x, value, derivative) < 0) {
+        lastError = _getLastError();
+        return null;
+      }
+      lastError = null;
+      return (value: value.value, derivative: derivative.value);
+    } finally {
+      calloc.free(value);
+      calloc.free(derivative);
+    }
+  }
+
+  // Bounds of the values for x between lo and hi, NaN bounds when there is no real value
+  ({double lo, double hi})? evaluateInterval(int index, double lo, double hi){
+    final evaluateIntervalFunc = dylib.lookupFunction<EvaluateIntervalFunc, EvaluateIntervalFuncDart>('evaluate_interval');
+    final outLo = calloc<ffi.Double>();
+    final outHi = calloc<ffi.Double>();
+    try {
+      if (evaluateIntervalFunc(index, lo, hi, outLo, outHi) < 0) {
+        lastError = _getLastError();
+        return null;
+      }
+      lastError = null;
+      return (lo: outLo.value, hi: outHi.value);
+    } finally {
+      calloc.free(outLo);
+      calloc.free(outHi);
+    }
+  }
 
+  // Null with lastError set when the evaluation failed
+  double? _checked(double res){
     final error = _getLastError();
     if(error != null && error.isNotEmpty){
       lastError = error;
@@ -60,6 +203,177 @@ class Parser {
     }
   }
 
+  // Stores the derivative of the expression at index, returns the index of the derivative
+  int? derive(int index, [String variable = 'x']){
+    final deriveFunc = dylib.lookupFunction<DeriveFunc, DeriveFuncDart>('derive');
+    final variablePtr = variable.toNativeUtf8();
+    try {
+      final res = deriveFunc(index, variablePtr);
+      if (res < 0) {
+        lastError = _getLastError();
+        return null;
+      }
+      lastError = null;
+      return res;
+    } finally {
+      malloc.free(variablePtr);
+    }
+  }
+
+  // Normalised text of the expression at index, like 2*x + 1 for 2x+1
+  String? expressionString(int index) => _expressionText(index, 'expression_to_string');
+
+  // LaTeX source of the expression at index
+  String? expressionLatex(int index) => _expressionText(index, 'expression_to_latex');
+
+  String? _expressionText(int index, String symbol){
+    final expressionText = dylib.lookupFunction<ExpressionToStringFunc, ExpressionToStringFuncDart>(symbol);
+    var size = 256;
+    while (true) {
+      final buf = calloc<ffi.Uint8>(size);
+      try {
+        final len = expressionText(index, buf.cast<Utf8>(), size);
+        if (len < 0) return null;
+        // The whole text fitted, otherwise retry with its length
+        if (len < size) return buf.cast<Utf8>().toDartString(length: len);
+        size = len + 1;
+      } finally {
+        calloc.free(buf);
+      }
+    }
+  }
+
+  // Evaluates count samples evenly spaced from start to end in a single call, failed samples are NaN
+  List<double>? evaluateRange(int index, double start, double end, int count){
+    final evaluateRangeFunc = dylib.lookupFunction<EvaluateRangeFunc, EvaluateRangeFuncDart>('evaluate_range');
+    final out = calloc<ffi.Float>(count);
+    try {
+      final res = evaluateRangeFunc(index, start, end, count, out, ffi.nullptr);
+      if (res < 0) {
+        lastError = _getLastError();
+        return null;
+      }
+      lastError = res > 0 ? _getLastError() : null;
+      return out.asTypedList(count).toList();
+    } finally {
+      calloc.free(out);
+    }
+  }
+
+  // Polylines drawing the curve at index inside the window, refined where it bends and cut where it jumps or has no value.
+  // tolerance is the largest distance to the curve as a fraction of yMax - yMin.
+  List<List<(double, double)>>? sampleCurve(int index, double xMin, double xMax, double yMin, double yMax, double tolerance){
+    final sampleCurveFunc = dylib.lookupFunction<SampleCurveFunc, SampleCurveFuncDart>('sample_curve');
+    var size = 4096;
+    while (true) {
+      final out = calloc<ffi.Double>(size);
+      try {
+        final len = sampleCurveFunc(index, xMin, xMax, yMin, yMax, tolerance, out, size);
+        if (len < 0) {
+          lastError = _getLastError();
+          return null;
+        }
+        lastError = _getLastError();
+        // The whole result fitted, otherwise retry with its length
+        if (len <= size) {
+          final polylines = <List<(double, double)>>[[]];
+          for (int i = 0; i + 1 < len; i += 2) {
+            if (out[i].isNaN) {
+              polylines.add([]);
+            } else {
+              polylines.last.add((out[i], out[i + 1]));
+            }
+          }
+          return polylines.where((polyline) => polyline.isNotEmpty).toList();
+        }
+        size = len;
+      } finally {
+        calloc.free(out);
+      }
+    }
+  }
+
+  // Roots of the expression at index for x in [a, b] sorted by x.
+  // multiplicity is an estimate, odd where the curve crosses 0 and even where it touches it.
+  List<({double x, double y, int multiplicity, int iterations, bool converged})>? roots(int index, double a, double b){
+    final rootsFunc = dylib.lookupFunction<RootsFunc, RootsFuncDart>('roots');
+    return _readRoots((out, len) => rootsFunc(index, a, b, out, len));
+  }
+
+  // Points where the curves of the expressions at indexA and indexB meet for x in [xMin, xMax] sorted by x, y is the value of both.
+  // multiplicity is odd where they cross and even where they only touch.
+  List<({double x, double y, int multiplicity, int iterations, bool converged})>? intersections(int indexA, int indexB, double xMin, double xMax){
+    final intersectionsFunc = dylib.lookupFunction<IntersectionsFunc, IntersectionsFuncDart>('intersections');
+    return _readRoots((out, len) => intersectionsFunc(indexA, indexB, xMin, xMax, out, len));
+  }
+
+  // find writes 5 values per root into out and returns their number
+  List<({double x, double y, int multiplicity, int iterations, bool converged})>? _readRoots(int Function(ffi.Pointer<ffi.Double>, int) find){
+    var count = 16;
+    while (true) {
+      final out = calloc<ffi.Double>(5 * count);
+      try {
+        final len = find(out, 5 * count);
+        if (len < 0) {
+          lastError = _getLastError();
+          return null;
+        }
+        lastError = null;
+        // All the roots fitted, otherwise retry with their number
+        if (len <= count) {
+          return [
+            for (int i = 0; i < len; i++)
+              (x: out[5 * i], y: out[5 * i + 1], multiplicity: out[5 * i + 2].toInt(), iterations: out[5 * i + 3].toInt(), converged: out[5 * i + 4] != 0)
+          ];
+        }
+        count = len;
+      } finally {
+        calloc.free(out);
+      }
+    }
+  }
+
+  // Local minima, maxima and inflection points of the expression at index for x in [a, b] sorted by x
+  List<({PointKind kind, double x, double y})>? criticalPoints(int index, double a, double b){
+    final criticalPointsFunc = dylib.lookupFunction<CriticalPointsFunc, CriticalPointsFuncDart>('critical_points');
+    var count = 16;
+    while (true) {
+      final out = calloc<ffi.Double>(3 * count);
+      try {
+        final len = criticalPointsFunc(index, a, b, out, 3 * count);
+        if (len < 0) {
+          lastError = _getLastError();
+          return null;
+        }
+        lastError = null;
+        // All the points fitted, otherwise retry with their number
+        if (len <= count) {
+          return [
+            for (int i = 0; i < len; i++)
+              (kind: PointKind.values[out[3 * i].toInt()], x: out[3 * i + 1], y: out[3 * i + 2])
+          ];
+        }
+        count = len;
+      } finally {
+        calloc.free(out);
+      }
+    }
+  }
+
+  void _getLastErrorSpan(){
+    final getLastErrorSpan = dylib.lookupFunction<GetLastErrorSpanFunc, GetLastErrorSpanFuncDart>('get_last_error_span');
+    final start = calloc<ffi.Int32>();
+    final end = calloc<ffi.Int32>();
+    try {
+      final code = getLastErrorSpan(start, end);
+      lastErrorStart = code > 0 ? start.value : null;
+      lastErrorEnd = code > 0 ? end.value : null;
+    } finally {
+      calloc.free(start);
+      calloc.free(end);
+    }
+  }
+
   String? _getLastError(){
     final getLastError = dylib.lookupFunction<GetLastErrorFunc, GetLastErrorFuncDart>('get_last_error');
     final freeLastError = dylib.lookupFunction<FreeLastErrorFunc, FreeLastErrorFuncDart>('free_last_error');
@@ -0,0 +1,76 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use aizebra::equation::{parse_with_functions, UserFunctions};
+
+// Counts allocations to check the compiled program does not allocate while running
+struct CountingAllocator;
+
+static ALLOCATIONS : AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout : Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr : *mut u8, layout : Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR : CountingAllocator = CountingAllocator;
+
+fn main() {
+    let mut functions = UserFunctions::new();
+    parse_with_functions("f(t) = t^2 - 2t + 1", &mut functions).unwrap();
+
+    let polynomial = (0..25).map(|i| format!("{}.25*x^{}", i + 1, i)).collect::<Vec<_>>().join(" + ");
+    let inputs = [
+        polynomial.as_str(),
+        "sin(x)*cos(x) + sqrt(abs(x))",
+        "if(x > 0, ln(x), exp(x))",
+        "sum(i=1, 20, x^i/i!)",
+        "f(x) + f(2x)",
+    ];
+    let samples : Vec<f32> = (0..1000).map(|i| i as f32 / 100.0 - 5.0).collect();
+
+    for input in inputs {
+        let expression = parse_with_functions(input, &mut functions).unwrap();
+        let program = expression.compile(&functions).unwrap();
+        let mut variables = HashMap::new();
+        let mut registers = program.registers(&variables);
+
+        let runs = 20;
+        let start = Instant::now();
+        for _ in 0..runs {
+            for x in &samples {
+                black_box(expression.evaluate_with(black_box(*x), &mut variables, &functions));
+            }
+        }
+        let tree = start.elapsed() / (runs * samples.len() as u32);
+
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        for _ in 0..runs {
+            for x in &samples {
+                black_box(program.run(black_box(*x), &mut registers).unwrap_or(f32::NAN));
+            }
+        }
+        let compiled = start.elapsed() / (runs * samples.len() as u32);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+        for x in &samples {
+            let expected = expression.evaluate_with(*x, &mut variables, &functions);
+            let value = program.run(*x, &mut registers).unwrap_or(f32::NAN);
+            assert!(value.to_bits() == expected.to_bits() || (value.is_nan() && expected.is_nan()), "{} at {}", input, x);
+        }
+
+        let name : String = input.chars().take(30).collect();
+        println!("{:<30} : tree {:>9.2?}  compiled {:>9.2?}  x{:<5.1} {} allocations", name, tree, compiled, tree.as_nanos() as f64 / compiled.as_nanos().max(1) as f64, allocations);
+    }
+}
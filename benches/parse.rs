@@ -0,0 +1,22 @@
+use std::hint::black_box;
+use std::time::Instant;
+
+use aizebra::equation::parse_expression;
+
+// Same shape as the polynomial fits pasted from spreadsheets
+fn polynomial(terms : usize) -> String {
+    (0..terms).map(|i| format!("{}.25*x^{}", i + 1, i)).collect::<Vec<_>>().join(" + ")
+}
+
+fn main() {
+    for terms in [25, 50, 100, 200] {
+        let input = polynomial(terms);
+        let runs = 200;
+        let start = Instant::now();
+        for _ in 0..runs {
+            black_box(parse_expression(black_box(&input)).unwrap());
+        }
+        let per_parse = start.elapsed() / runs;
+        println!("parse {:>3} terms ({:>4} chars) : {:>10.2?}  {:>6.1} ns/char", terms, input.chars().count(), per_parse, per_parse.as_nanos() as f64 / input.chars().count() as f64);
+    }
+}
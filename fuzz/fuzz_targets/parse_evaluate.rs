@@ -0,0 +1,17 @@
+#![no_main]
+
+use std::collections::HashMap;
+
+use aizebra::equation::parse_expression;
+use libfuzzer_sys::fuzz_target;
+
+// Any UTF-8 input must either fail to parse or evaluate to a value, never panic
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else { return; };
+    if let Ok(expression) = parse_expression(input) {
+        let mut variables = HashMap::new();
+        for x in [0.0, -1.0, 30.0, f32::NAN, f32::INFINITY] {
+            let _ = expression.evaluate(x, &mut variables);
+        }
+    }
+});
@@ -1,91 +1,84 @@
+// The command line only uses part of the shared module
+#[allow(dead_code, unused_imports)]
 #[path = "../../equation/mod.rs"]
 mod equation;
 
 use std::{io::stdin};
 use std::collections::HashMap;
 
-/*
-#[derive(Debug, Clone, Copy)]
-struct Point{
-    fields : [f32; Point::NUM_FIELD]
-}
-
-struct Curve {
-    points : Vec<Point>
-}
-
-impl ops::Mul<Point> for f32{
-    type Output = Point;
-    fn mul(self, p: Point) -> Self::Output {
-            Point{ fields : core::array::from_fn(|i| p.fields[i] * self )}
-    }
-}
-
-impl ops::Add<Point> for Point{
-    type Output = Point;
-    fn add(self, rhs: Point) -> Self::Output {
-        Point{fields : core::array::from_fn(|i| self.fields[i] + rhs.fields[i])}
-    }
-}
-
-impl Point{
-    pub const NUM_FIELD : usize = 14;
-
-    fn interpolate(p1 : &Point, p2 : &Point, t : f32) -> Point{
-        t * (*p2) + (1f32-t)*(*p1)
-    }    
-}
-
-impl Curve {
-    fn sample_from_function(e : equation::Expression, n : i32, start : f32, end : f32) -> Curve{
-        Curve{points : (0..n).map( |j| {Point{fields :
-                core::array::from_fn(|i| {
-                    let x = start + (end - start) * (j as f32) / ((n-1) as f32);
-                    if i == 0 {x} else {e.simple_evaluate(x)}
-                })}
-            }).collect()}
-    }
-
-    fn create(n : i32) -> Curve{
-        Curve{points : (0..n).map( |j| {Point{fields :
-                core::array::from_fn(|i| {
-                    if i == 0 {j as f32} else {j as f32 * 100f32}
-                })}
-            }).collect()}
-    }
-
-    fn print(&self){
-        for p in &self.points{
-            println!("Point debit = {}, pression = {}", p.fields[0], p.fields[1]);
-        }
-    }
-
-    fn interpolated(&self, t : f32) -> Point{
-        match self.points.iter().position(|&p| p.fields[0] > t){
-            None => *self.points.last().expect("Empty curve"),
-            Some(next) if next > 0 => {
-                let prec = next - 1;
-
-                Point::interpolate(&self.points[prec], &self.points[next], (t - self.points[prec].fields[0]) / (self.points[next].fields[0] - self.points[prec].fields[0]) )
-            },
-            Some(_) => *self.points.first().expect("Empty curve")
-        }
-    }
-}*/
-
-
 fn main() {
     let mut s=String::new();
     let mut variables : HashMap<String,f32> = HashMap::new();
+    let mut functions = equation::UserFunctions::new();
     loop{
-        println!("Enter an expression :");
-        let _ = stdin().read_line(& mut s);
+        println!("Enter an expression, :latex followed by one, :tex followed by a LaTeX formula, or :roots or :extrema followed by a b and an expression :");
+        // Stops at the end of the input instead of reading empty lines forever
+        if !matches!(stdin().read_line(& mut s), Ok(n) if n > 0) { break; }
 
         if matches!(s.chars().next_back(), Some('\n')) { s.pop(); }
         if matches!(s.chars().next_back(), Some('\r')) { s.pop(); }
 
-        equation::test_filter(s.clone(), &mut variables);
+        if let Some(input) = s.strip_prefix(":latex") {
+            match equation::parse_with_functions(input, &mut functions) {
+                Ok(expression) => println!("{}", expression.to_latex()),
+                Err(e) => println!("Parsing failed with : {}", e)
+            }
+        } else if let Some(input) = s.strip_prefix(":tex") {
+            match equation::parse_latex_with_functions(input, &functions) {
+                Ok(expression) => {
+                    println!("Understood       : {}", expression);
+                    match expression.try_evaluate_with(30.0, &mut variables, &functions) {
+                        Ok(value) => println!("Evaluated (30.0): {}", value),
+                        Err(e) => println!("Evaluated (30.0): {}", e)
+                    }
+                },
+                Err(e) => println!("Parsing failed with : {}", e)
+            }
+        } else if let Some(input) = s.strip_prefix(":roots") {
+            if let Some((a, b, expression)) = parse_range(":roots", input, &mut functions) {
+                match equation::roots_with(&expression, &to_f64(&variables), &functions, a, b) {
+                    Ok(roots) if roots.is_empty() => println!("No root in [{}, {}]", a, b),
+                    Ok(roots) => for root in roots {
+                        println!("x = {} (multiplicity {}, {} iterations{})", root.x, root.multiplicity, root.iterations, if root.converged { "" } else { ", not converged" });
+                    },
+                    Err(e) => println!("Evaluation failed with : {}", e)
+                }
+            }
+        } else if let Some(input) = s.strip_prefix(":extrema") {
+            if let Some((a, b, expression)) = parse_range(":extrema", input, &mut functions) {
+                match equation::critical_points_with(&expression, &to_f64(&variables), &functions, a, b) {
+                    Ok(points) if points.is_empty() => println!("No extremum nor inflection point in [{}, {}]", a, b),
+                    Ok(points) => for point in points {
+                        println!("{:?} at x = {}, y = {}", point.kind, point.x, point.y);
+                    },
+                    Err(e) => println!("Evaluation failed with : {}", e)
+                }
+            }
+        } else {
+            equation::test_filter(s.clone(), &mut variables, &mut functions);
+        }
         s.clear();
-        println!("");
+        println!();
+    }
+}
+
+// Reads the "a b expression" after a command, what is wrong is printed
+fn parse_range(command : &str, input : &str, functions : &mut equation::UserFunctions) -> Option<(f64, f64, equation::Expression)> {
+    let mut parts = input.trim_start().splitn(3, ' ');
+    let (a, b) = (parts.next().and_then(|a| a.parse::<f64>().ok()), parts.next().and_then(|b| b.parse::<f64>().ok()));
+    let (Some(a), Some(b), Some(input)) = (a, b, parts.next()) else {
+        println!("Expected {} a b expression", command);
+        return None;
+    };
+    match equation::parse_with_functions(input, functions) {
+        Ok(expression) => Some((a, b, expression)),
+        Err(e) => {
+            println!("Parsing failed with : {}", e);
+            None
+        }
     }
 }
+
+fn to_f64(variables : &HashMap<String, f32>) -> HashMap<String, f64> {
+    variables.iter().map(|(name, value)| (name.clone(), *value as f64)).collect()
+}
@@ -0,0 +1,256 @@
+use std::f64::consts::{FRAC_PI_2, LN_10};
+use std::fmt;
+use std::ops::{Add, Mul};
+
+use super::display::format_number;
+use super::number::{self, Number};
+use super::{Multi, MultiFunction, Simple, SimpleFunction};
+
+/// Complex value of an evaluation in double precision, written like `3 - 4i`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Complex {
+    pub re : f64,
+    pub im : f64,
+}
+
+impl Complex {
+    pub const I : Complex = Complex { re : 0.0, im : 1.0 };
+    const NAN : Complex = Complex { re : f64::NAN, im : f64::NAN };
+
+    pub fn new(re : f64, im : f64) -> Complex {
+        Complex { re, im }
+    }
+
+    pub fn real(re : f64) -> Complex {
+        Complex { re, im : 0.0 }
+    }
+
+    pub fn is_real(&self) -> bool {
+        self.im == 0.0
+    }
+
+    pub fn abs(&self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /// Angle in ]-pi, pi]
+    pub fn arg(&self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(&self) -> Complex {
+        Complex::new(self.re, -self.im)
+    }
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+
+    fn sub(self, other : Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+
+    fn div(self, other : Complex) -> Complex {
+        if other.is_real() { return Complex::new(self.re / other.re, self.im / other.re); }
+        let norm = other.re * other.re + other.im * other.im;
+        Complex::new((self.re * other.re + self.im * other.im) / norm, (self.im * other.re - self.re * other.im) / norm)
+    }
+
+    fn scale(self, k : f64) -> Complex {
+        Complex::new(self.re * k, self.im * k)
+    }
+
+    pub fn exp(self) -> Complex {
+        let (sin, cos) = self.im.sin_cos();
+        Complex::new(cos, sin).scale(self.re.exp())
+    }
+
+    /// Principal logarithm, its imaginary part is the argument
+    pub fn ln(self) -> Complex {
+        Complex::new(self.abs().ln(), self.arg())
+    }
+
+    /// Principal square root, its real part is not negative
+    pub fn sqrt(self) -> Complex {
+        let r = self.abs();
+        let re = ((r + self.re) / 2.0).sqrt();
+        let im = ((r - self.re) / 2.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    pub fn powc(self, exponent : Complex) -> Complex {
+        // Integer powers by squaring so i^2 is exactly -1
+        if exponent.is_real() && exponent.re.fract() == 0.0 && exponent.re.abs() <= 1024.0 {
+            let mut n = exponent.re.abs() as u32;
+            let (mut base, mut res) = (self, Complex::real(1.0));
+            while n > 0 {
+                if n & 1 == 1 { res = res * base; }
+                base = base * base;
+                n >>= 1;
+            }
+            return if exponent.re < 0.0 { Complex::real(1.0).div(res) } else { res };
+        }
+        if self == Complex::real(0.0) {
+            return if exponent.re > 0.0 { Complex::real(0.0) } else { Complex::NAN };
+        }
+        (exponent * self.ln()).exp()
+    }
+
+    fn sin(self) -> Complex {
+        Complex::new(self.re.sin() * self.im.cosh(), self.re.cos() * self.im.sinh())
+    }
+
+    fn cos(self) -> Complex {
+        Complex::new(self.re.cos() * self.im.cosh(), -self.re.sin() * self.im.sinh())
+    }
+
+    fn sinh(self) -> Complex {
+        Complex::new(self.re.sinh() * self.im.cos(), self.re.cosh() * self.im.sin())
+    }
+
+    fn cosh(self) -> Complex {
+        Complex::new(self.re.cosh() * self.im.cos(), self.re.sinh() * self.im.sin())
+    }
+
+    // -i ln(iz + sqrt(1 - z²))
+    fn asin(self) -> Complex {
+        let one = Complex::real(1.0);
+        (Complex::I * self + one.sub(self * self).sqrt()).ln() * Complex::I.neg()
+    }
+
+    // i/2 (ln(1 - iz) - ln(1 + iz))
+    fn atan(self) -> Complex {
+        let (one, iz) = (Complex::real(1.0), Complex::I * self);
+        (one.sub(iz).ln().sub((one + iz).ln())) * Complex::new(0.0, 0.5)
+    }
+
+    // Complex version of the functions built in
+    fn function(name : Simple, z : Complex) -> Option<Complex> {
+        let one = Complex::real(1.0);
+        Some(match name {
+            Simple::Sin => z.sin(),
+            Simple::Cos => z.cos(),
+            Simple::Tan => z.sin().div(z.cos()),
+            Simple::Cot => z.cos().div(z.sin()),
+            Simple::Sec => one.div(z.cos()),
+            Simple::Csc => one.div(z.sin()),
+            Simple::Abs => Complex::real(z.abs()),
+            Simple::Ceil => Complex::new(z.re.ceil(), z.im.ceil()),
+            Simple::Floor => Complex::new(z.re.floor(), z.im.floor()),
+            Simple::Round => Complex::new(z.re.round(), z.im.round()),
+            Simple::Exp => z.exp(),
+            Simple::Ln => z.ln(),
+            Simple::Log => z.ln().scale(1.0 / LN_10),
+            Simple::Sqrt => z.sqrt(),
+            Simple::Asin => z.asin(),
+            Simple::Acos => Complex::real(FRAC_PI_2).sub(z.asin()),
+            Simple::Atan => z.atan(),
+            Simple::Sinh => z.sinh(),
+            Simple::Cosh => z.cosh(),
+            Simple::Tanh => z.sinh().div(z.cosh()),
+            Simple::Asinh => (z + (z * z + one).sqrt()).ln(),
+            Simple::Acosh => (z + (z + one).sqrt() * z.sub(one).sqrt()).ln(),
+            Simple::Atanh => (one + z).ln().sub(one.sub(z).ln()).scale(0.5),
+            Simple::Re => Complex::real(z.re),
+            Simple::Im => Complex::real(z.im),
+            Simple::Arg => Complex::real(z.arg()),
+            Simple::Conj => z.conj(),
+            // The operators are taken by Number::simple, the others only have a real version
+            Simple::Plus | Simple::Minus | Simple::Square | Simple::Factorial | Simple::External => return None
+        })
+    }
+}
+
+impl fmt::Display for Complex {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        // Names like pi and inf need a * before the i
+        let imaginary = |im : f64| match (im, format_number(im)) {
+            (1.0, _) => "i".to_string(),
+            (-1.0, _) => "-i".to_string(),
+            (_, text) if text.ends_with(|c : char| c.is_ascii_digit()) => format!("{}i", text),
+            (_, text) => format!("{}*i", text)
+        };
+        if self.re.is_nan() || self.im.is_nan() {
+            f.write_str("NaN")
+        } else if self.is_real() {
+            f.write_str(&format_number(self.re))
+        } else if self.re == 0.0 {
+            f.write_str(&imaginary(self.im))
+        } else {
+            let sign = if self.im < 0.0 { "-" } else { "+" };
+            write!(f, "{} {} {}", format_number(self.re), sign, imaginary(self.im.abs()))
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other : Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other : Complex) -> Complex {
+        if self.is_real() && other.is_real() { return Complex::real(self.re * other.re); }
+        Complex::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+    }
+}
+
+fn truth(b : bool) -> Complex {
+    Complex::real(if b { 1.0 } else { 0.0 })
+}
+
+// Real values go through f64 as long as the result stays real, so only what is NaN in f64 changes
+impl Number for Complex {
+    fn from_f64(c : f64) -> Complex {
+        Complex::real(c)
+    }
+
+    fn to_f64(&self) -> f64 {
+        if self.is_real() { self.re } else { f64::NAN }
+    }
+
+    fn is_true(&self) -> Option<bool> {
+        Some(self.is_real() && self.re > 0.0)
+    }
+
+    fn imaginary_unit() -> Complex {
+        Complex::I
+    }
+
+    fn simple(name : Simple, f : SimpleFunction, z : Complex) -> Complex {
+        if z.is_real() {
+            let real = f64::simple(name, f, z.re);
+            if !real.is_nan() || z.re.is_nan() { return Complex::real(real); }
+        }
+        match name {
+            Simple::Plus => z,
+            Simple::Minus => z.neg(),
+            Simple::Square => z * z,
+            Simple::Factorial if z.is_real() => Complex::real(number::factorial(z.re)),
+            _ => Complex::function(name, z).unwrap_or(Complex::NAN)
+        }
+    }
+
+    fn multi(name : Multi, f : MultiFunction, x : &[Complex]) -> Complex {
+        if x.iter().all(Complex::is_real) {
+            let real = f64::multi(name, f, &x.iter().map(|z| z.re).collect::<Vec<_>>());
+            if !real.is_nan() || x.iter().any(|z| z.re.is_nan()) { return Complex::real(real); }
+        }
+        match (name, x) {
+            (Multi::Add, [a, b]) => *a + *b,
+            (Multi::Sub, [a, b]) => a.sub(*b),
+            (Multi::Mul, [a, b]) => *a * *b,
+            (Multi::Div, [a, b]) => a.div(*b),
+            (Multi::Pow | Multi::PowCall, [a, b]) => a.powc(*b),
+            (Multi::Equal, [a, b]) => truth(a == b),
+            (Multi::NotEqual, [a, b]) => truth(a != b),
+            // Complex values have no order
+            (Multi::LessEqual | Multi::Less | Multi::GreaterEqual | Multi::Greater | Multi::Max | Multi::Min, _) => Complex::NAN,
+            // Wrong number of arguments
+            (Multi::Add | Multi::Sub | Multi::Mul | Multi::Div | Multi::Pow | Multi::PowCall | Multi::Equal | Multi::NotEqual, _) => Complex::NAN
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use super::Expression;
+
+/// Points of a curve joined by segments
+pub type Polyline = Vec<(f64, f64)>;
+
+/// Evenly spaced segments sampled before refining, features narrower than one can be missed
+const INITIAL_SEGMENTS : usize = 128;
+/// Times a segment may be halved, the shortest is 2^-19 of the width
+const MAX_REFINEMENT : u32 = 12;
+/// Most evaluations of a curve, refinement stops once they are spent
+const MAX_SAMPLES : usize = 1 << 15;
+
+/// Samples `y = expression` for x in [xmin, xmax] in double precision, see `sample_function`
+pub fn sample_curve(e : &Expression, xmin : f64, xmax : f64, ymin : f64, ymax : f64, tolerance : f64) -> Vec<Polyline> {
+    sample_function(|x| e.evaluate_f64(x, &mut HashMap::new()), xmin, xmax, ymin, ymax, tolerance)
+}
+
+/// Polylines drawing `y = f(x)` for x in [xmin, xmax], cut to the window where y is in [ymin, ymax].
+/// Segments are halved until the curve is within `tolerance` of them, a fraction of ymax - ymin.
+/// The curve is split where it has no finite value and where it still jumps once segments cannot be halved anymore, like tan(x) at pi/2.
+pub fn sample_function(f : impl FnMut(f64) -> f64, xmin : f64, xmax : f64, ymin : f64, ymax : f64, tolerance : f64) -> Vec<Polyline> {
+    if !(xmin < xmax && ymin < ymax && tolerance > 0.0 && [xmin, xmax, ymin, ymax].iter().all(|c| c.is_finite())) {
+        return vec![];
+    }
+    let mut sampler = Sampler { f, ymin, ymax, tolerance : tolerance * (ymax - ymin), samples : 0, points : vec![] };
+    let step = (xmax - xmin) / INITIAL_SEGMENTS as f64;
+    let mut a = sampler.sample(xmin);
+    sampler.push(a);
+    for k in 1..=INITIAL_SEGMENTS {
+        // The last one is exactly xmax
+        let b = sampler.sample(if k == INITIAL_SEGMENTS { xmax } else { xmin + step * k as f64 });
+        sampler.refine(a, b, 0);
+        a = b;
+    }
+
+    let mut polylines = vec![];
+    for part in sampler.points.split(Option::is_none) {
+        let part : Polyline = part.iter().flatten().copied().collect();
+        clip(&part, ymin, ymax, &mut polylines);
+    }
+    polylines
+}
+
+struct Sampler<F> {
+    f : F,
+    ymin : f64,
+    ymax : f64,
+    // Distance in y units
+    tolerance : f64,
+    samples : usize,
+    // None cuts the curve
+    points : Vec<Option<(f64, f64)>>,
+}
+
+impl<F : FnMut(f64) -> f64> Sampler<F> {
+    fn sample(&mut self, x : f64) -> (f64, f64) {
+        self.samples += 1;
+        (x, (self.f)(x))
+    }
+
+    fn push(&mut self, p : (f64, f64)) {
+        self.points.push(p.1.is_finite().then_some(p));
+    }
+
+    // Adds the points after a up to b
+    fn refine(&mut self, a : (f64, f64), b : (f64, f64), depth : u32) {
+        let m = self.sample((a.0 + b.0) / 2.0);
+        let error = self.error(a, m, b);
+        if error > self.tolerance && depth < MAX_REFINEMENT && self.samples < MAX_SAMPLES {
+            self.refine(a, m, depth + 1);
+            self.refine(m, b, depth + 1);
+            return;
+        }
+        // Still off the segment when it is as short as it gets, the curve jumps on the steeper half
+        let jumps = error > self.tolerance && depth == MAX_REFINEMENT && a.1.is_finite() && m.1.is_finite() && b.1.is_finite();
+        if jumps && (m.1 - a.1).abs() > (b.1 - m.1).abs() { self.points.push(None); }
+        self.push(m);
+        if jumps && (m.1 - a.1).abs() <= (b.1 - m.1).abs() { self.points.push(None); }
+        self.push(b);
+    }
+
+    // Distance of the middle to the segment from a to b, infinite where the curve starts or stops having values
+    fn error(&self, a : (f64, f64), m : (f64, f64), b : (f64, f64)) -> f64 {
+        let defined = [a, m, b].map(|p| p.1.is_finite());
+        if defined != [true; 3] {
+            return if defined == [false; 3] { 0.0 } else { f64::INFINITY };
+        }
+        // Nothing is drawn from a part entirely above or below the window
+        if [a, m, b].iter().all(|p| p.1 > self.ymax) || [a, m, b].iter().all(|p| p.1 < self.ymin) {
+            return 0.0;
+        }
+        (m.1 - (a.1 + b.1) / 2.0).abs()
+    }
+}
+
+// Adds the parts of the polyline where y is in [ymin, ymax], segments crossing the border end on it
+fn clip(polyline : &[(f64, f64)], ymin : f64, ymax : f64, polylines : &mut Vec<Polyline>) {
+    let mut current = vec![];
+    for segment in polyline.windows(2) {
+        let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+        let at = |t : f64| if t == 0.0 { (x0, y0) } else if t == 1.0 { (x1, y1) } else { (x0 + t * (x1 - x0), y0 + t * (y1 - y0)) };
+        let (t0, t1) = if y0 == y1 {
+            if ymin <= y0 && y0 <= ymax { (0.0, 1.0) } else { (1.0, 0.0) }
+        } else {
+            let (ta, tb) = ((ymin - y0) / (y1 - y0), (ymax - y0) / (y1 - y0));
+            (ta.min(tb).max(0.0), ta.max(tb).min(1.0))
+        };
+        if t0 > t1 {
+            flush(&mut current, polylines);
+            continue;
+        }
+        let (start, end) = (at(t0), at(t1));
+        if current.last() != Some(&start) {
+            flush(&mut current, polylines);
+            current.push(start);
+        }
+        current.push(end);
+    }
+    flush(&mut current, polylines);
+}
+
+fn flush(current : &mut Polyline, polylines : &mut Vec<Polyline>) {
+    if current.len() >= 2 {
+        polylines.push(std::mem::take(current));
+    }
+    current.clear();
+}
@@ -0,0 +1,310 @@
+use super::{Expression, Function, Multi, Numeric, Operator, Simple, Span, UserFunctions, MAX_CALL_DEPTH, MAX_DEPTH};
+
+/// Most nodes made while deriving, nested derivatives grow exponentially
+const MAX_DERIVATIVE_SIZE : usize = 1 << 20;
+
+/// Derivative of `e` with respect to `var`, "x" being the input. Other variables are constants.
+/// Calls of user functions need their definition, see `derive_with`.
+pub fn derive(e : &Expression, var : &str) -> Expression {
+    derive_with(e, var, &UserFunctions::new())
+}
+
+/// Same as `derive`, calls of the user functions are derived through their body.
+/// Malformed nodes are kept as is so evaluating the derivative reports the same error, a derivative too large is NaN.
+pub fn derive_with(e : &Expression, var : &str, functions : &UserFunctions) -> Expression {
+    try_derive(e, var, functions).unwrap_or_else(|| constant(f64::NAN, e.span))
+}
+
+// None when the derivative is too large or too deep to be evaluated
+pub(super) fn try_derive(e : &Expression, var : &str, functions : &UserFunctions) -> Option<Expression> {
+    let mut derivative = Derivative { var, functions, inlining : vec![], size : 0 };
+    let res = derivative.derive(e);
+    (derivative.size <= MAX_DERIVATIVE_SIZE && depth(&res) <= 4 * MAX_DEPTH).then_some(res)
+}
+
+struct Derivative<'a> {
+    var : &'a str,
+    functions : &'a UserFunctions,
+    // User functions being derived, a function calling itself cannot be
+    inlining : Vec<&'a str>,
+    // Nodes made so far, counted again at every level so it grows faster than the result
+    size : usize,
+}
+
+impl<'a> Derivative<'a> {
+    fn derive(&mut self, e : &Expression) -> Expression {
+        if self.size > MAX_DERIVATIVE_SIZE { return constant(f64::NAN, e.span); }
+        let res = self.derive_node(e);
+        self.size += size(&res);
+        res
+    }
+
+    fn derive_node(&mut self, e : &Expression) -> Expression {
+        let span = e.span;
+        match (&e.function, &e.params[..]) {
+            (Function::Constant(_), _) => constant(0.0, span),
+            (Function::InputX | Function::Variable(_), _) => constant(if e.variable_name() == Some(self.var) { 1.0 } else { 0.0 }, span),
+            (Function::SimpleFunction(name, _), [u]) => {
+                let du = self.derive(u);
+                simple(*name, u, du, span)
+            },
+            (Function::MultiFunction(name @ (Multi::Max | Multi::Min), _), [_, ..]) => self.extremum(*name, &e.params, span),
+            (Function::MultiFunction(name, _), [u, v]) => {
+                let (du, dv) = (self.derive(u), self.derive(v));
+                match name {
+                    Multi::Add => add(du, dv, span),
+                    Multi::Sub => sub(du, dv, span),
+                    Multi::Mul => add(mul(du, v.clone(), span), mul(u.clone(), dv, span), span),
+                    Multi::Div => div(sub(mul(du, v.clone(), span), mul(u.clone(), dv, span), span), square(v.clone(), span), span),
+                    Multi::Pow | Multi::PowCall => self.power(u, v, du, dv, span),
+                    // Comparisons are piecewise constant
+                    Multi::Equal | Multi::NotEqual | Multi::LessEqual | Multi::Less | Multi::GreaterEqual | Multi::Greater => constant(0.0, span),
+                    // Two arguments of max and min are taken above
+                    Multi::Max | Multi::Min => constant(f64::NAN, span)
+                }
+            },
+            (Function::If, [condition, if_true, if_false]) => {
+                Expression::node(Function::If, vec![condition.clone(), self.derive(if_true), self.derive(if_false)], span)
+            },
+            (Function::Assign, [_, value]) => self.derive(value),
+            (Function::Iterator(o), [start, end, body]) => {
+                let (Function::Assign, [iterator, first]) = (&start.function, &start.params[..]) else { return e.clone(); };
+                let Function::Variable(it) = &iterator.function else { return e.clone(); };
+                // The iterator hides the variable in the body
+                if it == self.var { return constant(0.0, span); }
+
+                let body_derivative = self.derive(body);
+                match o {
+                    Operator::Add => Expression::node(Function::Iterator(Operator::Add), vec![start.clone(), end.clone(), body_derivative], span),
+                    // Product rule, the k-th term of the sum derives the k-th factor
+                    _ => {
+                        let k = fresh_name(it, e);
+                        let is_k = Expression::operator(Operator::TestEQU, vec![iterator.clone(), variable(&k, span)], span);
+                        let factor = Expression::node(Function::If, vec![is_k, body_derivative, body.clone()], span);
+                        let product = Expression::node(Function::Iterator(Operator::Mul), vec![start.clone(), end.clone(), factor], span);
+                        let k_start = Expression::node(Function::Assign, vec![variable(&k, span), first.clone()], start.span);
+                        Expression::node(Function::Iterator(Operator::Add), vec![k_start, end.clone(), product], span)
+                    }
+                }
+            },
+            // Leibniz rule, the bounds move the ends of the integral
+            (Function::Numeric(Numeric::Integral), params) => {
+                let Some(([body], bound, [a, b])) = Numeric::Integral.parts(params) else { return e.clone(); };
+                let inside = if bound == self.var { constant(0.0, span) } else { self.derive(body) };
+                let inside = if inside.value() == Some(0.0) { inside } else {
+                    let mut params = params.to_vec();
+                    params[0] = inside;
+                    Expression::node(Function::Numeric(Numeric::Integral), params, span)
+                };
+                let (da, db) = (self.derive(a), self.derive(b));
+                let at_b = mul(substitute(body, &[(bound, b)], span), db, span);
+                let at_a = mul(substitute(body, &[(bound, a)], span), da, span);
+                sub(add(inside, at_b, span), at_a, span)
+            },
+            // Roots and crossings are piecewise constant in the bounds of the search, a derivative or a limit moves with all its arguments.
+            // How they move is not known.
+            (Function::Numeric(method), params) => {
+                let Some((bodies, bound, others)) = method.parts(params) else { return e.clone(); };
+                let inside = bound != self.var && bodies.iter().any(|b| self.depends_on(b));
+                let outside = method.names_variable() && others.iter().any(|p| self.depends_on(p));
+                constant(if inside || outside { f64::NAN } else { 0.0 }, span)
+            },
+            (Function::Call(name), args) => {
+                let Some((name, function)) = self.functions.get_key_value(name) else { return e.clone(); };
+                if function.params.len() != args.len() { return e.clone(); }
+                if self.inlining.len() >= MAX_CALL_DEPTH || self.inlining.contains(&name.as_str()) { return constant(f64::NAN, span); }
+
+                let bindings : Vec<(&str, &Expression)> = function.params.iter().map(|p| p.as_str()).zip(args).collect();
+                let body = substitute(&function.body, &bindings, span);
+                self.inlining.push(name);
+                let res = self.derive(&body);
+                self.inlining.pop();
+                res
+            },
+            _ => e.clone()
+        }
+    }
+
+    fn depends_on(&self, e : &Expression) -> bool {
+        match &e.function {
+            Function::InputX | Function::Variable(_) => e.variable_name() == Some(self.var),
+            // The body of a user function can read any variable
+            Function::Call(_) => true,
+            _ => e.params.iter().any(|p| self.depends_on(p))
+        }
+    }
+
+    fn power(&self, u : &Expression, v : &Expression, du : Expression, dv : Expression, span : Span) -> Expression {
+        let u_pow_v = || Expression::operator(Operator::Pow, vec![u.clone(), v.clone()], span);
+        if !self.depends_on(v) {
+            let u_pow = Expression::operator(Operator::Pow, vec![u.clone(), sub(v.clone(), constant(1.0, span), span)], span);
+            mul(mul(v.clone(), u_pow, span), du, span)
+        } else if !self.depends_on(u) {
+            mul(mul(u_pow_v(), call("ln", u, span), span), dv, span)
+        } else {
+            let inner = add(mul(dv, call("ln", u, span), span), div(mul(v.clone(), du, span), u.clone(), span), span);
+            mul(u_pow_v(), inner, span)
+        }
+    }
+
+    // max(a, b, ...) is a when a >= max(b, ...)
+    fn extremum(&mut self, name : Multi, params : &[Expression], span : Span) -> Expression {
+        let [first, rest @ ..] = params else { return constant(f64::NAN, span); };
+        if rest.is_empty() { return self.derive(first); }
+        let rest_expression = Expression::builtin(name.name(), rest.to_vec(), span);
+        let comparison = if name == Multi::Max { Operator::TestGEQ } else { Operator::TestLEQ };
+        let condition = Expression::operator(comparison, vec![first.clone(), rest_expression], span);
+        let first_derivative = self.derive(first);
+        let rest_derivative = self.extremum(name, rest, span);
+        Expression::node(Function::If, vec![condition, first_derivative, rest_derivative], span)
+    }
+}
+
+// Chain rule for the functions of one argument
+fn simple(name : Simple, u : &Expression, du : Expression, span : Span) -> Expression {
+    let one = || constant(1.0, span);
+    let u_squared = || square(u.clone(), span);
+    match name {
+        Simple::Plus => du,
+        Simple::Minus => neg(du, span),
+        Simple::Square => mul(mul(constant(2.0, span), u.clone(), span), du, span),
+        Simple::Sin => mul(call("cos", u, span), du, span),
+        Simple::Cos => neg(mul(call("sin", u, span), du, span), span),
+        Simple::Tan => div(du, square(call("cos", u, span), span), span),
+        Simple::Cot => neg(div(du, square(call("sin", u, span), span), span), span),
+        Simple::Sec => mul(mul(call("sec", u, span), call("tan", u, span), span), du, span),
+        Simple::Csc => neg(mul(mul(call("csc", u, span), call("cot", u, span), span), du, span), span),
+        Simple::Abs => mul(div(u.clone(), call("abs", u, span), span), du, span),
+        Simple::Exp => mul(call("exp", u, span), du, span),
+        Simple::Ln => div(du, u.clone(), span),
+        Simple::Log => div(du, mul(u.clone(), call("ln", &constant(10.0, span), span), span), span),
+        Simple::Sqrt => div(du, mul(constant(2.0, span), call("sqrt", u, span), span), span),
+        Simple::Asin => div(du, call("sqrt", &sub(one(), u_squared(), span), span), span),
+        Simple::Acos => neg(div(du, call("sqrt", &sub(one(), u_squared(), span), span), span), span),
+        Simple::Atan => div(du, add(one(), u_squared(), span), span),
+        Simple::Sinh => mul(call("cosh", u, span), du, span),
+        Simple::Cosh => mul(call("sinh", u, span), du, span),
+        Simple::Tanh => div(du, square(call("cosh", u, span), span), span),
+        Simple::Asinh => div(du, call("sqrt", &add(u_squared(), one(), span), span), span),
+        Simple::Acosh => div(du, call("sqrt", &sub(u_squared(), one(), span), span), span),
+        Simple::Atanh => div(du, sub(one(), u_squared(), span), span),
+        Simple::Re | Simple::Conj => du,
+        // Piecewise constant, im and arg of a real number
+        Simple::Factorial | Simple::Ceil | Simple::Floor | Simple::Round | Simple::Im | Simple::Arg => constant(0.0, span),
+        // Functions given from outside have no known derivative
+        Simple::External => constant(f64::NAN, span)
+    }
+}
+
+// Replaces the parameters of a user function by the arguments of the call, nodes of the body take the span of the call
+fn substitute(e : &Expression, bindings : &[(&str, &Expression)], span : Span) -> Expression {
+    let lookup = |name : &str| bindings.iter().find(|(param, _)| *param == name).map(|(_, arg)| (*arg).clone());
+    match (&e.function, &e.params[..]) {
+        (Function::InputX, _) => lookup("x").unwrap_or_else(|| Expression::node(Function::InputX, vec![], span)),
+        (Function::Variable(name), []) => lookup(name).unwrap_or_else(|| variable(name, span)),
+        // The assigned variable stays a variable
+        (Function::Assign, [target, value]) => Expression::node(Function::Assign, vec![target.clone(), substitute(value, bindings, span)], span),
+        // A parameter named like the iterator is hidden in the body
+        (Function::Iterator(o), [start, end, body]) => {
+            let it = match (&start.function, &start.params[..]) {
+                (Function::Assign, [iterator, _]) => iterator.variable_name(),
+                _ => None
+            };
+            let inner : Vec<(&str, &Expression)> = bindings.iter().filter(|(param, _)| Some(*param) != it).copied().collect();
+            Expression::node(Function::Iterator(*o), vec![substitute(start, bindings, span), substitute(end, bindings, span), substitute(body, &inner, span)], span)
+        },
+        // The bound variable is hidden in the leading arguments
+        (Function::Numeric(method), params) => {
+            let Some((bodies, bound, _)) = method.parts(params) else { return e.clone(); };
+            let inner : Vec<(&str, &Expression)> = bindings.iter().filter(|(param, _)| *param != bound).copied().collect();
+            let params = params.iter().enumerate().map(|(i, p)| match (&p.function, &p.params[..]) {
+                // Both sides of an equation to solve
+                (Function::Assign, [lhs, rhs]) if i < bodies.len() && *method == Numeric::Solve => {
+                    Expression::node(Function::Assign, vec![substitute(lhs, &inner, span), substitute(rhs, &inner, span)], span)
+                },
+                _ if i < bodies.len() => substitute(p, &inner, span),
+                _ if i == bodies.len() && method.names_variable() => p.clone(),
+                _ => substitute(p, bindings, span)
+            }).collect();
+            Expression::node(e.function.clone(), params, span)
+        },
+        _ => Expression::node(e.function.clone(), e.params.iter().map(|p| substitute(p, bindings, span)).collect(), span)
+    }
+}
+
+// Name based on `base` that no variable of `e` uses
+fn fresh_name(base : &str, e : &Expression) -> String {
+    fn uses(e : &Expression, name : &str) -> bool {
+        e.variable_name() == Some(name) || e.params.iter().any(|p| uses(p, name))
+    }
+    (1..).map(|i| format!("{}_{}", base, i)).find(|name| !uses(e, name)).unwrap_or_default()
+}
+
+fn size(e : &Expression) -> usize {
+    1 + e.params.iter().map(size).sum::<usize>()
+}
+
+fn depth(e : &Expression) -> usize {
+    1 + e.params.iter().map(depth).max().unwrap_or(0)
+}
+
+fn constant(c : f64, span : Span) -> Expression {
+    Expression::constant(c, span)
+}
+
+fn variable(name : &str, span : Span) -> Expression {
+    Expression::node(Function::Variable(name.to_string()), vec![], span)
+}
+
+fn call(name : &str, u : &Expression, span : Span) -> Expression {
+    Expression::builtin(name, vec![u.clone()], span)
+}
+
+// The constructors below fold what a derivative commonly produces, like 0 * u or u * 1
+
+fn add(a : Expression, b : Expression, span : Span) -> Expression {
+    match (a.value(), b.value()) {
+        (Some(a), Some(b)) => constant(a + b, span),
+        (Some(0.0), _) => b,
+        (_, Some(0.0)) => a,
+        _ => Expression::operator(Operator::Add, vec![a, b], span)
+    }
+}
+
+fn sub(a : Expression, b : Expression, span : Span) -> Expression {
+    match (a.value(), b.value()) {
+        (Some(a), Some(b)) => constant(a - b, span),
+        (Some(0.0), _) => neg(b, span),
+        (_, Some(0.0)) => a,
+        _ => Expression::operator(Operator::Sub, vec![a, b], span)
+    }
+}
+
+fn mul(a : Expression, b : Expression, span : Span) -> Expression {
+    match (a.value(), b.value()) {
+        (Some(a), Some(b)) => constant(a * b, span),
+        (Some(0.0), _) | (_, Some(0.0)) => constant(0.0, span),
+        (Some(1.0), _) => b,
+        (_, Some(1.0)) => a,
+        _ => Expression::operator(Operator::Mul, vec![a, b], span)
+    }
+}
+
+fn div(a : Expression, b : Expression, span : Span) -> Expression {
+    match (a.value(), b.value()) {
+        (Some(0.0), _) => constant(0.0, span),
+        (_, Some(1.0)) => a,
+        _ => Expression::operator(Operator::Div, vec![a, b], span)
+    }
+}
+
+fn neg(a : Expression, span : Span) -> Expression {
+    match a.value() {
+        Some(a) => constant(-a, span),
+        None => Expression::operator(Operator::USub, vec![a], span)
+    }
+}
+
+fn square(a : Expression, span : Span) -> Expression {
+    Expression::operator(Operator::Square, vec![a], span)
+}
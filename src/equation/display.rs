@@ -0,0 +1,109 @@
+use std::f64;
+use std::fmt;
+
+use super::{Expression, Function, Multi, Operator, Simple};
+
+// Prints the infix form the parser reads back to the same tree, with only the parenthesis the precedences need
+impl fmt::Display for Expression {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match (self.as_operator(), &self.params[..]) {
+            (Some(o @ (Operator::UAdd | Operator::USub)), [a]) => {
+                f.write_str(o.to_string())?;
+                write_operand(f, a, a.precedence() < o.precedence())
+            },
+            (Some(o @ (Operator::Factorial | Operator::Square)), [a]) => {
+                write_operand(f, a, a.precedence() < o.precedence())?;
+                f.write_str(o.to_string())
+            },
+            (Some(o), [a, b]) => {
+                // Operators are left associative, a call assigned to would read as a definition
+                let parens = a.precedence() < o.precedence() || (o == Operator::Assign && matches!(a.function, Function::Call(_)));
+                write_operand(f, a, parens)?;
+                let separator = if matches!(o, Operator::Mul | Operator::Div | Operator::Pow) { "" } else { " " };
+                write!(f, "{}{}{}", separator, o.to_string(), separator)?;
+                // A prefix operator on the right only needs them to keep an operator binding tighter from taking its operand
+                let parens = if b.is_prefix() { b.precedence() < o.precedence() } else { b.precedence() <= o.precedence() };
+                write_operand(f, b, parens)
+            },
+            _ => match &self.function {
+                Function::Constant(c) => f.write_str(&format_number(*c)),
+                Function::InputX => f.write_str("x"),
+                Function::Variable(name) => f.write_str(name),
+                // Made by create_from_function, evaluated at x
+                Function::SimpleFunction(name, _) if self.params.is_empty() => write!(f, "{}(x)", name.name()),
+                Function::SimpleFunction(name, _) => write_call(f, name.name(), &self.params),
+                Function::MultiFunction(name, _) => write_call(f, name.name(), &self.params),
+                Function::Call(name) => write_call(f, name, &self.params),
+                Function::Iterator(Operator::Add) => write_call(f, "sum", &self.params),
+                Function::Iterator(_) => write_call(f, "prod", &self.params),
+                Function::If => write_call(f, "if", &self.params),
+                Function::Numeric(method) => write_call(f, method.name(), &self.params),
+                Function::Assign => write_call(f, "=", &self.params),
+            }
+        }
+    }
+}
+
+fn write_operand(f : &mut fmt::Formatter, e : &Expression, parens : bool) -> fmt::Result {
+    if parens { write!(f, "({})", e) } else { write!(f, "{}", e) }
+}
+
+fn write_call(f : &mut fmt::Formatter, name : &str, params : &[Expression]) -> fmt::Result {
+    write!(f, "{}(", name)?;
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 { f.write_str(", ")?; }
+        write!(f, "{}", param)?;
+    }
+    f.write_str(")")
+}
+
+// Shortest text parsed back to the same value, large and small values use an exponent
+pub(super) fn format_number(c : f64) -> String {
+    match c {
+        _ if c == f64::consts::PI => "pi".to_string(),
+        _ if c == f64::consts::E => "e".to_string(),
+        _ if c.is_sign_negative() && !c.is_nan() => format!("-{}", format_number(-c)),
+        _ if c.is_nan() => "NaN".to_string(),
+        _ if c.is_infinite() => "inf".to_string(),
+        _ if c != 0.0 && !(1e-4..1e16).contains(&c) => format!("{:e}", c),
+        _ => format!("{}", c)
+    }
+}
+
+impl Expression {
+    // Operator this node is printed with
+    pub(super) fn as_operator(&self) -> Option<Operator> {
+        Some(match (&self.function, self.params.len()) {
+            (Function::SimpleFunction(Simple::Plus, _), 1) => Operator::UAdd,
+            (Function::SimpleFunction(Simple::Minus, _), 1) => Operator::USub,
+            (Function::SimpleFunction(Simple::Factorial, _), 1) => Operator::Factorial,
+            (Function::SimpleFunction(Simple::Square, _), 1) => Operator::Square,
+            (Function::MultiFunction(Multi::Add, _), 2) => Operator::Add,
+            (Function::MultiFunction(Multi::Sub, _), 2) => Operator::Sub,
+            (Function::MultiFunction(Multi::Mul, _), 2) => Operator::Mul,
+            (Function::MultiFunction(Multi::Div, _), 2) => Operator::Div,
+            (Function::MultiFunction(Multi::Pow, _), 2) => Operator::Pow,
+            (Function::MultiFunction(Multi::Equal, _), 2) => Operator::TestEQU,
+            (Function::MultiFunction(Multi::NotEqual, _), 2) => Operator::TestNEQ,
+            (Function::MultiFunction(Multi::LessEqual, _), 2) => Operator::TestLEQ,
+            (Function::MultiFunction(Multi::Less, _), 2) => Operator::TestLSS,
+            (Function::MultiFunction(Multi::GreaterEqual, _), 2) => Operator::TestGEQ,
+            (Function::MultiFunction(Multi::Greater, _), 2) => Operator::TestGTR,
+            (Function::Assign, 2) => Operator::Assign,
+            _ => return None
+        })
+    }
+
+    // How tightly the printed node holds together, a negative constant is printed like a negation
+    pub(super) fn precedence(&self) -> u8 {
+        match (self.as_operator(), self.value()) {
+            (Some(o), _) => o.precedence(),
+            (None, Some(c)) if c.is_sign_negative() && !c.is_nan() => Operator::USub.precedence(),
+            _ => u8::MAX
+        }
+    }
+
+    pub(super) fn is_prefix(&self) -> bool {
+        self.precedence() == Operator::USub.precedence()
+    }
+}
@@ -0,0 +1,136 @@
+use std::f64::consts::LN_10;
+use std::ops::{Add, Mul};
+
+use super::number::Number;
+use super::{derivative, Limit, Multi, MultiFunction, Simple, SimpleFunction};
+
+/// Value of an expression with its derivative, carried through the evaluation in double precision.
+/// x is `Dual::variable(x)`, the variables that are not derived are `Dual::constant`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dual {
+    pub value : f64,
+    pub deriv : f64,
+}
+
+impl Dual {
+    pub fn new(value : f64, deriv : f64) -> Dual {
+        Dual { value, deriv }
+    }
+
+    pub fn constant(value : f64) -> Dual {
+        Dual { value, deriv : 0.0 }
+    }
+
+    /// The variable derived with respect to, its derivative is 1
+    pub fn variable(value : f64) -> Dual {
+        Dual { value, deriv : 1.0 }
+    }
+}
+
+// Chain rule term, a part that does not change adds nothing even where the factor is infinite or NaN.
+// derive folds the same terms away.
+fn scaled(deriv : f64, factor : impl FnOnce() -> f64) -> f64 {
+    if deriv == 0.0 { 0.0 } else { deriv * factor() }
+}
+
+// Derivative of the built-in at v, its value is given for the ones that reuse it
+fn slope(name : Simple, v : f64, value : f64) -> f64 {
+    match name {
+        Simple::Plus | Simple::Re | Simple::Conj => 1.0,
+        Simple::Minus => -1.0,
+        Simple::Square => 2.0 * v,
+        Simple::Sin => v.cos(),
+        Simple::Cos => -v.sin(),
+        Simple::Tan => 1.0 / (v.cos() * v.cos()),
+        Simple::Cot => -1.0 / (v.sin() * v.sin()),
+        Simple::Sec => value * v.tan(),
+        Simple::Csc => -value / v.tan(),
+        Simple::Abs => v / v.abs(),
+        Simple::Exp => value,
+        Simple::Ln => 1.0 / v,
+        Simple::Log => 1.0 / (v * LN_10),
+        Simple::Sqrt => 0.5 / value,
+        Simple::Asin => 1.0 / (1.0 - v * v).sqrt(),
+        Simple::Acos => -1.0 / (1.0 - v * v).sqrt(),
+        Simple::Atan => 1.0 / (1.0 + v * v),
+        Simple::Sinh => v.cosh(),
+        Simple::Cosh => v.sinh(),
+        Simple::Tanh => 1.0 / (v.cosh() * v.cosh()),
+        Simple::Asinh => 1.0 / (v * v + 1.0).sqrt(),
+        Simple::Acosh => 1.0 / (v * v - 1.0).sqrt(),
+        Simple::Atanh => 1.0 / (1.0 - v * v),
+        // Piecewise constant, im and arg of a real number
+        Simple::Factorial | Simple::Ceil | Simple::Floor | Simple::Round | Simple::Im | Simple::Arg => 0.0,
+        // Functions given from outside have no known derivative
+        Simple::External => f64::NAN
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add(self, other : Dual) -> Dual {
+        Dual::new(self.value + other.value, self.deriv + other.deriv)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul(self, other : Dual) -> Dual {
+        Dual::new(self.value * other.value, scaled(self.deriv, || other.value) + scaled(other.deriv, || self.value))
+    }
+}
+
+// Values are the ones of the f64 evaluation
+impl Number for Dual {
+    fn from_f64(c : f64) -> Dual {
+        Dual::constant(c)
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.value
+    }
+
+    fn is_true(&self) -> Option<bool> {
+        Some(self.value > 0.0)
+    }
+
+    fn is_constant(&self) -> bool {
+        self.deriv == 0.0
+    }
+
+    // The derivative along x is estimated from the values around, unknown when a variable also moves with x
+    fn from_numeric(value : f64, x : &Dual, at : impl FnMut(f64) -> f64, varies : bool) -> Dual {
+        if varies { return Dual::new(value, f64::NAN); }
+        if x.deriv == 0.0 { return Dual::constant(value); }
+        match derivative(at, x.value) {
+            Limit::Value(slope) => Dual::new(value, slope * x.deriv),
+            _ => Dual::new(value, f64::NAN)
+        }
+    }
+
+    fn simple(name : Simple, f : SimpleFunction, u : Dual) -> Dual {
+        let value = f64::simple(name, f, u.value);
+        Dual::new(value, scaled(u.deriv, || slope(name, u.value, value)))
+    }
+
+    fn multi(name : Multi, f : MultiFunction, x : &[Dual]) -> Dual {
+        let value = f64::multi(name, f, &x.iter().map(|d| d.value).collect::<Vec<_>>());
+        let deriv = match (name, x) {
+            (Multi::Add, [a, b]) => a.deriv + b.deriv,
+            (Multi::Sub, [a, b]) => a.deriv - b.deriv,
+            (Multi::Mul, [a, b]) => (*a * *b).deriv,
+            (Multi::Div, [a, b]) => scaled(scaled(a.deriv, || b.value) - scaled(b.deriv, || a.value), || 1.0 / (b.value * b.value)),
+            // Like derive, ln of the base is only needed when the exponent changes
+            (Multi::Pow | Multi::PowCall, [a, b]) if b.deriv == 0.0 => scaled(a.deriv, || b.value * a.value.powf(b.value - 1.0)),
+            (Multi::Pow | Multi::PowCall, [a, b]) if a.deriv == 0.0 => b.deriv * value * a.value.ln(),
+            (Multi::Pow | Multi::PowCall, [a, b]) => value * (b.deriv * a.value.ln() + b.value * a.deriv / a.value),
+            // Comparisons are piecewise constant
+            (Multi::Equal | Multi::NotEqual | Multi::LessEqual | Multi::Less | Multi::GreaterEqual | Multi::Greater, _) => 0.0,
+            // The first argument reaching the extremum, as derive does
+            (Multi::Max | Multi::Min, _) => x.iter().find(|d| d.value == value).map_or(f64::NAN, |d| d.deriv),
+            // Wrong number of arguments
+            (Multi::Add | Multi::Sub | Multi::Mul | Multi::Div | Multi::Pow | Multi::PowCall, _) => f64::NAN
+        };
+        Dual::new(value, deriv)
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+
+/// Position of a token in the parsed input, in chars (not bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// Smallest span covering both spans
+    pub fn merge(&self, other: Span) -> Span {
+        Span { start: self.start.min(other.start), end: self.end.max(other.end) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnbalancedParen(char, Span),
+    UnexpectedOperator(String, Span),
+    UnexpectedIdentifier(String, Span),
+    UnknownIdentifier(String, Span),
+    MissingArgument(Span),
+    TrailingToken(String, Span),
+    NestingTooDeep(Span),
+    /// Function name, minimum and maximum arity, number of arguments given
+    WrongArity(String, usize, usize, usize, Span),
+    MalformedNumber(String, Span),
+    ExpectedVariable(Span),
+    TooLarge(Span),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnbalancedParen(_, span)
+            | ParseError::UnexpectedOperator(_, span)
+            | ParseError::UnexpectedIdentifier(_, span)
+            | ParseError::UnknownIdentifier(_, span)
+            | ParseError::MissingArgument(span)
+            | ParseError::TrailingToken(_, span)
+            | ParseError::NestingTooDeep(span)
+            | ParseError::WrongArity(_, _, _, _, span)
+            | ParseError::MalformedNumber(_, span)
+            | ParseError::ExpectedVariable(span)
+            | ParseError::TooLarge(span) => *span,
+        }
+    }
+
+    /// Stable code given through the FFI, 0 is kept for "no error"
+    pub fn code(&self) -> i32 {
+        match self {
+            ParseError::UnbalancedParen(_, _) => 1,
+            ParseError::UnexpectedOperator(_, _) => 2,
+            ParseError::UnexpectedIdentifier(_, _) => 3,
+            ParseError::UnknownIdentifier(_, _) => 4,
+            ParseError::MissingArgument(_) => 5,
+            ParseError::TrailingToken(_, _) => 6,
+            ParseError::NestingTooDeep(_) => 7,
+            ParseError::WrongArity(_, _, _, _, _) => 8,
+            ParseError::MalformedNumber(_, _) => 9,
+            ParseError::ExpectedVariable(_) => 10,
+            ParseError::TooLarge(_) => 11,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnbalancedParen(c, _) => write!(f, "Unbalanced '{}'", c),
+            ParseError::UnexpectedOperator(o, _) => write!(f, "Unexpected operator : {}", o),
+            ParseError::UnexpectedIdentifier(s, _) => write!(f, "Unexpected identifier : {}", s),
+            ParseError::UnknownIdentifier(s, _) => write!(f, "Unknown identifier : {}", s),
+            ParseError::MissingArgument(_) => write!(f, "Expression was expected but none found"),
+            ParseError::TrailingToken(s, _) => write!(f, "Expected EOF but got : {}", s),
+            ParseError::NestingTooDeep(_) => write!(f, "Expression is nested too deeply"),
+            ParseError::WrongArity(name, min, max, got, _) => {
+                let plural = |n : &usize| if *n == 1 { "argument" } else { "arguments" };
+                if min == max {
+                    write!(f, "{} expects {} {}, got {}", name, min, plural(min), got)
+                } else if *max == usize::MAX {
+                    write!(f, "{} expects at least {} {}, got {}", name, min, plural(min), got)
+                } else {
+                    write!(f, "{} expects {} to {} arguments, got {}", name, min, max, got)
+                }
+            },
+            ParseError::MalformedNumber(s, _) => write!(f, "Malformed number : {}", s),
+            ParseError::ExpectedVariable(_) => write!(f, "Expected a variable"),
+            ParseError::TooLarge(_) => write!(f, "Expression is too large"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Reason why a parsed expression could not be evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    MissingArgument(Span),
+    InvalidAssignment(Span),
+    InvalidIterator(Span),
+    TooManyIterations(Span),
+    UnknownFunction(Span),
+    WrongArity(Span),
+    RecursionTooDeep(Span),
+    // Bound of a sum taking several values, like over an interval
+    UndeterminedBound(Span),
+    // What a built-in like integral binds is not a variable
+    InvalidVariable(Span),
+    // Integral, limit or derivative of a function that grows or oscillates too much, like 1/x from 0 to 1
+    NotConverged(Span),
+    // Limit with a different value on each side, like abs(x)/x at 0
+    OneSidedMismatch(Span),
+}
+
+impl EvalError {
+    pub fn span(&self) -> Span {
+        match self {
+            EvalError::MissingArgument(span)
+            | EvalError::InvalidAssignment(span)
+            | EvalError::InvalidIterator(span)
+            | EvalError::TooManyIterations(span)
+            | EvalError::UnknownFunction(span)
+            | EvalError::WrongArity(span)
+            | EvalError::RecursionTooDeep(span)
+            | EvalError::UndeterminedBound(span)
+            | EvalError::InvalidVariable(span)
+            | EvalError::NotConverged(span)
+            | EvalError::OneSidedMismatch(span) => *span,
+        }
+    }
+
+    pub fn with_span(self, span: Span) -> EvalError {
+        match self {
+            EvalError::MissingArgument(_) => EvalError::MissingArgument(span),
+            EvalError::InvalidAssignment(_) => EvalError::InvalidAssignment(span),
+            EvalError::InvalidIterator(_) => EvalError::InvalidIterator(span),
+            EvalError::TooManyIterations(_) => EvalError::TooManyIterations(span),
+            EvalError::UnknownFunction(_) => EvalError::UnknownFunction(span),
+            EvalError::WrongArity(_) => EvalError::WrongArity(span),
+            EvalError::RecursionTooDeep(_) => EvalError::RecursionTooDeep(span),
+            EvalError::UndeterminedBound(_) => EvalError::UndeterminedBound(span),
+            EvalError::InvalidVariable(_) => EvalError::InvalidVariable(span),
+            EvalError::NotConverged(_) => EvalError::NotConverged(span),
+            EvalError::OneSidedMismatch(_) => EvalError::OneSidedMismatch(span),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::MissingArgument(_) => write!(f, "Missing argument"),
+            EvalError::InvalidAssignment(_) => write!(f, "Only variables can be assigned"),
+            EvalError::InvalidIterator(_) => write!(f, "Iterator expects (i = start, end, expression)"),
+            EvalError::TooManyIterations(_) => write!(f, "Too many iterations"),
+            EvalError::UnknownFunction(_) => write!(f, "Unknown function"),
+            EvalError::WrongArity(_) => write!(f, "Wrong number of arguments"),
+            EvalError::RecursionTooDeep(_) => write!(f, "Too many nested function calls"),
+            EvalError::UndeterminedBound(_) => write!(f, "Bound of the sum is not a single integer"),
+            EvalError::InvalidVariable(_) => write!(f, "Expected a variable to bind"),
+            EvalError::NotConverged(_) => write!(f, "Does not converge"),
+            EvalError::OneSidedMismatch(_) => write!(f, "The limits from the left and the right differ"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
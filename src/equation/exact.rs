@@ -0,0 +1,526 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul};
+
+use super::display::format_number;
+use super::number::{self, Number};
+use super::{Multi, MultiFunction, Simple, SimpleFunction};
+
+/// Largest n computed exactly by `n!`, 10000! already has 35660 digits
+const MAX_FACTORIAL : u64 = 10_000;
+/// Most bits of a numerator computed exactly, about 40000 digits, larger ones are computed with floats
+const MAX_BITS : u64 = 1 << 17;
+/// Same for denominators, the time to reduce a fraction grows with the square of their size
+const MAX_DENOMINATOR_BITS : u64 = 1 << 10;
+/// Most iterations of an exact `sum` or `prod`, every step can work on fractions of thousands of digits
+const MAX_ITERATIONS : i64 = 10_000;
+
+// Magnitude of an integer of any size, little endian digits in base 2^32 without trailing zeros
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Natural(Vec<u32>);
+
+impl Natural {
+    fn zero() -> Natural {
+        Natural(vec![])
+    }
+
+    fn from_u64(n : u64) -> Natural {
+        Natural(vec![n as u32, (n >> 32) as u32]).trim()
+    }
+
+    fn trim(mut self) -> Natural {
+        while self.0.last() == Some(&0) { self.0.pop(); }
+        self
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        match self.0[..] {
+            [] => Some(0),
+            [low] => Some(low as u64),
+            [low, high] => Some(((high as u64) << 32) | low as u64),
+            _ => None
+        }
+    }
+
+    fn bits(&self) -> u64 {
+        match self.0.last() {
+            Some(top) => self.0.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0
+        }
+    }
+
+    fn add(&self, other : &Natural) -> Natural {
+        let (long, short) = if self.0.len() >= other.0.len() { (self, other) } else { (other, self) };
+        let mut digits = Vec::with_capacity(long.0.len() + 1);
+        let mut carry = 0u64;
+        for (i, digit) in long.0.iter().enumerate() {
+            let sum = *digit as u64 + *short.0.get(i).unwrap_or(&0) as u64 + carry;
+            digits.push(sum as u32);
+            carry = sum >> 32;
+        }
+        digits.push(carry as u32);
+        Natural(digits).trim()
+    }
+
+    // Only called with self >= other
+    fn sub(&self, other : &Natural) -> Natural {
+        let mut digits = Vec::with_capacity(self.0.len());
+        let mut borrow = 0i64;
+        for (i, digit) in self.0.iter().enumerate() {
+            let difference = *digit as i64 - *other.0.get(i).unwrap_or(&0) as i64 - borrow;
+            digits.push(difference as u32);
+            borrow = (difference < 0) as i64;
+        }
+        Natural(digits).trim()
+    }
+
+    fn mul(&self, other : &Natural) -> Natural {
+        if self.is_zero() || other.is_zero() { return Natural::zero(); }
+        let mut digits = vec![0u32; self.0.len() + other.0.len()];
+        for (i, a) in self.0.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.0.iter().enumerate() {
+                let product = *a as u64 * *b as u64 + digits[i + j] as u64 + carry;
+                digits[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            digits[i + other.0.len()] = carry as u32;
+        }
+        Natural(digits).trim()
+    }
+
+    fn shl(&self, n : u64) -> Natural {
+        if self.is_zero() { return Natural::zero(); }
+        let (words, bits) = ((n / 32) as usize, n % 32);
+        let mut digits = vec![0u32; words];
+        let mut carry = 0u32;
+        for digit in &self.0 {
+            digits.push((digit << bits) | carry);
+            carry = if bits == 0 { 0 } else { digit >> (32 - bits) };
+        }
+        digits.push(carry);
+        Natural(digits).trim()
+    }
+
+    fn shr(&self, n : u64) -> Natural {
+        let (words, bits) = ((n / 32) as usize, n % 32);
+        if words >= self.0.len() { return Natural::zero(); }
+        let digits = (words..self.0.len()).map(|i| {
+            let high = if bits == 0 { 0 } else { self.0.get(i + 1).map_or(0, |next| next << (32 - bits)) };
+            (self.0[i] >> bits) | high
+        }).collect();
+        Natural(digits).trim()
+    }
+
+    fn div_rem_small(&self, divisor : u32) -> (Natural, u32) {
+        let mut digits = vec![0u32; self.0.len()];
+        let mut remainder = 0u64;
+        for i in (0..self.0.len()).rev() {
+            let current = (remainder << 32) | self.0[i] as u64;
+            digits[i] = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        (Natural(digits).trim(), remainder as u32)
+    }
+
+    // Long division from Knuth's algorithm D, divisor is not zero
+    fn div_rem(&self, divisor : &Natural) -> (Natural, Natural) {
+        if self.cmp(divisor) == Ordering::Less { return (Natural::zero(), self.clone()); }
+        if let [digit] = divisor.0[..] {
+            let (quotient, remainder) = self.div_rem_small(digit);
+            return (quotient, Natural::from_u64(remainder as u64));
+        }
+
+        // The top digit of the divisor gets its high bit set so each estimated digit is off by 2 at most
+        let shift = divisor.0.last().map_or(0, |top| top.leading_zeros()) as u64;
+        let v = divisor.shl(shift).0;
+        let mut u = self.shl(shift).0;
+        u.resize(self.0.len() + 1, 0);
+        let n = v.len();
+        let m = u.len() - n;
+        let mut quotient = vec![0u32; m];
+        for j in (0..m).rev() {
+            let top = ((u[j + n] as u64) << 32) | u[j + n - 1] as u64;
+            let mut estimate = top / v[n - 1] as u64;
+            let mut remainder = top % v[n - 1] as u64;
+            while estimate >> 32 != 0 || estimate * v[n - 2] as u64 > ((remainder << 32) | u[j + n - 2] as u64) {
+                estimate -= 1;
+                remainder += v[n - 1] as u64;
+                if remainder >> 32 != 0 { break; }
+            }
+
+            let mut borrow = 0i64;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let product = estimate * v[i] as u64 + carry;
+                carry = product >> 32;
+                let difference = u[i + j] as i64 - borrow - (product & 0xFFFF_FFFF) as i64;
+                u[i + j] = difference as u32;
+                borrow = (difference < 0) as i64;
+            }
+            let difference = u[j + n] as i64 - borrow - carry as i64;
+            u[j + n] = difference as u32;
+
+            // Estimated one too many, added back
+            if difference < 0 {
+                estimate -= 1;
+                let mut carry = 0u64;
+                for i in 0..n {
+                    let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                    u[i + j] = sum as u32;
+                    carry = sum >> 32;
+                }
+                u[j + n] = u[j + n].wrapping_add(carry as u32);
+            }
+            quotient[j] = estimate as u32;
+        }
+        u.truncate(n);
+        (Natural(quotient).trim(), Natural(u).trim().shr(shift))
+    }
+
+    fn gcd(&self, other : &Natural) -> Natural {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        while !b.is_zero() {
+            let remainder = a.div_rem(&b).1;
+            a = b;
+            b = remainder;
+        }
+        a
+    }
+
+    fn pow(&self, mut exponent : u64) -> Natural {
+        let mut base = self.clone();
+        let mut res = Natural::from_u64(1);
+        while exponent > 0 {
+            if exponent & 1 == 1 { res = res.mul(&base); }
+            exponent >>= 1;
+            if exponent > 0 { base = base.mul(&base); }
+        }
+        res
+    }
+}
+
+impl Ord for Natural {
+    fn cmp(&self, other : &Natural) -> Ordering {
+        self.0.len().cmp(&other.0.len()).then_with(|| self.0.iter().rev().cmp(other.0.iter().rev()))
+    }
+}
+
+impl PartialOrd for Natural {
+    fn partial_cmp(&self, other : &Natural) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Natural {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        // Groups of 9 decimal digits, the lowest first
+        let mut groups = vec![];
+        let mut rest = self.clone();
+        while !rest.is_zero() {
+            let (quotient, group) = rest.div_rem_small(1_000_000_000);
+            groups.push(group);
+            rest = quotient;
+        }
+        match groups.split_last() {
+            None => f.write_str("0"),
+            Some((top, lower)) => {
+                write!(f, "{}", top)?;
+                lower.iter().rev().try_for_each(|group| write!(f, "{:09}", group))
+            }
+        }
+    }
+}
+
+/// Fraction of integers of any size, kept in lowest terms with a positive denominator
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rational {
+    negative : bool,
+    numerator : Natural,
+    denominator : Natural,
+}
+
+impl Rational {
+    fn new(negative : bool, numerator : Natural, denominator : Natural) -> Rational {
+        if numerator.is_zero() { return Rational::integer(0); }
+        // Integers are already in lowest terms
+        if denominator.to_u64() == Some(1) { return Rational { negative, numerator, denominator }; }
+        let divisor = numerator.gcd(&denominator);
+        Rational { negative, numerator : numerator.div_rem(&divisor).0, denominator : denominator.div_rem(&divisor).0 }
+    }
+
+    // Same as new, None when the fraction is too large to be reduced in reasonable time
+    fn checked(negative : bool, numerator : Natural, denominator : Natural) -> Option<Rational> {
+        if numerator.bits() > MAX_BITS || denominator.bits() > MAX_DENOMINATOR_BITS { return None; }
+        Some(Rational::new(negative, numerator, denominator))
+    }
+
+    pub fn integer(n : i64) -> Rational {
+        Rational { negative : n < 0, numerator : Natural::from_u64(n.unsigned_abs()), denominator : Natural::from_u64(1) }
+    }
+
+    /// Value of a decimal like `-12.5` or `3`, None for anything else
+    pub fn from_decimal(s : &str) -> Option<Rational> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s)
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if integer.is_empty() || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) { return None; }
+        let numerator = integer.chars().chain(fraction.chars())
+            .fold(Natural::zero(), |value, c| value.mul(&Natural::from_u64(10)).add(&Natural::from_u64(c as u64 - '0' as u64)));
+        Some(Rational::new(negative, numerator, Natural::from_u64(10).pow(fraction.len() as u64)))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator.to_u64() == Some(1)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.numerator.is_zero()
+    }
+
+    // Value when it is an integer fitting an i64
+    fn to_i64(&self) -> Option<i64> {
+        if !self.is_integer() { return None; }
+        let magnitude = i64::try_from(self.numerator.to_u64()?).ok()?;
+        Some(if self.negative { -magnitude } else { magnitude })
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        if self.is_zero() { return 0.0; }
+        // Quotient with 63 or 64 significant bits, then scaled back by a power of 2
+        let shift = 63 + self.denominator.bits() as i64 - self.numerator.bits() as i64;
+        let quotient = if shift >= 0 {
+            self.numerator.shl(shift as u64).div_rem(&self.denominator).0
+        } else {
+            self.numerator.div_rem(&self.denominator.shl(-shift as u64)).0
+        };
+        let mut value = quotient.to_u64().unwrap_or(u64::MAX) as f64;
+        let mut exponent = -shift;
+        // Steps keep the factor itself in range
+        while exponent.abs() > 1000 {
+            value *= 2f64.powi(1000 * exponent.signum() as i32);
+            exponent -= 1000 * exponent.signum();
+        }
+        value *= 2f64.powi(exponent as i32);
+        if self.negative { -value } else { value }
+    }
+
+    fn neg(&self) -> Rational {
+        Rational { negative : !self.negative && !self.is_zero(), ..self.clone() }
+    }
+
+    // None when the result is too large, like the operations below
+    fn add(&self, other : &Rational) -> Option<Rational> {
+        let a = self.numerator.mul(&other.denominator);
+        let b = other.numerator.mul(&self.denominator);
+        let denominator = self.denominator.mul(&other.denominator);
+        if self.negative == other.negative { return Rational::checked(self.negative, a.add(&b), denominator); }
+        // Different signs, the larger magnitude gives its sign
+        match a.cmp(&b) {
+            Ordering::Less => Rational::checked(other.negative, b.sub(&a), denominator),
+            _ => Rational::checked(self.negative, a.sub(&b), denominator)
+        }
+    }
+
+    fn mul(&self, other : &Rational) -> Option<Rational> {
+        Rational::checked(self.negative != other.negative, self.numerator.mul(&other.numerator), self.denominator.mul(&other.denominator))
+    }
+
+    fn div(&self, other : &Rational) -> Option<Rational> {
+        if other.is_zero() { return None; }
+        Rational::checked(self.negative != other.negative, self.numerator.mul(&other.denominator), self.denominator.mul(&other.numerator))
+    }
+
+    // Integer powers small enough to be computed, 0 has no negative power
+    fn pow(&self, exponent : &Rational) -> Option<Rational> {
+        let n = exponent.to_i64()?;
+        if n < 0 && self.is_zero() { return None; }
+        if self.numerator.bits().max(self.denominator.bits()).saturating_mul(n.unsigned_abs()) > MAX_BITS { return None; }
+        let (numerator, denominator) = (self.numerator.pow(n.unsigned_abs()), self.denominator.pow(n.unsigned_abs()));
+        let negative = self.negative && n % 2 != 0;
+        Some(if n < 0 { Rational::new(negative, denominator, numerator) } else { Rational::new(negative, numerator, denominator) })
+    }
+
+    fn factorial(&self) -> Option<Rational> {
+        let n = self.to_i64().filter(|n| (0..=MAX_FACTORIAL as i64).contains(n))?;
+        let numerator = (2..=n as u64).fold(Natural::from_u64(1), |product, i| product.mul(&Natural::from_u64(i)));
+        Some(Rational { negative : false, numerator, denominator : Natural::from_u64(1) })
+    }
+
+    // Largest integer not above the value
+    fn floor(&self) -> Rational {
+        let (quotient, remainder) = self.numerator.div_rem(&self.denominator);
+        let quotient = if self.negative && !remainder.is_zero() { quotient.add(&Natural::from_u64(1)) } else { quotient };
+        Rational::new(self.negative, quotient, Natural::from_u64(1))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other : &Rational) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (negative, _) => {
+                let magnitude = self.numerator.mul(&other.denominator).cmp(&other.numerator.mul(&self.denominator));
+                if negative { magnitude.reverse() } else { magnitude }
+            }
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other : &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Written as a fraction like -7/2, integers without denominator
+impl fmt::Display for Rational {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        if self.negative { f.write_str("-")?; }
+        write!(f, "{}", self.numerator)?;
+        if !self.is_integer() { write!(f, "/{}", self.denominator)?; }
+        Ok(())
+    }
+}
+
+/// Value of an exact evaluation, a float once a function without exact result like sin or sqrt was used
+#[derive(Clone, Debug, PartialEq)]
+pub enum Exact {
+    Rational(Rational),
+    Float(f64),
+}
+
+impl Exact {
+    /// The decimal the float is written with, like 1/10 for 0.1. pi, e, inf and NaN stay floats.
+    pub fn from_f64(c : f64) -> Exact {
+        if !c.is_finite() || c == std::f64::consts::PI || c == std::f64::consts::E { return Exact::Float(c); }
+        match Rational::from_decimal(&c.to_string()) {
+            Some(r) => Exact::Rational(r),
+            None => Exact::Float(c)
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Exact::Rational(r) => r.to_f64(),
+            Exact::Float(c) => *c
+        }
+    }
+
+    fn rational(&self) -> Option<&Rational> {
+        match self {
+            Exact::Rational(r) => Some(r),
+            Exact::Float(_) => None
+        }
+    }
+}
+
+impl fmt::Display for Exact {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Exact::Rational(r) => write!(f, "{}", r),
+            Exact::Float(c) => f.write_str(&format_number(*c))
+        }
+    }
+}
+
+impl Add for Exact {
+    type Output = Exact;
+    fn add(self, other : Exact) -> Exact {
+        let exact = match (&self, &other) {
+            (Exact::Rational(a), Exact::Rational(b)) => a.add(b),
+            _ => None
+        };
+        exact.map_or_else(|| Exact::Float(self.to_f64() + other.to_f64()), Exact::Rational)
+    }
+}
+
+impl Mul for Exact {
+    type Output = Exact;
+    fn mul(self, other : Exact) -> Exact {
+        let exact = match (&self, &other) {
+            (Exact::Rational(a), Exact::Rational(b)) => a.mul(b),
+            _ => None
+        };
+        exact.map_or_else(|| Exact::Float(self.to_f64() * other.to_f64()), Exact::Rational)
+    }
+}
+
+fn truth(b : bool) -> Exact {
+    Exact::Rational(Rational::integer(b as i64))
+}
+
+// Operators and built-ins without an exact result fall back to f64
+impl Number for Exact {
+    fn from_f64(c : f64) -> Exact {
+        Exact::from_f64(c)
+    }
+
+    fn to_f64(&self) -> f64 {
+        Exact::to_f64(self)
+    }
+
+    fn is_true(&self) -> Option<bool> {
+        Some(match self {
+            Exact::Rational(r) => !r.negative && !r.is_zero(),
+            Exact::Float(c) => *c > 0.0
+        })
+    }
+
+    fn simple(name : Simple, f : SimpleFunction, x : Exact) -> Exact {
+        let exact = match (name, x.rational()) {
+            (Simple::Plus | Simple::Re | Simple::Conj, Some(r)) => Some(r.clone()),
+            (Simple::Im, Some(_)) => Some(Rational::integer(0)),
+            (Simple::Minus, Some(r)) => Some(r.neg()),
+            (Simple::Square, Some(r)) => r.mul(r),
+            (Simple::Factorial, Some(r)) => r.factorial(),
+            (Simple::Abs, Some(r)) => Some(Rational { negative : false, ..r.clone() }),
+            (Simple::Floor, Some(r)) => Some(r.floor()),
+            (Simple::Ceil, Some(r)) => Some(r.neg().floor().neg()),
+            // Halves away from zero like f32::round
+            (Simple::Round, Some(r)) if r.negative => r.neg().add(&Rational::new(false, Natural::from_u64(1), Natural::from_u64(2))).map(|r| r.floor().neg()),
+            (Simple::Round, Some(r)) => r.add(&Rational::new(false, Natural::from_u64(1), Natural::from_u64(2))).map(|r| r.floor()),
+            _ => None
+        };
+        match exact {
+            Some(r) => Exact::Rational(r),
+            None if name == Simple::Factorial => Exact::Float(number::factorial(x.to_f64())),
+            None => Exact::Float(f64::simple(name, f, x.to_f64()))
+        }
+    }
+
+    fn multi(name : Multi, f : MultiFunction, x : &[Exact]) -> Exact {
+        let rationals : Option<Vec<&Rational>> = x.iter().map(Exact::rational).collect();
+        let exact = match (name, rationals.as_deref()) {
+            (Multi::Add, Some([a, b])) => a.add(b),
+            (Multi::Sub, Some([a, b])) => a.add(&b.neg()),
+            (Multi::Mul, Some([a, b])) => a.mul(b),
+            (Multi::Div, Some([a, b])) => a.div(b),
+            (Multi::Pow | Multi::PowCall, Some([a, b])) => a.pow(b),
+            (Multi::Equal, Some([a, b])) => return truth(a == b),
+            (Multi::NotEqual, Some([a, b])) => return truth(a != b),
+            (Multi::LessEqual, Some([a, b])) => return truth(a <= b),
+            (Multi::Less, Some([a, b])) => return truth(a < b),
+            (Multi::GreaterEqual, Some([a, b])) => return truth(a >= b),
+            (Multi::Greater, Some([a, b])) => return truth(a > b),
+            (Multi::Max, Some(rationals)) => rationals.iter().max().map(|r| (*r).clone()),
+            (Multi::Min, Some(rationals)) => rationals.iter().min().map(|r| (*r).clone()),
+            _ => None
+        };
+        match exact {
+            Some(r) => Exact::Rational(r),
+            None => Exact::Float(f64::multi(name, f, &x.iter().map(Exact::to_f64).collect::<Vec<_>>()))
+        }
+    }
+
+    fn max_iterations() -> i64 {
+        MAX_ITERATIONS
+    }
+}
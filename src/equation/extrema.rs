@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use super::{derive_with, simplify, Dual, EvalError, Expression, UserFunctions};
+
+/// Segments of [a, b] checked for a sign change of the derivatives, points closer than one of them to another can be missed
+const SCAN_SEGMENTS : usize = 1000;
+/// Most Newton steps refining a point, the bisection they fall back to needs less than 100 to reach the precision of a double
+const MAX_ITERATIONS : u32 = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointKind {
+    Minimum,
+    Maximum,
+    /// Where the curve turns from concave to convex or back
+    Inflection,
+}
+
+/// Point of a curve found by `find_critical_points`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CriticalPoint {
+    pub kind : PointKind,
+    pub x : f64,
+    pub y : f64,
+}
+
+/// Local minima, maxima and inflection points of `expression` for x in [a, b] sorted by x, evaluated in double precision
+pub fn critical_points(e : &Expression, a : f64, b : f64) -> Vec<CriticalPoint> {
+    critical_points_with(e, &HashMap::new(), &UserFunctions::new(), a, b).unwrap_or_default()
+}
+
+/// Same as `critical_points` with variables and user functions, the first failed evaluation is the error.
+/// The first two derivatives are built by `derive`, the next one comes with each evaluation of them.
+/// Assignments in the expression are not kept.
+pub fn critical_points_with(e : &Expression, variables : &HashMap<String, f64>, functions : &UserFunctions, a : f64, b : f64) -> Result<Vec<CriticalPoint>, EvalError> {
+    let slope = simplify(&derive_with(e, "x", functions));
+    let curvature = simplify(&derive_with(&slope, "x", functions));
+    let derivatives = [e, &slope, &curvature];
+    let mut scratch : HashMap<String, Dual> = variables.iter().map(|(name, value)| (name.clone(), Dual::constant(*value))).collect();
+    let mut error = None;
+    let res = find_critical_points(|order, x| match derivatives[order].try_evaluate_dual_with(Dual::variable(x), &mut scratch, functions) {
+        Ok(y) => y,
+        Err(e) => { error.get_or_insert(e); Dual::constant(f64::NAN) }
+    }, a, b);
+    match error {
+        Some(error) => Err(error),
+        None => Ok(res)
+    }
+}
+
+/// Local minima, maxima and inflection points for x in [a, b] sorted by x, `f(n, x)` gives the n-th derivative at x for n up to 2 with the next one.
+/// Extrema are where the first derivative changes sign, inflection points where the second one does, both refined with Newton's method kept inside the sign change.
+/// A kink like abs(x) at 0 is an extremum, a pole or a jump is neither, and the ends of [a, b] are not extrema.
+pub fn find_critical_points(mut f : impl FnMut(usize, f64) -> Dual, a : f64, b : f64) -> Vec<CriticalPoint> {
+    if !(a.is_finite() && b.is_finite() && a < b) { return vec![]; }
+    let step = (b - a) / SCAN_SEGMENTS as f64;
+    let xs : Vec<f64> = (0..=SCAN_SEGMENTS).map(|i| if i == SCAN_SEGMENTS { b } else { a + step * i as f64 }).collect();
+    let tolerance = f64::EPSILON * a.abs().max(b.abs());
+
+    let mut res = vec![];
+    for order in [1, 2] {
+        let gs : Vec<f64> = xs.iter().map(|x| f(order, *x).value).collect();
+        for i in 0..SCAN_SEGMENTS {
+            // The sign change is either on a sample, where the derivative is 0 or undefined like at a kink, or between two
+            let (before, x, after) = if gs[i] == 0.0 || gs[i].is_nan() {
+                if i == 0 { continue; }
+                (i - 1, xs[i], i + 1)
+            } else if (gs[i] < 0.0) != (gs[i + 1] < 0.0) && gs[i + 1] != 0.0 && !gs[i + 1].is_nan() {
+                (i, newton(|x| f(order, x), xs[i], xs[i + 1], gs[i], gs[i + 1], tolerance), i + 1)
+            } else {
+                continue;
+            };
+            let (g0, g1) = (gs[before], gs[after]);
+            if !(g0.is_finite() && g1.is_finite() && (g0 < 0.0) != (g1 < 0.0) && g0 != 0.0 && g1 != 0.0) { continue; }
+            // Near a pole the derivative grows past its values around, at a kink or a root it does not
+            let h = step * 1e-3;
+            let bound = g0.abs().max(g1.abs());
+            if !(f(order, x - h).value.abs() <= bound && f(order, x + h).value.abs() <= bound) { continue; }
+            let y = f(0, x).value;
+            if !y.is_finite() { continue; }
+            let kind = match order {
+                1 if g0 < 0.0 => PointKind::Minimum,
+                1 => PointKind::Maximum,
+                _ => PointKind::Inflection
+            };
+            // Neither lower nor higher than the curve around, like a jump
+            let (y0, y1) = (f(0, xs[before]).value, f(0, xs[after]).value);
+            let extremum = match kind {
+                PointKind::Minimum => y <= y0.min(y1),
+                PointKind::Maximum => y >= y0.max(y1),
+                PointKind::Inflection => true
+            };
+            if extremum {
+                res.push(CriticalPoint { kind, x, y });
+            }
+        }
+    }
+    res.sort_by(|p, q| p.x.total_cmp(&q.x));
+    res
+}
+
+// Newton's method on g = (value, derivative) from a to b where the value changes sign.
+// Steps leaving the bracket, and the ones where the derivative does not help, are replaced by bisection.
+fn newton(mut g : impl FnMut(f64) -> Dual, a : f64, b : f64, ga : f64, gb : f64, tolerance : f64) -> f64 {
+    let (mut lo, mut hi) = (a, b);
+    let mut x = if ga.abs() < gb.abs() { a + (b - a) / 4.0 } else { b - (b - a) / 4.0 };
+    for _ in 0..MAX_ITERATIONS {
+        let d = g(x);
+        if d.value == 0.0 { return x; }
+        if !d.value.is_nan() {
+            if (d.value < 0.0) == (ga < 0.0) { lo = x; } else { hi = x; }
+        }
+        let tol = 2.0 * f64::EPSILON * x.abs() + 0.5 * tolerance;
+        if hi - lo <= tol { break; }
+        let next = x - d.value / d.deriv;
+        if (next - x).abs() <= tol && lo <= next && next <= hi { return next; }
+        let middle = (lo + hi) / 2.0;
+        x = if lo < next && next < hi && !d.value.is_nan() { next } else if x != middle { middle } else { break };
+    }
+    x
+}
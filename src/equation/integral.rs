@@ -0,0 +1,115 @@
+/// Most parts the interval is split into, an integral still off by more than the tolerance does not converge
+const MAX_SUBDIVISIONS : usize = 2000;
+/// Largest error estimate relative to the integral of |f|
+const RELATIVE_TOLERANCE : f64 = 1e-10;
+
+// Gauss–Kronrod 15 point rule, nodes of the half with their weights. The odd ones are the nodes of the 7 point Gauss rule.
+const KRONROD_NODES : [f64; 8] = [
+    0.9914553711208126, 0.9491079123427585, 0.8648644233597691, 0.7415311855993945,
+    0.5860872354676911, 0.4058451513773972, 0.20778495500789848, 0.0,
+];
+const KRONROD_WEIGHTS : [f64; 8] = [
+    0.022935322010529224, 0.06309209262997856, 0.10479001032225019, 0.14065325971552592,
+    0.1690047266392679, 0.19035057806478542, 0.20443294007529889, 0.20948214108472782,
+];
+const GAUSS_WEIGHTS : [f64; 4] = [
+    0.1294849661688697, 0.27970539148927664, 0.3818300505051189, 0.4179591836734694,
+];
+
+/// Value of a definite integral found by `integrate`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Integral {
+    pub value : f64,
+    /// Estimate of how far value is from the integral
+    pub error : f64,
+    /// Whether the estimate got within the tolerance, false for 1/x from 0 to 1 or sin(x) from 0 to infinity
+    pub converged : bool,
+}
+
+/// Integral of f from a to b, either of them can be infinite. It is computed with the Gauss–Kronrod 15 point rule,
+/// splitting the part with the largest error estimate until they add up to less than 1e-10 of the integral of |f|.
+/// Values of f at a and b are not needed, so it can have a pole there like 1/sqrt(x) at 0.
+pub fn integrate(mut f : impl FnMut(f64) -> f64, a : f64, b : f64) -> Integral {
+    if a.is_nan() || b.is_nan() {
+        return Integral { value : f64::NAN, error : f64::NAN, converged : false };
+    }
+    if a == b {
+        return Integral { value : 0.0, error : 0.0, converged : true };
+    }
+    if a > b {
+        let reversed = integrate(f, b, a);
+        return Integral { value : -reversed.value, ..reversed };
+    }
+    // Infinite bounds are brought to a finite interval, x = a + t / (1 - t) from 0 to 1 for [a, inf).
+    // Nodes rounded to the end of it are at infinity, where f of a converging integral vanishes.
+    let mut at = |x : f64, scale : f64| if x.is_finite() { f(x) * scale } else { 0.0 };
+    match (a.is_finite(), b.is_finite()) {
+        (true, true) => adaptive(f, a, b),
+        (true, false) => adaptive(|t : f64| { let s = 1.0 - t; at(a + t / s, 1.0 / (s * s)) }, 0.0, 1.0),
+        (false, true) => adaptive(|t : f64| { let s = 1.0 - t; at(b - t / s, 1.0 / (s * s)) }, 0.0, 1.0),
+        (false, false) => adaptive(|t : f64| { let s = 1.0 - t * t; at(t / s, (1.0 + t * t) / (s * s)) }, -1.0, 1.0),
+    }
+}
+
+// Part of the interval with its integral, error estimate and integral of |f|
+struct Segment {
+    a : f64,
+    b : f64,
+    value : f64,
+    error : f64,
+    magnitude : f64,
+}
+
+fn adaptive(mut f : impl FnMut(f64) -> f64, a : f64, b : f64) -> Integral {
+    let mut segments = vec![kronrod(&mut f, a, b)];
+    loop {
+        let value : f64 = segments.iter().map(|s| s.value).sum();
+        let error : f64 = segments.iter().map(|s| s.error).sum();
+        let magnitude : f64 = segments.iter().map(|s| s.magnitude).sum();
+        if error.is_finite() && error <= RELATIVE_TOLERANCE * magnitude {
+            return Integral { value, error, converged : true };
+        }
+        let worst = (0..segments.len()).max_by(|i, j| segments[*i].error.total_cmp(&segments[*j].error)).unwrap_or(0);
+        let Segment { a, b, .. } = segments[worst];
+        let middle = (a + b) / 2.0;
+        // NaN and infinite values do not get better, neither do parts too short to be split
+        if !error.is_finite() || segments.len() >= MAX_SUBDIVISIONS || middle <= a || middle >= b {
+            return Integral { value, error, converged : false };
+        }
+        segments[worst] = kronrod(&mut f, a, middle);
+        segments.push(kronrod(&mut f, middle, b));
+    }
+}
+
+// The 15 point rule on [a, b], the error is estimated from the difference with the 7 point one as in QUADPACK
+fn kronrod(f : &mut impl FnMut(f64) -> f64, a : f64, b : f64) -> Segment {
+    let (center, half) = ((a + b) / 2.0, (b - a) / 2.0);
+    let fc = f(center);
+    let mut pairs = [(0.0, 0.0); 7];
+    for (pair, node) in pairs.iter_mut().zip(KRONROD_NODES) {
+        *pair = (f(center - half * node), f(center + half * node));
+    }
+    let mut kronrod = fc * KRONROD_WEIGHTS[7];
+    let mut gauss = fc * GAUSS_WEIGHTS[3];
+    let mut magnitude = fc.abs() * KRONROD_WEIGHTS[7];
+    for (i, (left, right)) in pairs.iter().enumerate() {
+        kronrod += KRONROD_WEIGHTS[i] * (left + right);
+        magnitude += KRONROD_WEIGHTS[i] * (left.abs() + right.abs());
+        if i % 2 == 1 { gauss += GAUSS_WEIGHTS[i / 2] * (left + right); }
+    }
+    // How far f strays from its mean, the error can hardly be smaller than a part of it
+    let mean = kronrod / 2.0;
+    let mut spread = KRONROD_WEIGHTS[7] * (fc - mean).abs();
+    for (i, (left, right)) in pairs.iter().enumerate() {
+        spread += KRONROD_WEIGHTS[i] * ((left - mean).abs() + (right - mean).abs());
+    }
+    let (value, magnitude, spread) = (kronrod * half, magnitude * half.abs(), spread * half.abs());
+    let mut error = ((kronrod - gauss) * half).abs();
+    if spread != 0.0 && error != 0.0 {
+        error = spread * (200.0 * error / spread).powf(1.5).min(1.0);
+    }
+    if magnitude > f64::MIN_POSITIVE / (50.0 * f64::EPSILON) {
+        error = error.max(50.0 * f64::EPSILON * magnitude);
+    }
+    Segment { a, b, value, error, magnitude }
+}
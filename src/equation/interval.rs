@@ -0,0 +1,343 @@
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+use std::fmt;
+use std::ops::{Add, Mul};
+
+use super::display::format_number;
+use super::number::{self, Number};
+use super::{Multi, MultiFunction, Simple, SimpleFunction};
+
+/// Closed set of reals holding every value an expression takes while its inputs stay in their intervals.
+/// Bounds may be infinite, the empty interval is where the expression has no real value like `sqrt` of negatives.
+/// Points where only a part of the expression has no value are left out, `sqrt(x) > -1` is 1 on [-1, 1].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub lo : f64,
+    pub hi : f64,
+}
+
+// Bounds around a result rounded to nearest, one ulp further unless it is exact
+fn widen(r : f64, exact : bool) -> (f64, f64) {
+    if exact || r.is_nan() { (r, r) } else { (r.next_down(), r.next_up()) }
+}
+
+// Infinite operands give exact results, otherwise the error of the rounding is computed with fma
+fn add_bounds(a : f64, b : f64) -> (f64, f64) {
+    let s = a + b;
+    let bp = s - a;
+    widen(s, !a.is_finite() || !b.is_finite() || (a - (s - bp)) + (b - bp) == 0.0)
+}
+
+// 0 * inf is 0, the infinite bound is only approached
+fn mul_bounds(a : f64, b : f64) -> (f64, f64) {
+    if a == 0.0 || b == 0.0 { return (0.0, 0.0); }
+    let p = a * b;
+    widen(p, !a.is_finite() || !b.is_finite() || a.mul_add(b, -p) == 0.0)
+}
+
+fn div_bounds(a : f64, b : f64) -> (f64, f64) {
+    let q = a / b;
+    widen(q, !a.is_finite() || !b.is_finite() || (-q).mul_add(b, a) == 0.0)
+}
+
+// Whether some point + k * period lies in [lo, hi], answers yes when rounding makes it unsure
+fn reaches(lo : f64, hi : f64, point : f64, period : f64) -> bool {
+    let k = ((lo - point) / period).ceil();
+    let margin = 4.0 * f64::EPSILON * (lo.abs().max(hi.abs()) + period);
+    [k - 1.0, k].iter().any(|k| {
+        let candidate = point + k * period;
+        lo - margin <= candidate && candidate <= hi + margin
+    })
+}
+
+impl Interval {
+    pub const EMPTY : Interval = Interval { lo : f64::NAN, hi : f64::NAN };
+    pub const ENTIRE : Interval = Interval { lo : f64::NEG_INFINITY, hi : f64::INFINITY };
+
+    /// Interval between the two bounds in any order, NaN bounds give the empty interval
+    pub fn new(a : f64, b : f64) -> Interval {
+        if a.is_nan() || b.is_nan() { return Interval::EMPTY; }
+        Interval { lo : a.min(b), hi : a.max(b) }
+    }
+
+    pub fn point(c : f64) -> Interval {
+        Interval::new(c, c)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lo.is_nan()
+    }
+
+    pub fn contains(&self, c : f64) -> bool {
+        self.lo <= c && c <= self.hi
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.lo.is_finite() && self.hi.is_finite()
+    }
+
+    pub fn width(&self) -> f64 {
+        self.hi - self.lo
+    }
+
+    /// Smallest interval holding both
+    pub fn hull(&self, other : &Interval) -> Interval {
+        match (self.is_empty(), other.is_empty()) {
+            (true, _) => *other,
+            (_, true) => *self,
+            _ => Interval { lo : self.lo.min(other.lo), hi : self.hi.max(other.hi) }
+        }
+    }
+
+    // Part where a function defined on [lo, hi] has values
+    fn clamp(&self, lo : f64, hi : f64) -> Interval {
+        if self.is_empty() || self.hi < lo || self.lo > hi { return Interval::EMPTY; }
+        Interval { lo : self.lo.max(lo), hi : self.hi.min(hi) }
+    }
+
+    fn neg(self) -> Interval {
+        Interval { lo : -self.hi, hi : -self.lo }
+    }
+
+    // Smallest lower bound and largest upper bound of the candidates, NaN ones are limits like inf / inf
+    fn from_bounds(bounds : &[(f64, f64)]) -> Interval {
+        let lo = bounds.iter().map(|b| b.0).filter(|b| !b.is_nan()).fold(f64::INFINITY, f64::min);
+        let hi = bounds.iter().map(|b| b.1).filter(|b| !b.is_nan()).fold(f64::NEG_INFINITY, f64::max);
+        if lo > hi { Interval::ENTIRE } else { Interval { lo, hi } }
+    }
+
+    fn sub(self, other : Interval) -> Interval {
+        self + other.neg()
+    }
+
+    fn div(self, other : Interval) -> Interval {
+        if self.is_empty() || other.is_empty() { return Interval::EMPTY; }
+        // Infinite with the sign of the zero, which is not kept
+        if other == Interval::point(0.0) { return Interval::ENTIRE; }
+        if self == Interval::point(0.0) { return self; }
+        if other.lo > 0.0 || other.hi < 0.0 {
+            let (a, b) = (self, other);
+            return Interval::from_bounds(&[div_bounds(a.lo, b.lo), div_bounds(a.lo, b.hi), div_bounds(a.hi, b.lo), div_bounds(a.hi, b.hi)]);
+        }
+        // The divisor reaches 0, the quotient goes to infinity on the sides it is approached from
+        match (self.lo >= 0.0, self.hi <= 0.0, other.lo == 0.0, other.hi == 0.0) {
+            (true, _, true, _) => Interval { lo : div_bounds(self.lo, other.hi).0, hi : f64::INFINITY },
+            (true, _, _, true) => Interval { lo : f64::NEG_INFINITY, hi : div_bounds(self.lo, other.lo).1 },
+            (_, true, true, _) => Interval { lo : f64::NEG_INFINITY, hi : div_bounds(self.hi, other.hi).1 },
+            (_, true, _, true) => Interval { lo : div_bounds(self.hi, other.lo).0, hi : f64::INFINITY },
+            _ => Interval::ENTIRE
+        }
+    }
+
+    // Monotonic function computed to nearest, widened by an ulp on each side
+    fn increasing(self, f : fn(f64) -> f64) -> Interval {
+        if self.is_empty() { return self; }
+        Interval::new(widen(f(self.lo), false).0, widen(f(self.hi), false).1)
+    }
+
+    fn decreasing(self, f : fn(f64) -> f64) -> Interval {
+        if self.is_empty() { return self; }
+        Interval::new(widen(f(self.hi), false).0, widen(f(self.lo), false).1)
+    }
+
+    // Decreasing then increasing around 0 like abs, x² or cosh
+    fn even(self, f : fn(f64) -> f64) -> Interval {
+        if self.is_empty() { return self; }
+        let low = if self.contains(0.0) { f(0.0) } else { widen(f(self.lo).min(f(self.hi)), false).0 };
+        Interval::new(low, widen(f(self.lo).max(f(self.hi)), false).1)
+    }
+
+    // Periodic function between -1 and 1 with its maximum at `max` and its minimum half a period later
+    fn wave(self, f : fn(f64) -> f64, max : f64) -> Interval {
+        if self.is_empty() { return self; }
+        if !self.is_bounded() || self.width() >= TAU { return Interval::new(-1.0, 1.0); }
+        let (a, b) = (f(self.lo), f(self.hi));
+        let lo = if reaches(self.lo, self.hi, max + PI, TAU) { -1.0 } else { widen(a.min(b), false).0.max(-1.0) };
+        let hi = if reaches(self.lo, self.hi, max, TAU) { 1.0 } else { widen(a.max(b), false).1.min(1.0) };
+        Interval::new(lo, hi)
+    }
+
+    fn tan(self) -> Interval {
+        if self.is_empty() { return self; }
+        if !self.is_bounded() || self.width() >= PI || reaches(self.lo, self.hi, FRAC_PI_2, PI) { return Interval::ENTIRE; }
+        self.increasing(f64::tan)
+    }
+
+    // sqrt is correctly rounded, its result is exact when squaring it gives back x
+    fn sqrt(self) -> Interval {
+        if self.is_empty() { return self; }
+        let bounds = |x : f64| { let r = x.sqrt(); widen(r, !x.is_finite() || r.mul_add(-r, x) == 0.0) };
+        Interval::new(bounds(self.lo).0.max(0.0), bounds(self.hi).1)
+    }
+
+    // Odd powers are increasing, even ones follow |x|. powi is off by more than an ulp for large n.
+    fn powi(self, n : i32) -> Interval {
+        if self.is_empty() { return self; }
+        let error = |r : f64| if r.is_finite() { 2.0 * n.unsigned_abs() as f64 * f64::EPSILON * r.abs() } else { 0.0 };
+        let exact = |x : f64| x == 0.0 || x.abs() == 1.0 || x.is_infinite();
+        // Squares are a single product so their rounding is known
+        let down = |x : f64| if n == 2 { mul_bounds(x, x).0 } else { let r = x.powi(n); widen(r - error(r), exact(x)).0 };
+        let up = |x : f64| if n == 2 { mul_bounds(x, x).1 } else { let r = x.powi(n); widen(r + error(r), exact(x)).1 };
+        match n {
+            0 => Interval::point(1.0),
+            _ if n < 0 => Interval::point(1.0).div(self.powi(-n)),
+            _ if n % 2 == 0 => {
+                let (near, far) = (self.lo.abs().min(self.hi.abs()), self.lo.abs().max(self.hi.abs()));
+                Interval::new(if self.contains(0.0) { 0.0 } else { down(near).max(0.0) }, up(far))
+            },
+            _ => Interval::new(down(self.lo), up(self.hi))
+        }
+    }
+
+    fn pow(self, exponent : Interval) -> Interval {
+        // x^0 and 1^y are 1 even for NaN like powf
+        if exponent == Interval::point(0.0) || self == Interval::point(1.0) { return Interval::point(1.0); }
+        if self.is_empty() || exponent.is_empty() { return Interval::EMPTY; }
+        if exponent.lo == exponent.hi && exponent.lo.fract() == 0.0 && exponent.lo.abs() <= i32::MAX as f64 {
+            return self.powi(exponent.lo as i32);
+        }
+        // Negative bases only have values at integer exponents
+        if self.lo < 0.0 && exponent.lo.ceil() <= exponent.hi { return Interval::ENTIRE; }
+        let base = self.clamp(0.0, f64::INFINITY);
+        if base.is_empty() { return base; }
+        (exponent * base.increasing(f64::ln)).increasing(f64::exp).clamp(0.0, f64::INFINITY)
+    }
+
+    fn comparison(self, other : Interval, name : Multi) -> Interval {
+        let (certain, possible) = match name {
+            Multi::Less => (self.hi < other.lo, self.lo < other.hi),
+            Multi::LessEqual => (self.hi <= other.lo, self.lo <= other.hi),
+            Multi::Greater => (self.lo > other.hi, self.hi > other.lo),
+            Multi::GreaterEqual => (self.lo >= other.hi, self.hi >= other.lo),
+            Multi::Equal => (self.lo == self.hi && self == other, self.lo <= other.hi && other.lo <= self.hi),
+            Multi::NotEqual => (self.hi < other.lo || self.lo > other.hi, !(self.lo == self.hi && self == other)),
+            // Not a comparison, it can be anything
+            Multi::Add | Multi::Sub | Multi::Mul | Multi::Div | Multi::Pow | Multi::PowCall | Multi::Max | Multi::Min => (false, true),
+        };
+        Interval::new(if certain { 1.0 } else { 0.0 }, if possible { 1.0 } else { 0.0 })
+    }
+
+    // Interval versions of the functions built in
+    fn function(self, name : Simple) -> Option<Interval> {
+        Some(match name {
+            Simple::Plus | Simple::Re | Simple::Conj => self,
+            Simple::Minus => self.neg(),
+            Simple::Square => self.powi(2),
+            Simple::Factorial => self.clamp(0.0, f64::INFINITY).increasing(number::factorial),
+            Simple::Sin => self.wave(f64::sin, FRAC_PI_2),
+            Simple::Cos => self.wave(f64::cos, 0.0),
+            Simple::Tan => self.tan(),
+            Simple::Cot => Interval::point(1.0).div(self.tan()),
+            Simple::Sec => Interval::point(1.0).div(self.wave(f64::cos, 0.0)),
+            Simple::Csc => Interval::point(1.0).div(self.wave(f64::sin, FRAC_PI_2)),
+            Simple::Abs => self.even(f64::abs),
+            Simple::Ceil => Interval::new(self.lo.ceil(), self.hi.ceil()),
+            Simple::Floor => Interval::new(self.lo.floor(), self.hi.floor()),
+            Simple::Round => Interval::new(self.lo.round(), self.hi.round()),
+            Simple::Exp => self.increasing(f64::exp).clamp(0.0, f64::INFINITY),
+            Simple::Ln => self.clamp(0.0, f64::INFINITY).increasing(f64::ln),
+            Simple::Log => self.clamp(0.0, f64::INFINITY).increasing(f64::log10),
+            Simple::Sqrt => self.clamp(0.0, f64::INFINITY).sqrt(),
+            Simple::Asin => self.clamp(-1.0, 1.0).increasing(f64::asin),
+            Simple::Acos => self.clamp(-1.0, 1.0).decreasing(f64::acos),
+            Simple::Atan => self.increasing(f64::atan),
+            Simple::Sinh => self.increasing(f64::sinh),
+            Simple::Cosh => self.even(f64::cosh),
+            Simple::Tanh => self.increasing(f64::tanh).clamp(-1.0, 1.0),
+            Simple::Asinh => self.increasing(f64::asinh),
+            Simple::Acosh => self.clamp(1.0, f64::INFINITY).increasing(f64::acosh),
+            Simple::Atanh => self.clamp(-1.0, 1.0).increasing(f64::atanh),
+            Simple::Im | Simple::Arg if self.is_empty() => self,
+            Simple::Im => Interval::point(0.0),
+            Simple::Arg => Interval::new(if self.hi >= 0.0 { 0.0 } else { PI }, if self.lo < 0.0 { PI } else { 0.0 }),
+            // Functions given from outside, nothing is known about them
+            Simple::External => return None
+        })
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() { return f.write_str("[]"); }
+        write!(f, "[{}, {}]", format_number(self.lo), format_number(self.hi))
+    }
+}
+
+impl Add for Interval {
+    type Output = Interval;
+    fn add(self, other : Interval) -> Interval {
+        if self.is_empty() || other.is_empty() { return Interval::EMPTY; }
+        let (lo, hi) = (add_bounds(self.lo, other.lo).0, add_bounds(self.hi, other.hi).1);
+        // inf - inf, the bound is only approached
+        Interval { lo : if lo.is_nan() { f64::NEG_INFINITY } else { lo }, hi : if hi.is_nan() { f64::INFINITY } else { hi } }
+    }
+}
+
+impl Mul for Interval {
+    type Output = Interval;
+    fn mul(self, other : Interval) -> Interval {
+        if self.is_empty() || other.is_empty() { return Interval::EMPTY; }
+        let (a, b) = (self, other);
+        Interval::from_bounds(&[mul_bounds(a.lo, b.lo), mul_bounds(a.lo, b.hi), mul_bounds(a.hi, b.lo), mul_bounds(a.hi, b.hi)])
+    }
+}
+
+// Functions given from outside only have a value on points
+impl Number for Interval {
+    fn from_f64(c : f64) -> Interval {
+        Interval::point(c)
+    }
+
+    fn to_f64(&self) -> f64 {
+        if self.lo == self.hi { self.lo } else { f64::NAN }
+    }
+
+    fn to_bound(&self) -> Option<i64> {
+        let (lo, hi) = (self.lo.round() as i32 as i64, self.hi.round() as i32 as i64);
+        (lo == hi).then_some(lo)
+    }
+
+    fn is_true(&self) -> Option<bool> {
+        match (self.lo > 0.0, self.hi > 0.0) {
+            (true, _) => Some(true),
+            (_, false) => Some(false),
+            _ if self.is_empty() => Some(false),
+            _ => None
+        }
+    }
+
+    fn union(self, other : Interval) -> Interval {
+        self.hull(&other)
+    }
+
+    // Computed in double precision without any bound on the error
+    fn from_numeric(_ : f64, _ : &Interval, _ : impl FnMut(f64) -> f64, _ : bool) -> Interval {
+        Interval::ENTIRE
+    }
+
+    fn simple(name : Simple, f : SimpleFunction, x : Interval) -> Interval {
+        match x.function(name) {
+            Some(res) => res,
+            None if x.lo == x.hi => Interval::point(f64::simple(name, f, x.lo)),
+            None if x.is_empty() => x,
+            None => Interval::ENTIRE
+        }
+    }
+
+    fn multi(name : Multi, f : MultiFunction, x : &[Interval]) -> Interval {
+        match (name, x) {
+            (Multi::Add, [a, b]) => *a + *b,
+            (Multi::Sub, [a, b]) => a.sub(*b),
+            (Multi::Mul, [a, b]) => *a * *b,
+            (Multi::Div, [a, b]) => a.div(*b),
+            (Multi::Pow | Multi::PowCall, [a, b]) => a.pow(*b),
+            // Like NaN compared in f64
+            (Multi::NotEqual, [a, b]) if a.is_empty() || b.is_empty() => Interval::point(1.0),
+            (_, [a, b]) if a.is_empty() || b.is_empty() => Interval::point(0.0),
+            (Multi::Equal | Multi::NotEqual | Multi::LessEqual | Multi::Less | Multi::GreaterEqual | Multi::Greater, [a, b]) => a.comparison(*b, name),
+            (Multi::Max, _) => x.iter().fold(Interval::point(f64::MIN), |max, next| Interval::new(max.lo.max(next.lo), max.hi.max(next.hi))),
+            (Multi::Min, _) => x.iter().fold(Interval::point(f64::MAX), |min, next| Interval::new(min.lo.min(next.lo), min.hi.min(next.hi))),
+            _ if x.iter().all(|v| v.lo == v.hi) => Interval::point(f64::multi(name, f, &x.iter().map(|v| v.lo).collect::<Vec<_>>())),
+            _ => Interval::ENTIRE
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::f64;
+
+use super::{Expression, Function, Multi, Numeric, Operator, Simple};
+
+pub(super) const GREEK : [&str; 22] = [
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa", "lambda",
+    "mu", "nu", "xi", "rho", "sigma", "tau", "upsilon", "phi", "chi", "psi", "omega",
+];
+
+impl Expression {
+    /// LaTeX source of the expression, fractions, roots, powers, sums and if become their usual notation
+    pub fn to_latex(&self) -> String {
+        let mut out = String::new();
+        self.write_latex(&mut out);
+        out
+    }
+
+    fn write_latex(&self, out : &mut String) {
+        match (self.as_operator(), &self.params[..]) {
+            (Some(Operator::Div), [a, b]) => {
+                out.push_str("\\frac{");
+                a.write_latex(out);
+                out.push_str("}{");
+                b.write_latex(out);
+                out.push('}');
+            },
+            (Some(Operator::Pow), [a, b]) => write_power(out, a, b),
+            (Some(Operator::Square), [a]) => write_power(out, a, &Expression::constant(2.0, self.span)),
+            (Some(o @ (Operator::UAdd | Operator::USub)), [a]) => {
+                out.push_str(o.to_string());
+                // -(-x) is not written --x
+                a.write_operand(out, a.latex_precedence() < o.precedence() || a.is_negative());
+            },
+            (Some(o @ Operator::Factorial), [a]) => {
+                // x^{2}! reads as a factorial of the power
+                a.write_operand(out, a.latex_precedence() < o.precedence() || matches!(a.as_operator(), Some(Operator::Div | Operator::Pow | Operator::Square)));
+                out.push('!');
+            },
+            (Some(o), [a, b]) => {
+                a.write_operand(out, a.latex_precedence() < o.precedence());
+                out.push_str(&match o {
+                    Operator::Mul => " \\cdot ".to_string(),
+                    Operator::Assign => " := ".to_string(),
+                    Operator::TestEQU => " = ".to_string(),
+                    Operator::TestNEQ => " \\neq ".to_string(),
+                    Operator::TestLEQ => " \\leq ".to_string(),
+                    Operator::TestGEQ => " \\geq ".to_string(),
+                    // +, -, < and >
+                    _ => format!(" {} ", o.to_string())
+                });
+                let parens = if b.is_prefix() { b.precedence() < o.precedence() } else { b.latex_precedence() <= o.precedence() };
+                b.write_operand(out, parens);
+            },
+            _ => match (&self.function, &self.params[..]) {
+                (Function::Constant(c), _) => write_number(out, *c),
+                (Function::InputX, _) => out.push('x'),
+                (Function::Variable(name), _) => write_name(out, name, false),
+                (Function::SimpleFunction(Simple::Sqrt, _), [a]) => {
+                    out.push_str("\\sqrt{");
+                    a.write_latex(out);
+                    out.push('}');
+                },
+                (Function::SimpleFunction(name @ (Simple::Abs | Simple::Ceil | Simple::Floor), _), [a]) => {
+                    let (open, close) = match name { Simple::Abs => ("|", "|"), Simple::Ceil => ("\\lceil ", "\\rceil"), _ => ("\\lfloor ", "\\rfloor") };
+                    out.push_str("\\left");
+                    out.push_str(open);
+                    a.write_latex(out);
+                    out.push_str("\\right");
+                    out.push_str(close);
+                },
+                (Function::MultiFunction(Multi::PowCall, _), [a, b]) => write_power(out, a, b),
+                // Made by create_from_function, evaluated at x
+                (Function::SimpleFunction(name, _), []) => {
+                    write_function_name(out, name.name());
+                    out.push_str("\\left(x\\right)");
+                },
+                (Function::SimpleFunction(name, _), params) => {
+                    write_function_name(out, name.name());
+                    write_arguments(out, params);
+                },
+                (Function::MultiFunction(name, _), params) => {
+                    write_function_name(out, name.name());
+                    write_arguments(out, params);
+                },
+                (Function::Call(name), params) => {
+                    write_name(out, name, true);
+                    write_arguments(out, params);
+                },
+                (Function::Iterator(o), [start, end, body]) => {
+                    out.push_str(if *o == Operator::Add { "\\sum_{" } else { "\\prod_{" });
+                    match (&start.function, &start.params[..]) {
+                        (Function::Assign, [iterator, first]) => {
+                            iterator.write_latex(out);
+                            out.push('=');
+                            first.write_latex(out);
+                        },
+                        _ => start.write_latex(out)
+                    }
+                    out.push_str("}^{");
+                    end.write_latex(out);
+                    out.push_str("} ");
+                    body.write_operand(out, body.latex_precedence() < Operator::Mul.precedence());
+                },
+                (Function::If, [condition, if_true, if_false]) => {
+                    out.push_str("\\begin{cases} ");
+                    if_true.write_latex(out);
+                    out.push_str(" & \\text{if } ");
+                    condition.write_latex(out);
+                    out.push_str(" \\\\ ");
+                    if_false.write_latex(out);
+                    out.push_str(" & \\text{otherwise} \\end{cases}");
+                },
+                // The equation reads back as a test of equality, which solve takes too
+                (Function::Numeric(Numeric::Solve), [equation, params @ ..]) if matches!(equation.function, Function::Assign) => {
+                    let equation = Expression::operator(Operator::TestEQU, equation.params.clone(), equation.span);
+                    write_function_name(out, "solve");
+                    write_arguments(out, &[&[equation], params].concat());
+                },
+                (Function::Numeric(method), params) => {
+                    write_function_name(out, method.name());
+                    write_arguments(out, params);
+                },
+                // Malformed nodes
+                (Function::Iterator(o), params) => {
+                    write_function_name(out, if *o == Operator::Add { "sum" } else { "prod" });
+                    write_arguments(out, params);
+                },
+                (Function::If, params) => {
+                    write_function_name(out, "if");
+                    write_arguments(out, params);
+                },
+                (Function::Assign, params) => write_arguments(out, params),
+            }
+        }
+    }
+
+    fn write_operand(&self, out : &mut String, parens : bool) {
+        if parens { out.push_str("\\left("); }
+        self.write_latex(out);
+        if parens { out.push_str("\\right)"); }
+    }
+
+    // Written with a leading minus sign
+    fn is_negative(&self) -> bool {
+        self.as_operator() == Some(Operator::USub) || self.value().is_some_and(|c| c.is_sign_negative() && !c.is_nan())
+    }
+
+    // Fractions hold together, sums only take what follows up to the next + or -
+    fn latex_precedence(&self) -> u8 {
+        match (self.as_operator(), &self.function) {
+            (Some(Operator::Div), _) => u8::MAX,
+            (None, Function::Iterator(_)) => Operator::Add.precedence(),
+            _ => self.precedence()
+        }
+    }
+}
+
+fn write_power(out : &mut String, base : &Expression, exponent : &Expression) {
+    // Only names, numbers and calls go without parenthesis, fractions included since x^{2}^{3} does not compile
+    let parens = base.precedence() < u8::MAX || base.latex_precedence() < u8::MAX || matches!(base.function, Function::If);
+    base.write_operand(out, parens);
+    out.push_str("^{");
+    exponent.write_latex(out);
+    out.push('}');
+}
+
+fn write_arguments(out : &mut String, params : &[Expression]) {
+    out.push_str("\\left(");
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 { out.push_str(", "); }
+        param.write_latex(out);
+    }
+    out.push_str("\\right)");
+}
+
+fn write_function_name(out : &mut String, name : &str) {
+    match name {
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "exp" | "ln" | "log" | "sinh" | "cosh" | "tanh" | "max" | "min" | "arg" => {
+            out.push('\\');
+            out.push_str(name);
+        },
+        "asin" | "acos" | "atan" => {
+            out.push_str("\\arc");
+            out.push_str(&name[1..]);
+        },
+        _ => write_name(out, name, true)
+    }
+}
+
+// Single letters are italic, greek letters get their symbol and longer names are upright
+fn write_name(out : &mut String, name : &str, is_function : bool) {
+    if name.chars().count() == 1 {
+        out.push_str(name);
+    } else if GREEK.contains(&name) {
+        out.push('\\');
+        out.push_str(name);
+    } else {
+        out.push_str(if is_function { "\\operatorname{" } else { "\\mathrm{" });
+        out.push_str(&name.replace('_', "\\_"));
+        out.push('}');
+    }
+}
+
+fn write_number(out : &mut String, c : f64) {
+    if c.is_sign_negative() && !c.is_nan() {
+        out.push('-');
+        return write_number(out, -c);
+    }
+    if c == f64::consts::PI {
+        out.push_str("\\pi");
+    } else if c.is_nan() {
+        out.push_str("\\mathrm{NaN}");
+    } else if c.is_infinite() {
+        out.push_str("\\infty");
+    } else if c != 0.0 && !(1e-4..1e16).contains(&c) {
+        // Scientific notation like 1.5e-7 is written 1.5 \cdot 10^{-7}
+        let text = format!("{:e}", c);
+        let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+        if mantissa != "1" {
+            out.push_str(mantissa);
+            out.push_str(" \\cdot ");
+        }
+        out.push_str("10^{");
+        out.push_str(exponent);
+        out.push('}');
+    } else if c == f64::consts::E {
+        out.push('e');
+    } else {
+        out.push_str(&c.to_string());
+    }
+}
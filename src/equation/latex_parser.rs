@@ -0,0 +1,535 @@
+use super::latex::GREEK;
+use super::{builtin_from_string, is_valid_name, sign_operator, Operator, ParseError, Signatures, Span, Token, MAX_DEPTH};
+
+// Commands read like the function of the same name
+const FUNCTIONS : [(&str, &str); 20] = [
+    ("sin", "sin"), ("cos", "cos"), ("tan", "tan"), ("cot", "cot"), ("sec", "sec"), ("csc", "csc"),
+    ("arcsin", "asin"), ("arccos", "acos"), ("arctan", "atan"), ("sinh", "sinh"), ("cosh", "cosh"), ("tanh", "tanh"),
+    ("exp", "exp"), ("ln", "ln"), ("log", "log"), ("max", "max"), ("min", "min"), ("arg", "arg"), ("Re", "re"), ("Im", "im"),
+];
+
+const SPACES : [&str; 7] = [",", ";", ":", "!", " ", "quad", "qquad"];
+
+// Commands starting an operand, a function argument like \sin 2x goes on over them
+const OPERANDS : [&str; 18] = [
+    "frac", "dfrac", "tfrac", "sqrt", "left", "lfloor", "lceil", "{", "pi", "infty", "operatorname", "mathrm", "mathit", "text", "mbox",
+    "sum", "prod", "begin",
+];
+
+/// Tokens of a LaTeX formula like `\frac{1}{2}\rho v^{2}`, the same the plain syntax gives so the parser does the rest.
+/// Letters are single variables, `v_0` or `x_{max}` and `\mathrm{name}` give longer names.
+pub(super) fn split_latex(s : &str, signatures : &Signatures) -> Result<Vec<Token>, ParseError> {
+    let mut lexer = Lexer { chars : s.chars().collect(), index : 0, tokens : vec![], signatures, depth : 0 };
+    while lexer.peek().is_some() {
+        lexer.item()?;
+    }
+    Ok(lexer.tokens)
+}
+
+struct Lexer<'a> {
+    chars : Vec<char>,
+    index : usize,
+    tokens : Vec<Token>,
+    signatures : &'a Signatures,
+    // Items being lexed inside each other
+    depth : usize,
+}
+
+// What follows an operand, tells where the argument of \sin x or the body of a sum stops
+#[derive(PartialEq)]
+enum Next {
+    Operand,
+    Continue,
+    Stop
+}
+
+impl Lexer<'_> {
+    // Next char after the spaces
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.index).is_some_and(|c| c.is_whitespace()) { self.index += 1; }
+        self.chars.get(self.index).copied()
+    }
+
+    // Name of the command at the current position without consuming it, like frac for \frac or , for \,
+    fn peek_command(&mut self) -> Option<String> {
+        if self.peek() != Some('\\') { return None; }
+        let start = self.index + 1;
+        let mut end = start;
+        while self.chars.get(end).is_some_and(char::is_ascii_alphabetic) { end += 1; }
+        if end == start && start < self.chars.len() { end += 1; }
+        Some(self.chars[start..end].iter().collect())
+    }
+
+    fn command(&mut self) -> Option<(String, Span)> {
+        let name = self.peek_command()?;
+        let start = self.index;
+        self.index += 1 + name.chars().count();
+        Some((name, Span::new(start, self.index)))
+    }
+
+    fn push(&mut self, o : Operator, span : Span) {
+        self.tokens.push(Token::Operator(o, span));
+    }
+
+    fn is_callable(&self, s : &str) -> bool {
+        self.signatures.contains_key(s) || builtin_from_string(s).is_some_and(|builtin| builtin.max_arity > 0)
+    }
+
+    // Juxtaposed operands are multiplied, parenthesis after a constant like e(x + 1) included
+    fn start_operand(&mut self, span : Span) {
+        let ends_operand = match self.tokens.last() {
+            Some(Token::Identifier(s, _)) => !self.is_callable(s),
+            Some(Token::Operator(o, _)) => matches!(o, Operator::ParensClose | Operator::Factorial | Operator::Square),
+            None => false
+        };
+        if ends_operand { self.push(Operator::Mul, Span::new(span.start, span.start)); }
+    }
+
+    fn identifier(&mut self, name : String, span : Span) {
+        self.start_operand(span);
+        self.tokens.push(Token::Identifier(name, span));
+    }
+
+    fn item(&mut self) -> Result<(), ParseError> {
+        if self.depth >= MAX_DEPTH { return Err(ParseError::NestingTooDeep(Span::new(self.index, self.index + 1))); }
+        self.depth += 1;
+        let res = self.lex_item();
+        self.depth -= 1;
+        res
+    }
+
+    // Lexes one operand, operator or group
+    fn lex_item(&mut self) -> Result<(), ParseError> {
+        let Some(c) = self.peek() else { return Ok(()); };
+        let start = self.index;
+        let span = Span::new(start, start + 1);
+        match c {
+            '\\' => self.command_item(),
+            '{' | '(' | '[' => {
+                self.index += 1;
+                self.group(c, span, None)
+            },
+            '|' => {
+                self.index += 1;
+                self.group(c, span, Some("abs"))
+            },
+            '}' | ')' | ']' => Err(ParseError::UnbalancedParen(c, span)),
+            _ if c.is_ascii_digit() || (c == '.' && self.chars.get(start + 1).is_some_and(char::is_ascii_digit)) => {
+                let mut end = start;
+                while self.chars.get(end).is_some_and(char::is_ascii_digit) { end += 1; }
+                if self.chars.get(end) == Some(&'.') && self.chars.get(end + 1).is_some_and(char::is_ascii_digit) {
+                    end += 1;
+                    while self.chars.get(end).is_some_and(char::is_ascii_digit) { end += 1; }
+                }
+                self.index = end;
+                self.identifier(self.chars[start..end].iter().collect(), Span::new(start, end));
+                Ok(())
+            },
+            _ if c.is_alphabetic() => {
+                self.index += 1;
+                let name = self.with_subscript(c.to_string())?;
+                self.identifier(name, Span::new(start, self.index));
+                Ok(())
+            },
+            '^' => {
+                self.index += 1;
+                self.push(Operator::Pow, span);
+                self.argument(span)
+            },
+            // = compares like in the printed LaTeX, := assigns
+            ':' if self.chars.get(start + 1) == Some(&'=') => {
+                self.index += 2;
+                self.push(Operator::Assign, Span::new(start, start + 2));
+                Ok(())
+            },
+            '=' if self.chars.get(start + 1) != Some(&'=') => {
+                self.index += 1;
+                self.push(Operator::TestEQU, span);
+                Ok(())
+            },
+            _ => match Operator::from_char(c, self.chars.get(start + 1)) {
+                Some((o @ (Operator::Add | Operator::Sub), n)) => {
+                    self.index += n;
+                    self.push(sign_operator(o, self.tokens.last()), Span::new(start, start + n));
+                    Ok(())
+                },
+                Some((o, n)) if !matches!(o, Operator::ParensOpen | Operator::ParensClose | Operator::Space) => {
+                    self.index += n;
+                    self.push(o, Span::new(start, start + n));
+                    Ok(())
+                },
+                _ => Err(ParseError::UnexpectedOperator(c.to_string(), span))
+            }
+        }
+    }
+
+    fn command_item(&mut self) -> Result<(), ParseError> {
+        let Some((name, span)) = self.command() else { return Ok(()); };
+        let operator = match name.as_str() {
+            "cdot" | "times" | "ast" => Some(Operator::Mul),
+            "div" => Some(Operator::Div),
+            "le" | "leq" | "leqslant" => Some(Operator::TestLEQ),
+            "ge" | "geq" | "geqslant" => Some(Operator::TestGEQ),
+            "ne" | "neq" => Some(Operator::TestNEQ),
+            "lt" => Some(Operator::TestLSS),
+            "gt" => Some(Operator::TestGTR),
+            _ => None
+        };
+        if let Some(o) = operator {
+            self.push(o, span);
+            return Ok(());
+        }
+        match name.as_str() {
+            _ if SPACES.contains(&name.as_str()) => Ok(()),
+            "frac" | "dfrac" | "tfrac" => {
+                self.start_operand(span);
+                self.push(Operator::ParensOpen, span);
+                self.argument(span)?;
+                self.push(Operator::Div, span);
+                self.argument(span)?;
+                self.push(Operator::ParensClose, span);
+                Ok(())
+            },
+            "sqrt" if self.peek() == Some('[') => {
+                // \sqrt[n]{x} is x^(1/n)
+                let open = Span::new(self.index, self.index + 1);
+                self.index += 1;
+                let start = self.tokens.len();
+                let close = self.contents('[', open)?;
+                let degree = self.tokens.split_off(start);
+                self.start_operand(span);
+                self.push(Operator::ParensOpen, span);
+                self.argument(span)?;
+                self.push(Operator::Pow, span);
+                self.push(Operator::ParensOpen, open);
+                self.tokens.push(Token::Identifier("1".to_string(), span));
+                self.push(Operator::Div, open);
+                self.push(Operator::ParensOpen, open);
+                self.tokens.extend(degree);
+                self.push(Operator::ParensClose, close);
+                self.push(Operator::ParensClose, close);
+                self.push(Operator::ParensClose, span);
+                Ok(())
+            },
+            "sqrt" => {
+                self.identifier(name, span);
+                self.argument(span)
+            },
+            "left" => {
+                let delimiter = self.delimiter(span)?;
+                match delimiter.as_str() {
+                    "(" | "[" | "." | "{" => self.group('(', span, None),
+                    "|" => self.group('|', span, Some("abs")),
+                    "lfloor" => self.group('⌊', span, Some("floor")),
+                    "lceil" => self.group('⌈', span, Some("ceil")),
+                    _ => Err(ParseError::UnexpectedOperator(delimiter, span))
+                }
+            },
+            "{" => self.group('(', span, None),
+            "lfloor" => self.group('⌊', span, Some("floor")),
+            "lceil" => self.group('⌈', span, Some("ceil")),
+            "right" | "}" | "rfloor" | "rceil" => Err(ParseError::UnbalancedParen(')', span)),
+            "pi" | "infty" => {
+                self.identifier(if name == "pi" { name } else { "inf".to_string() }, span);
+                Ok(())
+            },
+            "operatorname" | "mathrm" | "mathit" | "text" | "mbox" => {
+                let text = self.raw_group(span)?;
+                let text = text.trim();
+                if !is_valid_name(text) && text.parse::<f32>().is_err() { return Err(ParseError::UnknownIdentifier(text.to_string(), span)); }
+                if self.is_callable(text) { return self.function(text.to_string(), span); }
+                let name = self.with_subscript(text.to_string())?;
+                self.identifier(name, Span::new(span.start, self.index));
+                Ok(())
+            },
+            "sum" | "prod" => self.iterator(name, span),
+            "begin" => self.cases(span),
+            _ if GREEK.contains(&name.as_str()) => {
+                let name = self.with_subscript(name)?;
+                self.identifier(name, Span::new(span.start, self.index));
+                Ok(())
+            },
+            _ => match FUNCTIONS.iter().find(|(command, _)| *command == name) {
+                Some((_, function)) => self.function(function.to_string(), span),
+                None if name.chars().all(|c| c.is_ascii_alphabetic()) => Err(ParseError::UnknownIdentifier(format!("\\{}", name), span)),
+                None => Err(ParseError::UnexpectedOperator(format!("\\{}", name), span))
+            }
+        }
+    }
+
+    // Contents up to the closing delimiter, `function` is called on them like abs for |x|
+    fn group(&mut self, open : char, span : Span, function : Option<&str>) -> Result<(), ParseError> {
+        match function {
+            Some(function) => self.identifier(function.to_string(), span),
+            None => self.start_operand(span)
+        }
+        self.push(Operator::ParensOpen, span);
+        let close = self.contents(open, span)?;
+        self.push(Operator::ParensClose, close);
+        Ok(())
+    }
+
+    // Lexes up to the delimiter closing the group opened at open_span, which is consumed
+    fn contents(&mut self, open : char, open_span : Span) -> Result<Span, ParseError> {
+        loop {
+            if let Some(close) = self.closer(open) { return Ok(close); }
+            if self.peek().is_none() { return Err(ParseError::UnbalancedParen(open, open_span)); }
+            self.item()?;
+        }
+    }
+
+    fn closer(&mut self, open : char) -> Option<Span> {
+        let start = self.index;
+        match (open, self.peek()?) {
+            ('{', '}') | ('(', ')') | ('[', ']') | ('|', '|') => {
+                self.index += 1;
+                Some(Span::new(start, self.index))
+            },
+            (_, '\\') => match self.peek_command()?.as_str() {
+                "right" => {
+                    self.command();
+                    // The delimiter, \right. included
+                    if self.peek_command().is_some() { self.command(); } else if self.peek().is_some() { self.index += 1; }
+                    Some(Span::new(start, self.index))
+                },
+                "}" | "rfloor" | "rceil" => self.command().map(|(_, span)| span),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    // Delimiter following \left, a char or a command name like lfloor
+    fn delimiter(&mut self, span : Span) -> Result<String, ParseError> {
+        match self.peek() {
+            Some('\\') => Ok(self.command().map(|(name, _)| name).unwrap_or_default()),
+            Some(c) => {
+                self.index += 1;
+                Ok(c.to_string())
+            },
+            None => Err(ParseError::MissingArgument(span))
+        }
+    }
+
+    // Argument of a command or a power, a braced group or a single char or command like in x^2 or \frac12
+    fn argument(&mut self, span : Span) -> Result<(), ParseError> {
+        match self.peek() {
+            Some('{') => self.item(),
+            Some(c) if c.is_alphanumeric() || c == '\\' => {
+                let start = self.index;
+                self.start_operand(span);
+                self.push(Operator::ParensOpen, span);
+                if c == '\\' {
+                    self.item()?;
+                } else {
+                    self.index += 1;
+                    self.tokens.push(Token::Identifier(c.to_string(), Span::new(start, start + 1)));
+                }
+                self.push(Operator::ParensClose, span);
+                Ok(())
+            },
+            _ => Err(ParseError::MissingArgument(span))
+        }
+    }
+
+    // Text of a braced group read as is, like the name in \mathrm{name}
+    fn raw_group(&mut self, span : Span) -> Result<String, ParseError> {
+        if self.peek() != Some('{') { return Err(ParseError::MissingArgument(span)); }
+        let open = self.index;
+        let mut text = String::new();
+        self.index += 1;
+        loop {
+            match self.chars.get(self.index) {
+                None => return Err(ParseError::UnbalancedParen('{', Span::new(open, open + 1))),
+                Some('}') => break,
+                // Escaped underscores of \mathrm{my\_name}
+                Some('\\') if self.chars.get(self.index + 1) == Some(&'_') => {
+                    text.push('_');
+                    self.index += 1;
+                },
+                Some(c) => text.push(*c)
+            }
+            self.index += 1;
+        }
+        self.index += 1;
+        Ok(text)
+    }
+
+    // Subscripts are part of the name, v_0 or x_{max} are variables
+    fn with_subscript(&mut self, name : String) -> Result<String, ParseError> {
+        if self.peek() != Some('_') { return Ok(name); }
+        let span = Span::new(self.index, self.index + 1);
+        self.index += 1;
+        let subscript = match self.peek() {
+            Some('{') => self.raw_group(span)?,
+            Some(c) if c.is_alphanumeric() => {
+                self.index += 1;
+                c.to_string()
+            },
+            _ => return Err(ParseError::UnexpectedOperator("_".to_string(), span))
+        };
+        let name = format!("{}_{}", name, subscript.trim());
+        if !is_valid_name(&name) { return Err(ParseError::UnknownIdentifier(name, span)); }
+        Ok(name)
+    }
+
+    fn next(&mut self) -> Next {
+        match self.peek() {
+            Some(c) if c.is_alphanumeric() || matches!(c, '.' | '{' | '(' | '[') => Next::Operand,
+            Some('^' | '!' | '²' | '*' | '/') => Next::Continue,
+            // Signs like in x \cdot -2
+            Some('+' | '-') if matches!(self.tokens.last(), Some(Token::Operator(o, _)) if !matches!(o, Operator::ParensClose | Operator::Factorial | Operator::Square)) => Next::Continue,
+            Some('\\') => match self.peek_command() {
+                Some(name) if OPERANDS.contains(&name.as_str()) || GREEK.contains(&name.as_str()) || FUNCTIONS.iter().any(|(command, _)| *command == name) => Next::Operand,
+                Some(name) if SPACES.contains(&name.as_str()) || matches!(name.as_str(), "cdot" | "times" | "ast" | "div") => Next::Continue,
+                _ => Next::Stop
+            },
+            _ => Next::Stop
+        }
+    }
+
+    // Operands multiplied together with their powers, up to the next + or -, like the argument of \sin 2x
+    fn term(&mut self, span : Span) -> Result<(), ParseError> {
+        if self.next() != Next::Operand { return Err(ParseError::MissingArgument(span)); }
+        self.item()?;
+        while self.next() != Next::Stop {
+            self.item()?;
+        }
+        Ok(())
+    }
+
+    // \sin x, \sin(x) or \sin^2 x which is sin(x)^2
+    fn function(&mut self, name : String, span : Span) -> Result<(), ParseError> {
+        let power = if self.peek() == Some('^') {
+            self.index += 1;
+            let start = self.tokens.len();
+            self.argument(span)?;
+            Some(self.tokens.split_off(start))
+        } else {
+            None
+        };
+        if power.is_some() {
+            self.start_operand(span);
+            self.push(Operator::ParensOpen, span);
+        }
+        self.identifier(name, span);
+        self.push(Operator::ParensOpen, span);
+        // Parenthesis hold the arguments, \max(1, x) is a call and \sin(x) y is sin(x)*y
+        let open = self.index;
+        let parens = match self.peek() {
+            Some(c @ ('(' | '[' | '{')) => Some((c, 1)),
+            Some('\\') if self.peek_command().as_deref() == Some("left") && matches!(self.chars.get(open + 5), Some('(' | '[')) => Some(('(', 6)),
+            _ => None
+        };
+        if let Some((c, n)) = parens {
+            self.index += n;
+            self.contents(c, Span::new(open, open + n))?;
+        } else if self.peek() == Some('|') || self.peek_command().as_deref() == Some("left") {
+            self.item()?;
+        } else {
+            self.term(span)?;
+        }
+        self.push(Operator::ParensClose, span);
+        if let Some(power) = power {
+            self.push(Operator::ParensClose, span);
+            self.push(Operator::Pow, span);
+            self.tokens.extend(power);
+        }
+        Ok(())
+    }
+
+    // \sum_{i=1}^{n} body, the body runs like the argument of \sin 2x
+    fn iterator(&mut self, name : String, span : Span) -> Result<(), ParseError> {
+        self.identifier(name, span);
+        self.push(Operator::ParensOpen, span);
+        if self.peek() != Some('_') { return Err(ParseError::MissingArgument(span)); }
+        self.index += 1;
+        let start = self.tokens.len();
+        self.argument(span)?;
+        // The = of the lower bound assigns the iterator
+        if let Some(Token::Operator(o, _)) = self.tokens[start..].iter_mut().find(|token| matches!(token, Token::Operator(Operator::TestEQU, _))) {
+            *o = Operator::Assign;
+        }
+        self.push(Operator::Comma, span);
+        if self.peek() != Some('^') { return Err(ParseError::MissingArgument(span)); }
+        self.index += 1;
+        self.argument(span)?;
+        self.push(Operator::Comma, span);
+        self.term(span)?;
+        self.push(Operator::ParensClose, span);
+        Ok(())
+    }
+
+    // \begin{cases} a & \text{if } c \\ b & \text{otherwise} \end{cases} gives nested ifs, NaN when no row holds
+    fn cases(&mut self, span : Span) -> Result<(), ParseError> {
+        let environment = self.raw_group(span)?;
+        if environment != "cases" { return Err(ParseError::UnknownIdentifier(format!("\\begin{{{}}}", environment), span)); }
+
+        let mut rows = vec![];
+        loop {
+            let mut value = self.cell(span)?;
+            // Rows like `x, & x > 0`
+            if let Some(Token::Operator(Operator::Comma, _)) = value.last() { value.pop(); }
+            let mut condition = None;
+            if self.peek() == Some('&') {
+                self.index += 1;
+                // Words around the condition like \text{if } or \text{otherwise}
+                let otherwise = match self.peek_command().as_deref() {
+                    Some("text" | "mbox" | "mathrm") => {
+                        self.command();
+                        matches!(self.raw_group(span)?.trim(), "otherwise" | "else")
+                    },
+                    _ => false
+                };
+                let tokens = self.cell(span)?;
+                if !otherwise { condition = Some(tokens); }
+            }
+            if !value.is_empty() || condition.is_some() { rows.push((value, condition)); }
+            match self.command() {
+                Some((name, _)) if name == "\\" => continue,
+                // Only \end is left, cell stops there
+                _ => {
+                    self.raw_group(span)?;
+                    break;
+                }
+            }
+        }
+
+        self.start_operand(span);
+        let mut opened = 0;
+        for (value, condition) in rows {
+            let Some(condition) = condition else {
+                self.tokens.extend(value);
+                break;
+            };
+            self.tokens.push(Token::Identifier("if".to_string(), span));
+            self.push(Operator::ParensOpen, span);
+            self.tokens.extend(condition);
+            self.push(Operator::Comma, span);
+            self.tokens.extend(value);
+            self.push(Operator::Comma, span);
+            opened += 1;
+        }
+        if matches!(self.tokens.last(), Some(Token::Operator(Operator::Comma, _))) {
+            self.tokens.push(Token::Identifier("NaN".to_string(), span));
+        }
+        for _ in 0..opened {
+            self.push(Operator::ParensClose, span);
+        }
+        Ok(())
+    }
+
+    // Tokens up to the next &, \\ or \end of a cases environment
+    fn cell(&mut self, span : Span) -> Result<Vec<Token>, ParseError> {
+        let start = self.tokens.len();
+        loop {
+            match self.peek() {
+                None => return Err(ParseError::MissingArgument(span)),
+                Some('&') => break,
+                Some('\\') if matches!(self.peek_command().as_deref(), Some("\\" | "end")) => break,
+                _ => self.item()?
+            }
+        }
+        Ok(self.tokens.split_off(start))
+    }
+}
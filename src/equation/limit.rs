@@ -0,0 +1,118 @@
+/// Largest error estimate of a limit or a derivative relative to its value, or absolute below 1
+const TOLERANCE : f64 = 1e-6;
+/// Steps of a derivative, the difference quotient gets less precise past them
+const DERIVATIVE_STEPS : usize = 12;
+/// Steps of a limit, the distance is quartered at each one until the values stop changing
+const LIMIT_STEPS : usize = 40;
+/// Columns of the extrapolation, higher orders only amplify rounding errors
+const MAX_ORDER : usize = 6;
+/// How much worse than the best estimate the extrapolation gets before the steps stop
+const SAFE : f64 = 2.0;
+
+/// Direction a limit is taken from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+    /// Both sides giving the same value
+    Both,
+}
+
+/// Value approached found by `limit` or `derivative`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    /// NaN where the function has no value around the point
+    Value(f64),
+    /// Different values on each side, like abs(x)/x at 0
+    Mismatch { left : f64, right : f64 },
+    /// No value is approached, like 1/x or sin(1/x) at 0
+    Diverges,
+}
+
+/// Limit of f at `at`, which can be infinite, from the given side.
+/// The values f takes closer and closer to `at` are extrapolated to it with Richardson's method.
+/// The side where f has no value at all is ignored when both are asked for, like for sqrt(x) at 0.
+pub fn limit(mut f : impl FnMut(f64) -> f64, at : f64, side : Side) -> Limit {
+    if at.is_nan() { return Limit::Value(f64::NAN); }
+    // Infinity is approached from the only side there is, with x = ±1 / h
+    if at.is_infinite() {
+        return one_sided(|h| f(at.signum() / h), 1.0);
+    }
+    let start = 0.1 * at.abs().max(1.0);
+    let mut left = || one_sided(|h| f(at - h), start);
+    match side {
+        Side::Left => left(),
+        Side::Right => one_sided(|h| f(at + h), start),
+        Side::Both => {
+            let left = left();
+            match (left, one_sided(|h| f(at + h), start)) {
+                (Limit::Value(l), right) if l.is_nan() => right,
+                (left, Limit::Value(r)) if r.is_nan() => left,
+                (Limit::Value(l), Limit::Value(r)) if (l - r).abs() <= TOLERANCE * l.abs().max(r.abs()).max(1.0) => Limit::Value((l + r) / 2.0),
+                (Limit::Value(left), Limit::Value(right)) => Limit::Mismatch { left, right },
+                _ => Limit::Diverges
+            }
+        }
+    }
+}
+
+/// Derivative of f at `at` from central differences extrapolated with Ridders' method, which also estimates its error
+pub fn derivative(mut f : impl FnMut(f64) -> f64, at : f64) -> Limit {
+    if !at.is_finite() { return Limit::Value(f64::NAN); }
+    let (value, error) = extrapolate(|h| (f(at + h) - f(at - h)) / (2.0 * h), 0.1 * at.abs().max(1.0), 1.4, 2, DERIVATIVE_STEPS);
+    settle(value, error)
+}
+
+// Limit of g(h) for h going to 0 from above
+fn one_sided(mut g : impl FnMut(f64) -> f64, start : f64) -> Limit {
+    let (value, error) = extrapolate(&mut g, start, 4.0, 1, LIMIT_STEPS);
+    settle(value, error)
+}
+
+fn settle(value : f64, error : f64) -> Limit {
+    if value.is_nan() || (value.is_finite() && error <= TOLERANCE * value.abs().max(1.0)) { Limit::Value(value) } else { Limit::Diverges }
+}
+
+// Richardson extrapolation of g(h) to h = 0 when its error shrinks like h^order, h is divided by `shrink` at each step.
+// Gives the extrapolated value with the smallest estimated error and that error, NaN when g has no value at all.
+fn extrapolate(mut g : impl FnMut(f64) -> f64, start : f64, shrink : f64, order : i32, steps : usize) -> (f64, f64) {
+    let ratio = shrink.powi(order);
+    let mut h = start;
+    let mut previous = vec![g(h)];
+    let mut constant = true;
+    let (mut best, mut error) = (f64::NAN, f64::INFINITY);
+    for _ in 1..steps {
+        h /= shrink;
+        let y = g(h);
+        // A value repeated exactly after others is lost in rounding, like 1 - cos(h) once it is 0
+        constant &= y == previous[0];
+        if y == previous[0] && !constant { break; }
+        // Starts over past the values too far to tell anything, like at a pole
+        if !previous[0].is_finite() {
+            previous = vec![y];
+            constant = true;
+            continue;
+        }
+        // The value itself is a candidate, closer than the previous one when they converge like h^order
+        let estimate = (y - previous[0]).abs();
+        if estimate <= error {
+            (best, error) = (y, estimate);
+        }
+        let mut row = vec![y];
+        let mut factor = ratio;
+        for j in 1..=previous.len().min(MAX_ORDER) {
+            let next = (row[j - 1] * factor - previous[j - 1]) / (factor - 1.0);
+            let estimate = (next - row[j - 1]).abs().max((next - previous[j - 1]).abs());
+            if estimate <= error {
+                (best, error) = (next, estimate);
+            }
+            row.push(next);
+            factor *= ratio;
+        }
+        // Once settled, the extrapolation getting worse means rounding errors took over
+        let worse = (row[row.len() - 1] - previous[previous.len() - 1]).abs() >= SAFE * error;
+        previous = row;
+        if worse && error <= TOLERANCE * best.abs().max(1.0) { break; }
+    }
+    (best, error)
+}
@@ -2,138 +2,691 @@ use core::f32;
 use std::{vec};
 use std::collections::HashMap;
 
+mod complex;
+mod curve;
+mod derive;
+mod display;
+mod dual;
+mod error;
+mod extrema;
+mod integral;
+mod exact;
+mod interval;
+mod latex;
+mod latex_parser;
+mod limit;
+mod number;
+mod program;
+mod roots;
+mod simplify;
+mod solve;
+pub use complex::Complex;
+pub use curve::{sample_curve, sample_function, Polyline};
+pub use derive::{derive, derive_with};
+pub use dual::Dual;
+pub use error::{EvalError, ParseError, Span};
+pub use integral::{integrate, Integral};
+pub use extrema::{critical_points, critical_points_with, find_critical_points, CriticalPoint, PointKind};
+pub use exact::{Exact, Rational};
+pub use interval::Interval;
+pub use limit::{derivative, limit, Limit, Side};
+pub use program::{Program, Registers};
+pub use roots::{find_roots, intersections, intersections_with, roots, roots_with, Root};
+pub use simplify::simplify;
+pub use solve::{find_solutions, polynomial_degree, solve, solve_with, Solutions};
+
+use number::Number;
+
+/// Deepest expression tree accepted by the parser, evaluation recurses as deep as the tree
+const MAX_DEPTH : usize = 256;
+/// Most iterations a `sum` or `prod` may run
+const MAX_ITERATIONS : i64 = 1_000_000;
+/// Deepest chain of user function calls, stops definitions like `f(x) = f(x)`
+const MAX_CALL_DEPTH : usize = 64;
+/// Variable holding the imaginary unit of complex evaluations until something assigns it.
+/// Not a built-in, so it stays the iterator of sums and i(x+1) is i*(x+1) as for any variable.
+const IMAGINARY_UNIT : &str = "i";
+
 pub type SimpleFunction = fn(f32) -> f32;
 type MultiFunction = fn(&[f32]) -> f32;
+#[derive(Clone, Debug)]
+#[allow(clippy::enum_variant_names)]
 enum Function {
-    MultiFunction(MultiFunction),
-    SimpleFunction(SimpleFunction),
-    Constant (f32),
+    // Functions and operators with their f32 version, the other number types have their own
+    MultiFunction(Multi, MultiFunction),
+    SimpleFunction(Simple, SimpleFunction),
+    // Kept in double precision, f32 evaluations round it
+    Constant (f64),
     Iterator(Operator),
     InputX,
     Variable(String),
     Assign,
-    If
+    If,
+    Call(String),
+    Numeric(Numeric)
+}
+
+/// Functions and operators of one argument
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Simple {
+    // Operators
+    Plus,
+    Minus,
+    Square,
+    Factorial,
+    // Built-ins
+    Sin,
+    Cos,
+    Tan,
+    Cot,
+    Sec,
+    Csc,
+    Abs,
+    Ceil,
+    Floor,
+    Round,
+    Exp,
+    Ln,
+    Log,
+    Sqrt,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Asinh,
+    Acosh,
+    Atanh,
+    Re,
+    Im,
+    Arg,
+    Conj,
+    /// Made by create_from_function, only its f32 version exists
+    External,
+}
+
+impl Simple {
+    // Name it is written with, the operators are not called by it
+    fn name(self) -> &'static str {
+        match self {
+            Simple::Plus => "+",
+            Simple::Minus => "-",
+            Simple::Square => "²",
+            Simple::Factorial => "!",
+            Simple::Sin => "sin",
+            Simple::Cos => "cos",
+            Simple::Tan => "tan",
+            Simple::Cot => "cot",
+            Simple::Sec => "sec",
+            Simple::Csc => "csc",
+            Simple::Abs => "abs",
+            Simple::Ceil => "ceil",
+            Simple::Floor => "floor",
+            Simple::Round => "round",
+            Simple::Exp => "exp",
+            Simple::Ln => "ln",
+            Simple::Log => "log",
+            Simple::Sqrt => "sqrt",
+            Simple::Asin => "asin",
+            Simple::Acos => "acos",
+            Simple::Atan => "atan",
+            Simple::Sinh => "sinh",
+            Simple::Cosh => "cosh",
+            Simple::Tanh => "tanh",
+            Simple::Asinh => "asinh",
+            Simple::Acosh => "acosh",
+            Simple::Atanh => "atanh",
+            Simple::Re => "re",
+            Simple::Im => "im",
+            Simple::Arg => "arg",
+            Simple::Conj => "conj",
+            Simple::External => "f",
+        }
+    }
+}
+
+/// Functions and operators of several arguments
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Multi {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    PowCall,
+    Equal,
+    NotEqual,
+    LessEqual,
+    Less,
+    GreaterEqual,
+    Greater,
+    Max,
+    Min,
+}
+
+impl Multi {
+    // Name it is written with
+    fn name(self) -> &'static str {
+        match self {
+            Multi::Add => "+",
+            Multi::Sub => "-",
+            Multi::Mul => "*",
+            Multi::Div => "/",
+            Multi::Pow => "^",
+            Multi::PowCall => "pow",
+            Multi::Equal => "==",
+            Multi::NotEqual => "!=",
+            Multi::LessEqual => "<=",
+            Multi::Less => "<",
+            Multi::GreaterEqual => ">=",
+            Multi::Greater => ">",
+            Multi::Max => "max",
+            Multi::Min => "min",
+        }
+    }
+}
+
+/// Built-ins evaluating their leading arguments at other values of a variable bound inside them, x unless it is named
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Numeric {
+    Roots,
+    Intersect,
+    Integral,
+    Deriv,
+    Limit,
+    Solve,
+}
+
+impl Numeric {
+    fn name(self) -> &'static str {
+        match self {
+            Numeric::Roots => "roots",
+            Numeric::Intersect => "intersect",
+            Numeric::Integral => "integral",
+            Numeric::Deriv => "deriv",
+            Numeric::Limit => "limit",
+            Numeric::Solve => "solve",
+        }
+    }
+
+    // Leading arguments where x is bound
+    fn bodies(self) -> usize {
+        match self {
+            Numeric::Roots => 1,
+            Numeric::Intersect => 2,
+            Numeric::Integral | Numeric::Deriv | Numeric::Limit | Numeric::Solve => 1,
+        }
+    }
+
+    // Whether the argument after the leading ones names the bound variable
+    fn names_variable(self) -> bool {
+        matches!(self, Numeric::Integral | Numeric::Deriv | Numeric::Limit | Numeric::Solve)
+    }
+
+    // Leading arguments, the bound variable and the other arguments
+    fn parts(self, params : &[Expression]) -> Option<(&[Expression], &str, &[Expression])> {
+        if params.len() < self.bodies() { return None; }
+        let (bodies, params) = params.split_at(self.bodies());
+        if !self.names_variable() { return Some((bodies, "x", params)); }
+        let (variable, params) = params.split_first()?;
+        match (&variable.function, &variable.params[..]) {
+            (Function::InputX, _) => Some((bodies, "x", params)),
+            (Function::Variable(name), []) => Some((bodies, name, params)),
+            _ => None
+        }
+    }
+}
+
+/// Function defined by the user like `f(x, y) = x * y`
+pub struct UserFunction {
+    params : Vec<String>,
+    body : Expression
+}
+
+pub type UserFunctions = HashMap<String, UserFunction>;
+
+/// Entry of the built-in registry, call arities are checked when parsing
+pub struct Builtin {
+    pub name : &'static str,
+    pub signature : &'static str,
+    pub min_arity : usize,
+    pub max_arity : usize,
+}
+
+const fn builtin(name : &'static str, signature : &'static str, min_arity : usize, max_arity : usize) -> Builtin {
+    Builtin { name, signature, min_arity, max_arity }
+}
+
+pub static BUILTINS : [Builtin; 42] = [
+    builtin("sin", "sin(x)", 1, 1),
+    builtin("cos", "cos(x)", 1, 1),
+    builtin("tan", "tan(x)", 1, 1),
+    builtin("cot", "cot(x)", 1, 1),
+    builtin("sec", "sec(x)", 1, 1),
+    builtin("csc", "csc(x)", 1, 1),
+    builtin("abs", "abs(x)", 1, 1),
+    builtin("ceil", "ceil(x)", 1, 1),
+    builtin("floor", "floor(x)", 1, 1),
+    builtin("round", "round(x)", 1, 1),
+    builtin("exp", "exp(x)", 1, 1),
+    builtin("ln", "ln(x)", 1, 1),
+    builtin("log", "log(x)", 1, 1),
+    builtin("sqrt", "sqrt(x)", 1, 1),
+    builtin("asin", "asin(x)", 1, 1),
+    builtin("acos", "acos(x)", 1, 1),
+    builtin("atan", "atan(x)", 1, 1),
+    builtin("sinh", "sinh(x)", 1, 1),
+    builtin("cosh", "cosh(x)", 1, 1),
+    builtin("tanh", "tanh(x)", 1, 1),
+    builtin("asinh", "asinh(x)", 1, 1),
+    builtin("acosh", "acosh(x)", 1, 1),
+    builtin("atanh", "atanh(x)", 1, 1),
+    builtin("re", "re(z)", 1, 1),
+    builtin("im", "im(z)", 1, 1),
+    builtin("arg", "arg(z)", 1, 1),
+    builtin("conj", "conj(z)", 1, 1),
+
+    builtin("pi", "pi", 0, 0),
+    builtin("e", "e", 0, 0),
+
+    builtin("max", "max(a, ...)", 1, usize::MAX),
+    builtin("min", "min(a, ...)", 1, usize::MAX),
+    builtin("sum", "sum(i = start, end, expression)", 3, 3),
+    builtin("prod", "prod(i = start, end, expression)", 3, 3),
+    builtin("if", "if(condition, then, else)", 3, 3),
+    builtin("roots", "roots(expression, a, b, n)", 3, 4),
+    builtin("intersect", "intersect(f, g, a, b, n)", 4, 5),
+    builtin("integral", "integral(expression, variable, a, b)", 4, 4),
+    builtin("deriv", "deriv(expression, variable, at)", 3, 3),
+    builtin("limit", "limit(expression, variable, at, side)", 3, 4),
+    builtin("solve", "solve(equation, variable, guess)", 2, 3),
+
+    builtin("pow", "pow(base, exponent)", 2, 2),
+
+    // Replaced while parsing
+    builtin("d", "d(expression, variable)", 2, 2),
+];
+
+pub fn builtin_from_string(s : &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name.eq_ignore_ascii_case(s))
 }
 
 fn function_from_string(s : &str) -> Option<Function>{
 
-    match s.to_ascii_lowercase().as_str() {
-        "sin" => Some(Function::SimpleFunction(f32::sin)),
-        "cos" => Some(Function::SimpleFunction(f32::cos)),
-        "tan" => Some(Function::SimpleFunction(f32::tan)),
-        "cot" => Some(Function::SimpleFunction(|x| 1f32 / f32::tan(x))),
-        "sec" => Some(Function::SimpleFunction(|x| 1f32 / f32::cos(x))),
-        "csc" => Some(Function::SimpleFunction(|x| 1f32 / f32::sin(x))),
-        "abs" => Some(Function::SimpleFunction(f32::abs)),
-        "ceil" => Some(Function::SimpleFunction(f32::ceil)),
-        "floor" => Some(Function::SimpleFunction(f32::floor)),
-        "round" => Some(Function::SimpleFunction(f32::round)),
-        "exp" => Some(Function::SimpleFunction(f32::exp)),
-        "ln" => Some(Function::SimpleFunction(f32::ln)),
-        "log" => Some(Function::SimpleFunction(f32::log10)),
-        "sqrt" => Some(Function::SimpleFunction(f32::sqrt)),
-        "asin" => Some(Function::SimpleFunction(f32::asin)),
-        "acos" => Some(Function::SimpleFunction(f32::acos)),
-        "atan" => Some(Function::SimpleFunction(f32::atan)),
-        "sinh" => Some(Function::SimpleFunction(f32::sinh)),
-        "cosh" => Some(Function::SimpleFunction(f32::cosh)),
-        "tanh" => Some(Function::SimpleFunction(f32::tanh)),
-        "asinh" => Some(Function::SimpleFunction(f32::asinh)),
-        "acosh" => Some(Function::SimpleFunction(f32::acosh)),
-        "atanh" => Some(Function::SimpleFunction(f32::atanh)),
-
-        "pi" => Some(Function::Constant(f32::consts::PI)),
-        "e" => Some(Function::Constant(f32::consts::E)),
-
-        "max" => Some(Function::MultiFunction(|x| x.iter().fold(f32::MIN, |max, next| max.max(*next)))),
-        "min" => Some(Function::MultiFunction(|x| x.iter().fold(f32::MAX, |min, next| min.min(*next)))),
+    match builtin_from_string(s)?.name {
+        "sin" => Some(Function::SimpleFunction(Simple::Sin, f32::sin)),
+        "cos" => Some(Function::SimpleFunction(Simple::Cos, f32::cos)),
+        "tan" => Some(Function::SimpleFunction(Simple::Tan, f32::tan)),
+        "cot" => Some(Function::SimpleFunction(Simple::Cot, |x| 1f32 / f32::tan(x))),
+        "sec" => Some(Function::SimpleFunction(Simple::Sec, |x| 1f32 / f32::cos(x))),
+        "csc" => Some(Function::SimpleFunction(Simple::Csc, |x| 1f32 / f32::sin(x))),
+        "abs" => Some(Function::SimpleFunction(Simple::Abs, f32::abs)),
+        "ceil" => Some(Function::SimpleFunction(Simple::Ceil, f32::ceil)),
+        "floor" => Some(Function::SimpleFunction(Simple::Floor, f32::floor)),
+        "round" => Some(Function::SimpleFunction(Simple::Round, f32::round)),
+        "exp" => Some(Function::SimpleFunction(Simple::Exp, f32::exp)),
+        "ln" => Some(Function::SimpleFunction(Simple::Ln, f32::ln)),
+        "log" => Some(Function::SimpleFunction(Simple::Log, f32::log10)),
+        "sqrt" => Some(Function::SimpleFunction(Simple::Sqrt, f32::sqrt)),
+        "asin" => Some(Function::SimpleFunction(Simple::Asin, f32::asin)),
+        "acos" => Some(Function::SimpleFunction(Simple::Acos, f32::acos)),
+        "atan" => Some(Function::SimpleFunction(Simple::Atan, f32::atan)),
+        "sinh" => Some(Function::SimpleFunction(Simple::Sinh, f32::sinh)),
+        "cosh" => Some(Function::SimpleFunction(Simple::Cosh, f32::cosh)),
+        "tanh" => Some(Function::SimpleFunction(Simple::Tanh, f32::tanh)),
+        "asinh" => Some(Function::SimpleFunction(Simple::Asinh, f32::asinh)),
+        "acosh" => Some(Function::SimpleFunction(Simple::Acosh, f32::acosh)),
+        "atanh" => Some(Function::SimpleFunction(Simple::Atanh, f32::atanh)),
+        // Real numbers are their own real part and conjugate
+        "re" => Some(Function::SimpleFunction(Simple::Re, |x| x)),
+        "im" => Some(Function::SimpleFunction(Simple::Im, |x| 0f32 * x)),
+        "arg" => Some(Function::SimpleFunction(Simple::Arg, |x| 0f32.atan2(x))),
+        "conj" => Some(Function::SimpleFunction(Simple::Conj, |x| x)),
+
+        "pi" => Some(Function::Constant(std::f64::consts::PI)),
+        "e" => Some(Function::Constant(std::f64::consts::E)),
+
+        "max" => Some(Function::MultiFunction(Multi::Max, |x| x.iter().fold(f32::MIN, |max, next| max.max(*next)))),
+        "min" => Some(Function::MultiFunction(Multi::Min, |x| x.iter().fold(f32::MAX, |min, next| min.min(*next)))),
         "sum" => Some(Function::Iterator(Operator::Add)),
         "prod" => Some(Function::Iterator(Operator::Mul)),
         "if" => Some(Function::If),
+        "roots" => Some(Function::Numeric(Numeric::Roots)),
+        "intersect" => Some(Function::Numeric(Numeric::Intersect)),
+        "integral" => Some(Function::Numeric(Numeric::Integral)),
+        "deriv" => Some(Function::Numeric(Numeric::Deriv)),
+        "limit" => Some(Function::Numeric(Numeric::Limit)),
+        "solve" => Some(Function::Numeric(Numeric::Solve)),
         
-        "pow" => Some(Function::MultiFunction(|x| x[0].powf(x[1]))),
+        "pow" => Some(Function::MultiFunction(Multi::PowCall, |x| binary(x, f32::powf))),
         _ => None
     }
 }
 
+// Value of a variable nothing assigned, 0 except `i` that is the imaginary unit of complex numbers
+fn unassigned<T : Number>(name : &str) -> T {
+    if name == IMAGINARY_UNIT { T::imaginary_unit() } else { T::from_f64(0.0) }
+}
+
+// Binary functions get exactly two operands, anything else comes from a malformed call
+fn binary(x : &[f32], f : fn(f32, f32) -> f32) -> f32 {
+    match x {
+        [a, b] => f(*a, *b),
+        _ => f32::NAN
+    }
+}
+
+// Whether evaluating `e` can read the variable, the body of a user function can read any
+fn reads(e : &Expression, name : &str) -> bool {
+    e.variable_name() == Some(name) || matches!(e.function, Function::Call(_)) || e.params.iter().any(|p| reads(p, name))
+}
+
+#[derive(Clone, Debug)]
 pub struct Expression{
     params : Vec<Expression>,
-    function : Function
+    function : Function,
+    span : Span
+}
+
+// Functions are the same when they have the same name
+impl PartialEq for Function {
+    fn eq(&self, other : &Function) -> bool {
+        match (self, other) {
+            (Function::MultiFunction(a, _), Function::MultiFunction(b, _)) => a == b,
+            (Function::SimpleFunction(a, _), Function::SimpleFunction(b, _)) => a == b,
+            (Function::Constant(a), Function::Constant(b)) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+            (Function::Iterator(a), Function::Iterator(b)) => a == b,
+            (Function::Numeric(a), Function::Numeric(b)) => a == b,
+            (Function::Variable(a), Function::Variable(b)) | (Function::Call(a), Function::Call(b)) => a == b,
+            (Function::InputX, Function::InputX) | (Function::Assign, Function::Assign) | (Function::If, Function::If) => true,
+            _ => false
+        }
+    }
+}
+
+/// Same tree, spans are not compared
+impl PartialEq for Expression {
+    fn eq(&self, other : &Expression) -> bool {
+        self.function == other.function && self.params == other.params
+    }
 }
 
 impl Expression{
 
     pub fn evaluate(&self, x : f32, variables : &mut HashMap<String, f32>) -> f32{
-        let param_values : Vec<f32> = match &self.function {
-            Function::Iterator(_) | Function::If => vec![],
-            _ => self.params.iter().map(|e| e.evaluate(x, variables)).collect()
+        self.try_evaluate(x, variables).unwrap_or(f32::NAN)
+    }
+
+    /// Same as `evaluate` but gives the reason when the tree cannot be evaluated
+    pub fn try_evaluate(&self, x : f32, variables : &mut HashMap<String, f32>) -> Result<f32, EvalError>{
+        self.eval(&x, variables, &UserFunctions::new(), 0)
+    }
+
+    /// Evaluates with the user functions the expression was parsed with
+    pub fn evaluate_with(&self, x : f32, variables : &mut HashMap<String, f32>, functions : &UserFunctions) -> f32{
+        self.try_evaluate_with(x, variables, functions).unwrap_or(f32::NAN)
+    }
+
+    pub fn try_evaluate_with(&self, x : f32, variables : &mut HashMap<String, f32>, functions : &UserFunctions) -> Result<f32, EvalError>{
+        self.eval(&x, variables, functions, 0)
+    }
+
+    /// Same as `evaluate` in double precision, constants keep the precision they were written with
+    pub fn evaluate_f64(&self, x : f64, variables : &mut HashMap<String, f64>) -> f64{
+        self.try_evaluate_f64(x, variables).unwrap_or(f64::NAN)
+    }
+
+    pub fn try_evaluate_f64(&self, x : f64, variables : &mut HashMap<String, f64>) -> Result<f64, EvalError>{
+        self.eval(&x, variables, &UserFunctions::new(), 0)
+    }
+
+    pub fn evaluate_f64_with(&self, x : f64, variables : &mut HashMap<String, f64>, functions : &UserFunctions) -> f64{
+        self.try_evaluate_f64_with(x, variables, functions).unwrap_or(f64::NAN)
+    }
+
+    pub fn try_evaluate_f64_with(&self, x : f64, variables : &mut HashMap<String, f64>, functions : &UserFunctions) -> Result<f64, EvalError>{
+        self.eval(&x, variables, functions, 0)
+    }
+
+    /// Evaluates with integers and fractions of any size, functions like sin or sqrt give a float
+    pub fn evaluate_exact(&self, x : Exact, variables : &mut HashMap<String, Exact>) -> Exact{
+        self.try_evaluate_exact(x, variables).unwrap_or(Exact::Float(f64::NAN))
+    }
+
+    pub fn try_evaluate_exact(&self, x : Exact, variables : &mut HashMap<String, Exact>) -> Result<Exact, EvalError>{
+        self.eval(&x, variables, &UserFunctions::new(), 0)
+    }
+
+    pub fn evaluate_exact_with(&self, x : Exact, variables : &mut HashMap<String, Exact>, functions : &UserFunctions) -> Exact{
+        self.try_evaluate_exact_with(x, variables, functions).unwrap_or(Exact::Float(f64::NAN))
+    }
+
+    pub fn try_evaluate_exact_with(&self, x : Exact, variables : &mut HashMap<String, Exact>, functions : &UserFunctions) -> Result<Exact, EvalError>{
+        self.eval(&x, variables, functions, 0)
+    }
+
+    /// Evaluates with complex numbers, `i` is the imaginary unit and `sqrt(-1)` or `ln(-2)` have a value
+    pub fn evaluate_complex(&self, x : Complex, variables : &mut HashMap<String, Complex>) -> Complex{
+        self.try_evaluate_complex(x, variables).unwrap_or(Complex::new(f64::NAN, f64::NAN))
+    }
+
+    pub fn try_evaluate_complex(&self, x : Complex, variables : &mut HashMap<String, Complex>) -> Result<Complex, EvalError>{
+        self.eval(&x, variables, &UserFunctions::new(), 0)
+    }
+
+    pub fn evaluate_complex_with(&self, x : Complex, variables : &mut HashMap<String, Complex>, functions : &UserFunctions) -> Complex{
+        self.try_evaluate_complex_with(x, variables, functions).unwrap_or(Complex::new(f64::NAN, f64::NAN))
+    }
+
+    pub fn try_evaluate_complex_with(&self, x : Complex, variables : &mut HashMap<String, Complex>, functions : &UserFunctions) -> Result<Complex, EvalError>{
+        self.eval(&x, variables, functions, 0)
+    }
+
+    /// Bounds of every value over the interval of x, like `[-1, 1]` for sin(x) on [0, 10].
+    /// The bounds are rounded outward so they hold despite floating point errors, poles give infinite bounds.
+    pub fn evaluate_interval(&self, x : Interval, variables : &mut HashMap<String, Interval>) -> Interval{
+        self.try_evaluate_interval(x, variables).unwrap_or(Interval::ENTIRE)
+    }
+
+    pub fn try_evaluate_interval(&self, x : Interval, variables : &mut HashMap<String, Interval>) -> Result<Interval, EvalError>{
+        self.eval(&x, variables, &UserFunctions::new(), 0)
+    }
+
+    pub fn evaluate_interval_with(&self, x : Interval, variables : &mut HashMap<String, Interval>, functions : &UserFunctions) -> Interval{
+        self.try_evaluate_interval_with(x, variables, functions).unwrap_or(Interval::ENTIRE)
+    }
+
+    pub fn try_evaluate_interval_with(&self, x : Interval, variables : &mut HashMap<String, Interval>, functions : &UserFunctions) -> Result<Interval, EvalError>{
+        self.eval(&x, variables, functions, 0)
+    }
+
+    /// Value and derivative at once, the derivative is with respect to what has a derivative of 1 like `Dual::variable(x)`.
+    /// Unlike `derive` nothing is built, and variables assigned in the expression keep their derivative.
+    pub fn evaluate_dual(&self, x : Dual, variables : &mut HashMap<String, Dual>) -> Dual{
+        self.try_evaluate_dual(x, variables).unwrap_or(Dual::new(f64::NAN, f64::NAN))
+    }
+
+    pub fn try_evaluate_dual(&self, x : Dual, variables : &mut HashMap<String, Dual>) -> Result<Dual, EvalError>{
+        self.eval(&x, variables, &UserFunctions::new(), 0)
+    }
+
+    pub fn evaluate_dual_with(&self, x : Dual, variables : &mut HashMap<String, Dual>, functions : &UserFunctions) -> Dual{
+        self.try_evaluate_dual_with(x, variables, functions).unwrap_or(Dual::new(f64::NAN, f64::NAN))
+    }
+
+    pub fn try_evaluate_dual_with(&self, x : Dual, variables : &mut HashMap<String, Dual>, functions : &UserFunctions) -> Result<Dual, EvalError>{
+        self.eval(&x, variables, functions, 0)
+    }
+
+    // `depth` counts the user function calls currently being evaluated
+    fn eval<T : Number>(&self, x : &T, variables : &mut HashMap<String, T>, functions : &UserFunctions, depth : usize) -> Result<T, EvalError>{
+        let param_values : Vec<T> = match &self.function {
+            Function::Iterator(_) | Function::If | Function::Numeric(_) => vec![],
+            _ => self.params.iter().map(|e| e.eval(x, variables, functions, depth)).collect::<Result<_, _>>()?
         };
         match &self.function {
-            Function::SimpleFunction(f) => {
-                f(param_values[0])
+            Function::SimpleFunction(name, f) => {
+                match param_values.first() {
+                    Some(v) => Ok(T::simple(*name, *f, v.clone())),
+                    None => Err(EvalError::MissingArgument(self.span))
+                }
             },
-            Function::MultiFunction(f) => {
-                f(&param_values)
+            Function::MultiFunction(name, f) => {
+                Ok(T::multi(*name, *f, &param_values))
             },
             Function::Constant(c) => {
-                *c
+                Ok(T::from_f64(*c))
             }
             Function::Variable(s) => {
-                match variables.get(s) {
-                    Some(res) => *res,
-                    None => 0.0
-                }
+                Ok(variables.get(s).cloned().unwrap_or_else(|| unassigned(s)))
             }
             Function::InputX => {
-                x
+                Ok(x.clone())
             },
             Function::If => {
-                if self.params.len() < 3 { return f32::NAN; }
+                let [condition, if_true, if_false] = &self.params[..] else { return Err(EvalError::MissingArgument(self.span)); };
                 // Lazy evaluation
-                if self.params[0].evaluate(x, variables) > 0.0 { self.params[1].evaluate(x, variables) }
-                else{ self.params[2].evaluate(x, variables) }
+                match condition.eval(x, variables, functions, depth)?.is_true() {
+                    Some(true) => if_true.eval(x, variables, functions, depth),
+                    Some(false) => if_false.eval(x, variables, functions, depth),
+                    // Like an interval crossing the condition, values and assignments of both branches are kept
+                    None => {
+                        let mut true_variables = variables.clone();
+                        let true_value = if_true.eval(x, &mut true_variables, functions, depth)?;
+                        let false_value = if_false.eval(x, variables, functions, depth)?;
+                        for (name, value) in true_variables {
+                            let other = variables.get(&name).cloned().unwrap_or_else(|| unassigned(&name));
+                            variables.insert(name, value.union(other));
+                        }
+                        Ok(true_value.union(false_value))
+                    }
+                }
             },
             Function::Assign =>{
-                match &self.params[0].function {
-                    Function::Variable(s) => {variables.insert(s.to_string(), param_values[1]); },
-                    _ => {return f32::NAN;}
+                match (self.params.first().map(|p| &p.function), param_values.get(1)) {
+                    (Some(Function::Variable(s)), Some(value)) => {variables.insert(s.to_string(), value.clone()); Ok(value.clone())},
+                    _ => Err(EvalError::InvalidAssignment(self.span))
                 }
-                param_values[1]
             },
             Function::Iterator(o) =>{
-                match &self.params[0].function {
-                    Function::Assign => {
-                        match &self.params[0].params[0].function {
-                            Function::Variable(it) => {
-                                let mut intermediate_variables = variables.clone();
-
-                                let mut res = match o {
-                                    Operator::Add => 0.0,
-                                    Operator::Mul => 1.0,
-                                    _ => panic!("Internal error non authorised operation in iterator")
-                                };
-
-                                let max = self.params[1].evaluate(x, variables).round() as i32;
-                                let mut current = self.params[0].params[1].evaluate(x, variables).round() as i32;
-                                
-                                while current <= max {
-                                    intermediate_variables.insert(it.to_string(), current as f32);
-
-                                    res = match o {
-                                        Operator::Add => res + self.params[2].evaluate(x, &mut intermediate_variables),
-                                        Operator::Mul => res * self.params[2].evaluate(x, &mut intermediate_variables),
-                                        _ => panic!("Internal error non authorised operation in iterator")
-                                    };
-
-                                    current = current + 1;
-                                }
-                                res
-                            },  
-                            _ => {return f32::NAN;}
-                        }
-                    }
-                    _ => {return f32::NAN;} 
+                let [start, end, body] = &self.params[..] else { return Err(EvalError::InvalidIterator(self.span)); };
+                let (Function::Assign, [iterator, first]) = (&start.function, &start.params[..]) else { return Err(EvalError::InvalidIterator(start.span)); };
+                let Function::Variable(it) = &iterator.function else { return Err(EvalError::InvalidIterator(iterator.span)); };
+
+                let mut intermediate_variables = variables.clone();
+
+                let mut res = T::from_f64(match o {
+                    Operator::Add => 0.0,
+                    _ => 1.0
+                });
+
+                let Some(max) = end.eval(x, variables, functions, depth)?.to_bound() else { return Err(EvalError::UndeterminedBound(end.span)); };
+                let Some(mut current) = first.eval(x, variables, functions, depth)?.to_bound() else { return Err(EvalError::UndeterminedBound(first.span)); };
+                if max - current >= T::max_iterations() { return Err(EvalError::TooManyIterations(self.span)); }
+
+                while current <= max {
+                    intermediate_variables.insert(it.to_string(), T::from_f64(current as f64));
+
+                    res = match o {
+                        Operator::Add => res + body.eval(x, &mut intermediate_variables, functions, depth)?,
+                        _ => res * body.eval(x, &mut intermediate_variables, functions, depth)?,
+                    };
+
+                    current += 1;
+                }
+                Ok(res)
+            }
+            Function::Call(name) =>{
+                let Some(function) = functions.get(name) else { return Err(EvalError::UnknownFunction(self.span)); };
+                if function.params.len() != param_values.len() { return Err(EvalError::WrongArity(self.span)); }
+                if depth >= MAX_CALL_DEPTH { return Err(EvalError::RecursionTooDeep(self.span)); }
+
+                // Parameters are scoped like the iterator of a sum, a parameter named x replaces the input
+                let mut call_variables = variables.clone();
+                let mut call_x = x.clone();
+                for (param, value) in function.params.iter().zip(param_values) {
+                    if param == "x" { call_x = value; } else { call_variables.insert(param.clone(), value); }
+                }
+                // Spans inside the body point in the text of the definition, not of this expression
+                function.body.eval(&call_x, &mut call_variables, functions, depth + 1).map_err(|e| e.with_span(self.span))
+            }
+            Function::Numeric(method) => self.numeric(*method, x, variables, functions, depth)
+        }
+    }
+
+    // The other arguments are evaluated at x, the leading ones in double precision at the values of the bound variable the method needs.
+    // Assignments in the leading ones are not kept. Other number types get the result through `Number::from_numeric`.
+    fn numeric<T : Number>(&self, method : Numeric, x : &T, variables : &mut HashMap<String, T>, functions : &UserFunctions, depth : usize) -> Result<T, EvalError> {
+        let Some((bodies, bound, params)) = method.parts(&self.params) else {
+            return Err(if method.names_variable() { EvalError::InvalidVariable(self.span) } else { EvalError::MissingArgument(self.span) });
+        };
+        let params : Vec<f64> = params.iter().map(|p| p.eval(x, variables, functions, depth).map(|v| v.to_f64())).collect::<Result<_, _>>()?;
+        // An equation is solved as lhs - rhs = 0, written with = or ==, an expression as it is
+        let bodies : Vec<&Expression> = match (method, bodies) {
+            (Numeric::Solve, [equation]) => match (&equation.function, &equation.params[..]) {
+                (Function::Assign | Function::MultiFunction(Multi::Equal, _), [lhs, rhs]) => vec![lhs, rhs],
+                _ => vec![equation]
+            },
+            _ => bodies.iter().collect()
+        };
+        let values : HashMap<String, f64> = variables.iter().map(|(name, value)| (name.clone(), value.to_f64())).collect();
+        let mut scratch = values.clone();
+        let outer_x = x.to_f64();
+        let mut error = None;
+        let mut f = |body : usize, t : f64| {
+            let value = if bound == "x" {
+                bodies[body].eval(&t, &mut scratch, functions, depth)
+            } else {
+                scratch.insert(bound.to_string(), t);
+                bodies[body].eval(&outer_x, &mut scratch, functions, depth)
+            };
+            value.unwrap_or_else(|e| { error.get_or_insert(e); f64::NAN })
+        };
+        // The n-th one from the left, the first one by default
+        let nth = |xs : Vec<f64>, n : Option<&f64>| {
+            let n = n.copied().unwrap_or(1.0);
+            if n >= 1.0 && n.fract() == 0.0 { xs.get(n as usize - 1).copied().unwrap_or(f64::NAN) } else { f64::NAN }
+        };
+        let res = match (method, &params[..]) {
+            (Numeric::Roots, [a, b, n @ ..]) => nth(find_roots(|t| f(0, t), *a, *b).iter().map(|root| root.x).collect(), n.first()),
+            (Numeric::Intersect, [a, b, n @ ..]) => nth(find_roots(|t| f(0, t) - f(1, t), *a, *b).iter().map(|root| root.x).collect(), n.first()),
+            // Undefined somewhere on the interval gives NaN like sqrt(-1) does, an integral that does not settle is an error
+            (Numeric::Integral, [a, b]) => {
+                let mut undefined = false;
+                let integral = integrate(|t| { let y = f(0, t); undefined |= y.is_nan(); y }, *a, *b);
+                if !integral.converged && !undefined && !a.is_nan() && !b.is_nan() { return Err(EvalError::NotConverged(self.span)); }
+                if integral.converged { integral.value } else { f64::NAN }
+            },
+            // From the left for a negative side, the right for a positive one and both by default
+            (Numeric::Deriv | Numeric::Limit, [at, side @ ..]) => {
+                let side = match side.first() {
+                    Some(side) if *side < 0.0 => Side::Left,
+                    Some(side) if *side > 0.0 => Side::Right,
+                    _ => Side::Both
+                };
+                let found = if method == Numeric::Deriv { derivative(|t| f(0, t), *at) } else { limit(|t| f(0, t), *at, side) };
+                match found {
+                    Limit::Value(value) => value,
+                    Limit::Mismatch { .. } if error.is_none() => return Err(EvalError::OneSidedMismatch(self.span)),
+                    Limit::Diverges if error.is_none() => return Err(EvalError::NotConverged(self.span)),
+                    _ => f64::NAN
                 }
+            },
+            // The solution closest to the guess, 0 by default
+            (Numeric::Solve, guess) => {
+                let around = guess.first().copied().unwrap_or(0.0);
+                let degree = bodies.iter().map(|body| polynomial_degree(body, bound)).try_fold(0, |max, degree| degree.map(|d| d.max(max)));
+                let solutions = find_solutions(|t| if bodies.len() > 1 { f(0, t) - f(1, t) } else { f(0, t) }, degree, around);
+                solutions.closest(around)
+            },
+            _ => return Err(EvalError::MissingArgument(self.span))
+        };
+        match error {
+            Some(error) => Err(error),
+            None => {
+                let varies = variables.iter().any(|(name, value)| !value.is_constant() && reads(self, name));
+                Ok(T::from_numeric(res, x, |x| self.eval(&x, &mut values.clone(), functions, depth).unwrap_or(f64::NAN), varies))
             }
         }
     }
@@ -146,12 +699,54 @@ impl Expression{
     pub fn create_from_function(f : SimpleFunction) -> Expression {
         Expression {
             params: vec![],
-            function: Function::SimpleFunction(f),
+            function: Function::SimpleFunction(Simple::External, f),
+            span: Span::default(),
+        }
+    }
+
+    /// Part of the input this expression was parsed from
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    // Node made by a transformation of the tree, `span` is the part of the input it comes from
+    fn node(function : Function, params : Vec<Expression>, span : Span) -> Expression {
+        Expression { params, function, span }
+    }
+
+    fn constant(c : f64, span : Span) -> Expression {
+        Expression::node(Function::Constant(c), vec![], span)
+    }
+
+    fn operator(o : Operator, params : Vec<Expression>, span : Span) -> Expression {
+        // Only parenthesis and commas have no function
+        Expression::node(o.get_function().unwrap_or(Function::Constant(f64::NAN)), params, span)
+    }
+
+    fn builtin(name : &str, params : Vec<Expression>, span : Span) -> Expression {
+        Expression::node(function_from_string(name).unwrap_or(Function::Constant(f64::NAN)), params, span)
+    }
+
+    // Value of the expression when it is a constant
+    fn value(&self) -> Option<f64> {
+        match (&self.function, &self.params[..]) {
+            (Function::Constant(c), []) => Some(*c),
+            _ => None
+        }
+    }
+
+    // Name of the variable this expression is, x for the input
+    fn variable_name(&self) -> Option<&str> {
+        match &self.function {
+            Function::InputX => Some("x"),
+            Function::Variable(name) => Some(name),
+            _ => None
         }
     }
 }
 
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum Operator {
     Add,
     UAdd,
@@ -208,7 +803,7 @@ impl Operator {
             _ => None,
         }
     }
-    fn to_string(&self) -> &str {
+    fn to_string(self) -> &'static str {
         match self {
             Operator::Add | Operator::UAdd=> "+",
             Operator::Sub | Operator::USub => "-",
@@ -247,19 +842,23 @@ impl Operator {
         }
     }
 
-    fn get_function(&self) -> Function{
-        match self {
-            Operator::Add => Function::MultiFunction(|x| x[0] + x[1]),
-            Operator::Sub => Function::MultiFunction(|x| x[0] - x[1]),
-            Operator::Mul | Operator::Space => Function::MultiFunction(|x| x[0] * x[1]),
-            Operator::Div => Function::MultiFunction(|x| x[0] / x[1]),
-            Operator::Pow => Function::MultiFunction(|x| x[0].powf(x[1])),
-            Operator::Square => Function::SimpleFunction(|x| x * x),
+    fn get_function(&self) -> Option<Function>{
+        Some(match self {
+            Operator::Add => Function::MultiFunction(Multi::Add, |x| binary(x, |a, b| a + b)),
+            Operator::Sub => Function::MultiFunction(Multi::Sub, |x| binary(x, |a, b| a - b)),
+            Operator::Mul | Operator::Space => Function::MultiFunction(Multi::Mul, |x| binary(x, |a, b| a * b)),
+            Operator::Div => Function::MultiFunction(Multi::Div, |x| binary(x, |a, b| a / b)),
+            Operator::Pow => Function::MultiFunction(Multi::Pow, |x| binary(x, f32::powf)),
+            Operator::Square => Function::SimpleFunction(Simple::Square, |x| x * x),
             Operator::Assign => Function::Assign,
-            Operator::Factorial => Function::SimpleFunction(|x| {
+            Operator::Factorial => Function::SimpleFunction(Simple::Factorial, |x| {
                 if x < 0.0 {
                     return f32::NAN;
                 }
+                // 35! is already out of f32 range, no need to loop up to u32::MAX
+                if x >= 35.0 {
+                    return f32::INFINITY;
+                }
                 let mut result = 1.0;
                 let mut n = x as u32;
                 while n > 1 {
@@ -268,29 +867,101 @@ impl Operator {
                 }
                 result
             }),
-            Operator::UAdd => Function::SimpleFunction(|x| {x}),
-            Operator::USub => Function::SimpleFunction(|x| {-x}),
-            Operator::TestEQU => Function::MultiFunction(|x| if x[0] == x[1] {1.0} else {0.0}),
-            Operator::TestNEQ => Function::MultiFunction(|x| if x[0] != x[1] {1.0} else {0.0}),
-            Operator::TestLEQ => Function::MultiFunction(|x| if x[0] <= x[1] {1.0} else {0.0}),
-            Operator::TestLSS => Function::MultiFunction(|x| if x[0] <  x[1] {1.0} else {0.0}),
-            Operator::TestGEQ => Function::MultiFunction(|x| if x[0] >= x[1] {1.0} else {0.0}),
-            Operator::TestGTR => Function::MultiFunction(|x| if x[0] >  x[1] {1.0} else {0.0}),
+            Operator::UAdd => Function::SimpleFunction(Simple::Plus, |x| {x}),
+            Operator::USub => Function::SimpleFunction(Simple::Minus, |x| {-x}),
+            Operator::TestEQU => Function::MultiFunction(Multi::Equal, |x| binary(x, |a, b| if a == b {1.0} else {0.0})),
+            Operator::TestNEQ => Function::MultiFunction(Multi::NotEqual, |x| binary(x, |a, b| if a != b {1.0} else {0.0})),
+            Operator::TestLEQ => Function::MultiFunction(Multi::LessEqual, |x| binary(x, |a, b| if a <= b {1.0} else {0.0})),
+            Operator::TestLSS => Function::MultiFunction(Multi::Less, |x| binary(x, |a, b| if a <  b {1.0} else {0.0})),
+            Operator::TestGEQ => Function::MultiFunction(Multi::GreaterEqual, |x| binary(x, |a, b| if a >= b {1.0} else {0.0})),
+            Operator::TestGTR => Function::MultiFunction(Multi::Greater, |x| binary(x, |a, b| if a >  b {1.0} else {0.0})),
 
-            _ => panic!("No function for this operator"),
-        }
+            Operator::ParensOpen | Operator::ParensClose | Operator::Comma => return None,
+        })
     }
 }
 
 enum Token {
-    Operator(Operator),
-    Identifier(String),
+    Operator(Operator, Span),
+    Identifier(String, Span),
+}
+
+fn is_valid_name(s : &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_') && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+// End of the numeric literal starting at `start`, either decimal with an optional exponent,
+// 0x hexadecimal or 0b binary. `_` can separate two digits.
+fn scan_number(chars : &[char], start : usize) -> Result<usize, ParseError> {
+    let digits_end = |from : usize, is_digit : fn(&char) -> bool| {
+        let mut end = from;
+        loop {
+            if chars.get(end).is_some_and(is_digit) { end += 1; }
+            else if end > from && chars.get(end) == Some(&'_') && chars.get(end + 1).is_some_and(is_digit) { end += 2; }
+            else { return end; }
+        }
+    };
+    // 0x and 0b always start a prefix, 0x is not 0 * x
+    let radix_digit : Option<fn(&char) -> bool> = match (chars.get(start), chars.get(start + 1)) {
+        (Some('0'), Some('x' | 'X')) => Some(char::is_ascii_hexdigit),
+        (Some('0'), Some('b' | 'B')) => Some(|c| matches!(c, '0' | '1')),
+        _ => None
+    };
+
+    let end = match radix_digit {
+        Some(is_digit) => digits_end(start + 2, is_digit),
+        None => {
+            let mut end = digits_end(start, char::is_ascii_digit);
+            if chars.get(end) == Some(&'.') { end = digits_end(end + 1, char::is_ascii_digit); }
+            // Only an exponent when digits follow, 2e stays 2 * e
+            if matches!(chars.get(end), Some('e' | 'E')) {
+                let mut exponent = end + 1;
+                if matches!(chars.get(exponent), Some('+' | '-')) { exponent += 1; }
+                if chars.get(exponent).is_some_and(char::is_ascii_digit) { end = digits_end(exponent, char::is_ascii_digit); }
+            }
+            end
+        }
+    };
+
+    // A literal running into more digits, dots or separators is malformed, like 1.2.3, 1__0, 0b102 or 0x1FG,
+    // and so is a prefix without digits like 0x or 0xG
+    let mut malformed_end = end;
+    while chars.get(malformed_end).is_some_and(|c| c.is_ascii_digit() || *c == '.' || *c == '_' || (radix_digit.is_some() && c.is_alphanumeric())) {
+        malformed_end += 1;
+    }
+    if malformed_end > end || (radix_digit.is_some() && end == start + 2) {
+        return Err(ParseError::MalformedNumber(chars[start..malformed_end].iter().collect(), Span::new(start, malformed_end)));
+    }
+    Ok(end)
 }
 
-fn split_operator(s : &str) -> Vec<Token>{
+fn parse_number(s : &str) -> Option<f64> {
+    // Names like inf are still given to the standard parser
+    if !s.starts_with(|c : char| c.is_ascii_digit() || c == '.') { return s.parse().ok(); }
+    let digits = s.replace('_', "");
+    let radix = match digits.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0b" | "0B") => 2,
+        _ => return digits.parse().ok()
+    };
+    digits[2..].chars().try_fold(0f64, |value, c| c.to_digit(radix).map(|d| value * radix as f64 + d as f64))
+}
+
+// Plus and minus are unary unless following an operand or a postfix operator
+fn sign_operator(o : Operator, previous : Option<&Token>) -> Operator {
+    match (previous, o) {
+        (Some(Token::Identifier(_, _) | Token::Operator(Operator::ParensClose | Operator::Factorial | Operator::Square, _)), _) => o,
+        (_, Operator::Add) => Operator::UAdd,
+        (_, Operator::Sub) => Operator::USub,
+        _ => o
+    }
+}
+
+fn split_operator(s : &str) -> Result<Vec<Token>, ParseError>{
     let mut parts : Vec<Token> = vec![];
     let mut current_expr = String::new();
-    let mut is_numeric = true;
+    let mut current_start = 0;
     let mut index  = 0;
     let vec_chars: Vec<char> = s.chars().collect();
 
@@ -301,37 +972,34 @@ fn split_operator(s : &str) -> Vec<Token>{
 
                 // Finish last identfier
                 if !current_expr.is_empty() {
-                    parts.push(Token::Identifier(current_expr));
+                    parts.push(Token::Identifier(current_expr, Span::new(current_start, index)));
                     current_expr = String::new();
                 }
+                let span = Span::new(index, index + nb_char);
 
                 match op {
                     // Detect unary ops with same symbol
-                    o @ (Operator::Add | Operator::Sub) => {
-                        match parts.last() {
-                            None | Some(Token::Operator(Operator::Add | Operator::Sub | Operator::ParensOpen | Operator::Div | Operator::Mul | Operator::Pow)) => 
-                                parts.push(if matches!(o, Operator::Add) { Token::Operator(Operator::UAdd) } else {Token::Operator(Operator::USub) }),
-                            _ => parts.push(Token::Operator(o)) 
-                        }
-                    },
+                    o @ (Operator::Add | Operator::Sub) => parts.push(Token::Operator(sign_operator(o, parts.last()), span)),
                     // Convert spaces
                     Operator::Space => {},
                     
                     // Else just push operator
-                    _ => parts.push(Token::Operator(op))
+                    _ => parts.push(Token::Operator(op, span))
                 }
    
                 index += nb_char;
             }
-            None => {
-                if !(c.is_ascii_digit() || c == '.') {
-                    if !current_expr.is_empty() && is_numeric {
-                        parts.push(Token::Identifier(current_expr));
-                        parts.push(Token::Operator(Operator::Mul));
-                        current_expr = String::new();
-                    }
-                    is_numeric = false;
+            None if current_expr.is_empty() && (c.is_ascii_digit() || (c == '.' && vec_chars.get(index + 1).is_some_and(char::is_ascii_digit))) => {
+                let end = scan_number(&vec_chars, index)?;
+                parts.push(Token::Identifier(vec_chars[index..end].iter().collect(), Span::new(index, end)));
+                // Number directly followed by a name, like 2x
+                if end < vec_chars.len() && Operator::from_char(vec_chars[end], vec_chars.get(end + 1)).is_none() {
+                    parts.push(Token::Operator(Operator::Mul, Span::new(end, end)));
                 }
+                index = end;
+            }
+            None => {
+                if current_expr.is_empty() { current_start = index; }
                 current_expr.push(c);
                 index += 1;
             }
@@ -339,214 +1007,346 @@ fn split_operator(s : &str) -> Vec<Token>{
     }
 
     if !current_expr.is_empty() {
-        match parts.last() {
-            Some(Token::Operator(Operator::ParensClose)) => parts.push(Token::Operator(Operator::Mul)),
-            _ => ()
+        if let Some(Token::Operator(Operator::ParensClose, _)) = parts.last() {
+            parts.push(Token::Operator(Operator::Mul, Span::new(current_start, current_start)));
         }
-        parts.push(Token::Identifier(current_expr));
+        parts.push(Token::Identifier(current_expr, Span::new(current_start, vec_chars.len())));
     }
 
-    return parts;
+    Ok(parts)
 }
 
-fn add_implicit_mul(input : &mut Vec<Token>) -> Vec<Token>{
+// Number of arguments of the user functions callable from a parsed expression
+type Signatures = HashMap<String, usize>;
+
+fn add_implicit_mul(input : Vec<Token>, signatures : &Signatures) -> Vec<Token>{
     let mut out = Vec::with_capacity(input.len());
     let mut last_is_potential = false;
-    while !input.is_empty() {
-        match &input[0] {
-            Token::Identifier(_) | Token::Operator(Operator::ParensOpen) if last_is_potential => out.push(Token::Operator(Operator::Mul)),
+    for token in input {
+        match &token {
+            Token::Identifier(_, span) | Token::Operator(Operator::ParensOpen, span) if last_is_potential => out.push(Token::Operator(Operator::Mul, Span::new(span.start, span.start))),
             _ => ()
         }
 
-        match &input[0]{
-            Token::Operator(Operator::ParensClose) => last_is_potential = true,
-            Token::Identifier(s) => last_is_potential = matches!(function_from_string(&s), None),
+        match &token{
+            Token::Operator(Operator::ParensClose, _) => last_is_potential = true,
+            Token::Identifier(s, _) => last_is_potential = builtin_from_string(s).is_none() && !signatures.contains_key(s),
             _ => last_is_potential = false,
         }
-        out.push(input.remove(0));
+        out.push(token);
     }
     out
 }
 
 
-// Shunting yard algorithm
-fn filter_tokens_priority(input : &mut Vec<Token>) -> Result<Vec<Token>, String>{
+// Shunting yard algorithm, the output is in reverse polish notation
+fn filter_tokens_priority(input : Vec<Token>) -> Result<Vec<Token>, ParseError>{
     let mut out = Vec::with_capacity(input.len());
     let mut operator_queue : Vec<Token> = Vec::new();
+    let mut input = input.into_iter().peekable();
 
-    while !input.is_empty() {
-        match &input[0] {
-            Token::Operator(Operator::ParensOpen) => {
-                operator_queue.push(input.remove(0));
+    while let Some(token) = input.next() {
+        match token {
+            Token::Operator(Operator::ParensOpen, _) => {
+                operator_queue.push(token);
             },
-            Token::Operator(Operator::ParensClose) => {
-                while !operator_queue.is_empty() {
-                    match &operator_queue.last().unwrap(){
-                        Token::Operator(Operator::ParensOpen)=> {break;}
-                        Token::Operator(_) => {
-                                out.push(operator_queue.pop().unwrap());
-                        }
-                        Token::Identifier(_) => panic!("Internal error"),
-                    }
+            Token::Operator(Operator::ParensClose, close_span) => {
+                while let Some(Token::Operator(o, _)) = operator_queue.last() {
+                    if matches!(o, Operator::ParensOpen) { break; }
+                    out.push(operator_queue.pop().unwrap());
                 }
-                if operator_queue.is_empty() { return Err("Unbalanced ')'".to_string())}
+                if !matches!(operator_queue.last(), Some(Token::Operator(Operator::ParensOpen, _))) { return Err(ParseError::UnbalancedParen(')', close_span))}
                 operator_queue.pop();
 
-                input.remove(0);
-
-                match &operator_queue.last() {
-                    Some(Token::Identifier(_)) => {out.push(Token::Operator(Operator::ParensOpen)); out.push(operator_queue.pop().unwrap());},
-                    _ => ()
+                // The parenthesis marker of a function call spans its closing parenthesis
+                if let Some(Token::Identifier(_, _)) = &operator_queue.last() {
+                    out.push(Token::Operator(Operator::ParensOpen, close_span));
+                    out.push(operator_queue.pop().unwrap());
                 }
             },
-            Token::Operator(Operator::Comma) => {
-                while !operator_queue.is_empty() {
-                    match &operator_queue.last().unwrap(){
-                        Token::Operator(Operator::ParensOpen)=> {break;}
-                        Token::Operator(_) => {
-                                out.push(operator_queue.pop().unwrap());
-                        }
-                        Token::Identifier(_) => panic!("Internal error"),
-                    }
+            Token::Operator(Operator::Comma, comma_span) => {
+                while let Some(Token::Operator(o, _)) = operator_queue.last() {
+                    if matches!(o, Operator::ParensOpen) { break; }
+                    out.push(operator_queue.pop().unwrap());
                 }
-                if !matches!(operator_queue.last(), Some(Token::Operator(Operator::ParensOpen))) { return Err("Expected '(' before ','".to_string());}
-                out.push(input.remove(0))
+                if !matches!(operator_queue.last(), Some(Token::Operator(Operator::ParensOpen, _))) { return Err(ParseError::UnexpectedOperator(",".to_string(), comma_span));}
+                out.push(token)
             }
-            Token::Operator(o1) => {
-                while !operator_queue.is_empty() {
-                    match &operator_queue.last().unwrap(){
-                        Token::Operator(Operator::ParensOpen | Operator::Comma)=> {break;}
-                        Token::Operator(o2) => {
-                            if o2.precedence() >= o1.precedence() {
-                                out.push(operator_queue.pop().unwrap());
-                            }else{
-                                break;
-                            }
-                        }
-                        Token::Identifier(_) => panic!("Internal error"),
-                    }
+            Token::Operator(ref o1, _) => {
+                // A prefix operator has no operand on its left, nothing to pop
+                let is_prefix = matches!(o1, Operator::UAdd | Operator::USub);
+                while let Some(Token::Operator(o2, _)) = operator_queue.last() {
+                    if is_prefix || matches!(o2, Operator::ParensOpen | Operator::Comma) || o2.precedence() < o1.precedence() { break; }
+                    out.push(operator_queue.pop().unwrap());
                 }
-                operator_queue.push(input.remove(0));
+                operator_queue.push(token);
             },
-            Token::Identifier(_) => {
-                match input.get(1) {
-                    Some(Token::Operator(Operator::ParensOpen)) => {
+            Token::Identifier(_, _) => {
+                match input.next_if(|next| matches!(next, Token::Operator(Operator::ParensOpen, _))) {
+                    Some(parens) => {
+                        // Arguments of a call start with a closing parenthesis marker so they are not mixed with
+                        // the arguments of an enclosing call
+                        if let Token::Operator(_, open_span) = &parens { out.push(Token::Operator(Operator::ParensClose, *open_span)); }
                         // Function add id both to operator queue
-                        operator_queue.push(input.remove(0));
-                        operator_queue.push(input.remove(0));
+                        operator_queue.push(token);
+                        operator_queue.push(parens);
                     }
-                    _ => out.push(input.remove(0))
+                    None => out.push(token)
                 }
             },
         }
     }
-    while !operator_queue.is_empty() {
-        match operator_queue.last().unwrap(){
-            Token::Operator(Operator::ParensOpen) => {return Err("Unbalanced '('".to_string());},
-            Token::Operator(Operator::Comma) => {return Err("Unexpected ','".to_string());},
+    while let Some(last) = operator_queue.last() {
+        match last {
+            Token::Operator(Operator::ParensOpen, span) => {return Err(ParseError::UnbalancedParen('(', *span));},
+            Token::Operator(Operator::Comma, span) => {return Err(ParseError::UnexpectedOperator(",".to_string(), *span));},
             _ => ()
         }
         out.push(operator_queue.pop().unwrap());
     }
     
-    out.reverse();
     Ok(out)
 }
 
-fn match_expression(tokens : &mut Vec<Token>) -> Result<Expression, String>{
-    if tokens.is_empty() {return Err("Expression was expected but none found".to_string()); }
-    match tokens.remove(0) {
-        Token::Identifier(s) => { 
+// Builds the tree by consuming the reverse polish notation from its end.
+// `parent` is the span of the token needing this expression, used to locate missing arguments
+fn match_expression(tokens : &mut Vec<Token>, signatures : &Signatures, functions : &UserFunctions, parent : Span, depth : usize) -> Result<Expression, ParseError>{
+    if depth > MAX_DEPTH {return Err(ParseError::NestingTooDeep(parent)); }
+    let Some(token) = tokens.pop() else { return Err(ParseError::MissingArgument(parent)); };
+    match token {
+        Token::Identifier(s, span) => { 
             let func = match function_from_string(&s) {
+                None if signatures.contains_key(&s) => Function::Call(s.clone()),
                 None => {
                     if s == "x" { Function::InputX
                     }else{ 
-                        match s.parse() {
-                            Ok(res) => Function::Constant(res),
-                            Err(_) => Function::Variable(s.clone())
+                        match parse_number(&s) {
+                            Some(res) => Function::Constant(res),
+                            None if is_valid_name(&s) => Function::Variable(s.clone()),
+                            None => return Err(ParseError::UnknownIdentifier(s, span))
                         } 
                     }
                 },
                 Some(function) => {function}
             };
-            match &tokens.get(0) {
-                Some(Token::Operator(Operator::ParensOpen)) => {
-                    tokens.remove(0);
-                    match func {
-                        Function::Assign | Function::Constant(_) | Function::Variable(_) | Function::InputX => Ok(Expression{function: func, params: vec![]}),
-                        Function::SimpleFunction(_) => Ok(Expression { function: func, params: vec![match_expression(tokens)?] }),
-                        Function::MultiFunction(_) | Function::Iterator(_) | Function::If => {
-                            let mut params : Vec<Expression> = vec![match_expression(tokens)?];
-                            loop{
-                                match tokens.get(0) {
-                                    Some(Token::Operator(Operator::Comma)) => {tokens.remove(0); params.insert(0, match_expression(tokens)?);},
-                                    _ => break,
-                                };
-                            }
-                            Ok(Expression { function: func, params : params})
+            match tokens.last() {
+                Some(Token::Operator(Operator::ParensOpen, close_span)) => {
+                    let span = span.merge(*close_span);
+                    tokens.pop();
+                    let mut params : Vec<Expression> = vec![];
+                    // The arguments end at the closing parenthesis marker, right away for an empty call
+                    if let Some(Token::Operator(Operator::ParensClose, _)) = tokens.last() {
+                        tokens.pop();
+                    }else{
+                        // Arguments come last to first
+                        params.push(match_expression(tokens, signatures, functions, span, depth + 1)?);
+                        while let Some(Token::Operator(Operator::Comma, comma_span)) = tokens.last() {
+                            let comma_span = *comma_span;
+                            tokens.pop();
+                            params.push(match_expression(tokens, signatures, functions, comma_span, depth + 1)?);
                         }
+                        params.reverse();
+                        if let Some(Token::Operator(Operator::ParensClose, _)) = tokens.last() {
+                            tokens.pop();
+                        }
+                    }
+                    if let Some(builtin) = builtin_from_string(&s)
+                        && !(builtin.min_arity..=builtin.max_arity).contains(&params.len()) {
+                        return Err(ParseError::WrongArity(builtin.name.to_string(), builtin.min_arity, builtin.max_arity, params.len(), span));
+                    }
+                    if let Some(arity) = signatures.get(&s)
+                        && params.len() != *arity {
+                        return Err(ParseError::WrongArity(s, *arity, *arity, params.len(), span));
                     }
+                    // Derivatives are taken while parsing, what is left is a plain expression
+                    if let (Some("d"), [expression, variable]) = (builtin_from_string(&s).map(|b| b.name), &params[..]) {
+                        let Some(var) = variable.variable_name() else { return Err(ParseError::ExpectedVariable(variable.span)); };
+                        return derive::try_derive(expression, var, functions).ok_or(ParseError::TooLarge(span));
+                    }
+                    Ok(Expression { function: func, params, span })
                 }
                 _ => { 
                     match func {
-                        Function::MultiFunction(_) | Function::SimpleFunction(_) | Function::Iterator(_) | Function::If => Err(format!("Error unexpected identifier : {}", s)),
-                        Function::Assign |Function::Constant(_) | Function::Variable(_) | Function::InputX => Ok(Expression{function : func, params: vec![]}),
+                        Function::Variable(_) if builtin_from_string(&s).is_some_and(|builtin| builtin.max_arity > 0) => Err(ParseError::UnexpectedIdentifier(s, span)),
+                        Function::MultiFunction(_, _) | Function::SimpleFunction(_, _) | Function::Iterator(_) | Function::If | Function::Call(_) | Function::Numeric(_) => Err(ParseError::UnexpectedIdentifier(s, span)),
+                        Function::Assign |Function::Constant(_) | Function::Variable(_) | Function::InputX => Ok(Expression{function : func, params: vec![], span}),
                     }
                 }
             }
         },
-        Token::Operator(o @ (Operator::Factorial | Operator::Square)) => {
-            Ok(Expression{function : o.get_function(), params: vec![match_expression(tokens)?]})
-        },
-        Token::Operator(o @ (Operator::USub | Operator::UAdd)) => { 
-            Ok(Expression{function: o.get_function(), params: vec![match_expression(tokens)?]})
-        },
-        Token::Operator(Operator::ParensOpen) => { 
-            panic!("Internal error, Parenthesis should have been removed");   
+        Token::Operator(o @ (Operator::Factorial | Operator::Square | Operator::USub | Operator::UAdd), span) => {
+            let Some(function) = o.get_function() else { return Err(ParseError::UnexpectedOperator(o.to_string().to_string(), span)); };
+            let e = match_expression(tokens, signatures, functions, span, depth + 1)?;
+            Ok(Expression{function, span: span.merge(e.span), params: vec![e]})
         },
-        Token::Operator(o @ (Operator::Comma | Operator::ParensClose)) => {
-            Err(format!("Unwaited operator : {}", o.to_string()))
-        }
-        Token::Operator(o) =>{
-            let e2 = match_expression(tokens)?;
-            Ok(Expression{function: o.get_function(), params: vec![match_expression(tokens)?, e2]})
+        Token::Operator(o, span) =>{
+            // Parenthesis and commas have no function, they should all have been consumed
+            let Some(function) = o.get_function() else { return Err(ParseError::UnexpectedOperator(o.to_string().to_string(), span)); };
+            let e2 = match_expression(tokens, signatures, functions, span, depth + 1)?;
+            let e1 = match_expression(tokens, signatures, functions, span, depth + 1)?;
+            Ok(Expression{function, span: e1.span.merge(e2.span), params: vec![e1, e2]})
         }
     }
 
 }
 
-pub fn parse_expression(s : &str) -> Result<Expression, String>{
-    let mut tokens = split_operator(s);
-    let mut tokens_implicit_mul = add_implicit_mul(&mut tokens);
-    let mut filtered = filter_tokens_priority(&mut tokens_implicit_mul)?;
-    let expression = match_expression(&mut filtered)?;
+fn parse_tokens(tokens : Vec<Token>, signatures : &Signatures, functions : &UserFunctions, parent : Span) -> Result<Expression, ParseError>{
+    let tokens_implicit_mul = add_implicit_mul(tokens, signatures);
+    let mut filtered = filter_tokens_priority(tokens_implicit_mul)?;
+    let expression = match_expression(&mut filtered, signatures, functions, parent, 0)?;
     match filtered.last() {
         None => Ok(expression),
-        Some(Token::Identifier(s)) => Err(format!("Expected EOF but got : {}", s)),
-        Some(Token::Operator(o)) => Err(format!("Expected EOF but got : {}", o.to_string()))
+        Some(Token::Identifier(s, span)) => Err(ParseError::TrailingToken(s.clone(), *span)),
+        Some(Token::Operator(o, span)) => Err(ParseError::TrailingToken(o.to_string().to_string(), *span))
+    }
+}
+
+pub fn parse_expression(s : &str) -> Result<Expression, ParseError>{
+    parse_tokens(split_operator(s)?, &Signatures::new(), &UserFunctions::new(), Span::default())
+}
+
+/// Same as parse_expression followed by simplify
+pub fn parse_simplified(s : &str) -> Result<Expression, ParseError>{
+    parse_expression(s).map(|e| simplify(&e))
+}
+
+/// Parses a LaTeX formula like `\frac{1}{2}\rho v^{2}` to the same expression as its plain form `1/2*rho*v^2`
+pub fn parse_latex(s : &str) -> Result<Expression, ParseError>{
+    parse_latex_with_functions(s, &UserFunctions::new())
+}
+
+/// Same as parse_latex with the user functions callable, like `f\left(x\right)`
+pub fn parse_latex_with_functions(s : &str, functions : &UserFunctions) -> Result<Expression, ParseError>{
+    let signatures : Signatures = functions.iter().map(|(name, function)| (name.clone(), function.params.len())).collect();
+    parse_tokens(latex_parser::split_latex(s, &signatures)?, &signatures, functions, Span::default())
+}
+
+// Head of a definition like `f(x, y) = ...`
+struct Definition {
+    name : String,
+    params : Vec<(String, Span)>,
+    // Span of `f(x, y)`
+    span : Span,
+    // Span of `=`, the body follows it
+    assign_span : Span,
+    body_start : usize,
+}
+
+fn match_definition(tokens : &[Token]) -> Option<Definition> {
+    let Some(Token::Identifier(name, name_span)) = tokens.first() else { return None; };
+    if !is_valid_name(name) || name == "x" || builtin_from_string(name).is_some() { return None; }
+    if !matches!(tokens.get(1), Some(Token::Operator(Operator::ParensOpen, _))) { return None; }
+
+    let mut params = vec![];
+    let mut index = 2;
+    loop {
+        match tokens.get(index) {
+            Some(Token::Identifier(param, span)) if is_valid_name(param) => params.push((param.clone(), *span)),
+            _ => return None
+        }
+        match tokens.get(index + 1) {
+            Some(Token::Operator(Operator::Comma, _)) => index += 2,
+            Some(Token::Operator(Operator::ParensClose, close_span)) => {
+                let Some(Token::Operator(Operator::Assign, assign_span)) = tokens.get(index + 2) else { return None; };
+                return Some(Definition { name : name.clone(), params, span : name_span.merge(*close_span), assign_span : *assign_span, body_start : index + 3 });
+            },
+            _ => return None
+        }
     }
 }
 
-pub fn test_filter(s: String, variables : &mut HashMap<String, f32>) {
-    let mut tokens = split_operator(&s);
-    let mut tokens_implicit_mul = add_implicit_mul(&mut tokens);
-    let res_parsing = filter_tokens_priority(&mut tokens_implicit_mul);
+/// Parses `s` with the user functions, storing the function when `s` defines one like `f(x) = x^2`.
+/// A definition gives back a call of the function with x as first argument so it can be plotted.
+pub fn parse_with_functions(s : &str, functions : &mut UserFunctions) -> Result<Expression, ParseError>{
+    let mut tokens = split_operator(s)?;
+    let mut signatures : Signatures = functions.iter().map(|(name, function)| (name.clone(), function.params.len())).collect();
+    let Some(definition) = match_definition(&tokens) else { return parse_tokens(tokens, &signatures, functions, Span::default()); };
+
+    for (i, (param, span)) in definition.params.iter().enumerate() {
+        if definition.params[..i].iter().any(|(other, _)| other == param) {
+            return Err(ParseError::UnexpectedIdentifier(param.clone(), *span));
+        }
+    }
+
+    // Known before parsing the body so the function can call itself
+    signatures.insert(definition.name.clone(), definition.params.len());
+    let body = parse_tokens(tokens.split_off(definition.body_start), &signatures, functions, definition.assign_span)?;
+
+    let call_params = definition.params.iter().enumerate().map(|(i, (param, span))| Expression {
+        function : if i == 0 { Function::InputX } else { Function::Variable(param.clone()) },
+        params : vec![],
+        span : *span
+    }).collect();
+    let params = definition.params.into_iter().map(|(param, _)| param).collect();
+    functions.insert(definition.name.clone(), UserFunction { params, body });
+
+    Ok(Expression { function : Function::Call(definition.name), params : call_params, span : definition.span })
+}
+
+/// Prints the input with the erroneous part underlined
+fn print_underlined(s : &str, span : Span) {
+    println!("  {}", s);
+    println!("  {}{}", " ".repeat(span.start), "^".repeat((span.end - span.start).max(1)));
+}
+
+pub fn test_filter(s: String, variables : &mut HashMap<String, f32>, functions : &mut UserFunctions) {
+    let mut signatures : Signatures = functions.iter().map(|(name, function)| (name.clone(), function.params.len())).collect();
+    let mut defined = None;
+    let res_parsing = split_operator(&s).and_then(|mut tokens| match match_definition(&tokens) {
+        // Only the body of a definition goes through the shunting yard
+        Some(definition) => {
+            defined = Some(definition.name.clone());
+            signatures.insert(definition.name, definition.params.len());
+            filter_tokens_priority(add_implicit_mul(tokens.split_off(definition.body_start), &signatures))
+        },
+        None => filter_tokens_priority(add_implicit_mul(tokens, &signatures))
+    });
     match res_parsing {
-        Err(e) => print!("Parsing failed with : {}", e),
+        Err(e) => {
+            println!("Parsing failed with : {}", e);
+            print_underlined(&s, e.span());
+        },
         Ok(new_tokens) =>{
             let mut res : String = String::new();
-            for t in new_tokens.iter() {
+            for t in new_tokens.iter().rev() {
                 match t {
-                    Token::Operator(operator) => res += &operator.to_string(),
-                    Token::Identifier(s) => res += &s,
+                    Token::Operator(operator, _) => res += operator.to_string(),
+                    Token::Identifier(s, _) => res += s,
                 }
                 res += ";";
             }
             println!("Parsed           : {}", res);
-            println!("Evaluated (30.0): {}", match parse_expression(&s){
-                Ok(expression) => expression.evaluate(30.0, variables).to_string(),
-                Err(e) => e
-            })
+            match parse_with_functions(&s, functions){
+                Ok(expression) => {
+                    match defined.as_ref().and_then(|name| functions.get(name).map(|function| (name, function))) {
+                        Some((name, function)) => println!("Understood       : {}({}) = {}", name, function.params.join(", "), function.body),
+                        None => println!("Understood       : {}", expression)
+                    }
+                    match expression.try_evaluate_with(30.0, variables, functions) {
+                        Ok(value) => println!("Evaluated (30.0): {}", value),
+                        Err(e) => {
+                            println!("Evaluated (30.0): {}", e);
+                            print_underlined(&s, e.span());
+                        }
+                    }
+                    // Variables hold the decimal they are shown with
+                    let mut exact_variables = variables.iter()
+                        .map(|(name, value)| (name.clone(), Rational::from_decimal(&value.to_string()).map_or(Exact::Float(*value as f64), Exact::Rational)))
+                        .collect();
+                    if let Ok(Exact::Rational(value)) = expression.try_evaluate_exact_with(Exact::from_f64(30.0), &mut exact_variables, functions) {
+                        println!("Exact (30)      : {}", value);
+                    }
+                    let mut complex_variables = variables.iter().map(|(name, value)| (name.clone(), Complex::real(*value as f64))).collect();
+                    if let Ok(value) = expression.try_evaluate_complex_with(Complex::real(30.0), &mut complex_variables, functions)
+                        && !value.is_real() {
+                        println!("Complex (30)    : {}", value);
+                    }
+                },
+                Err(e) => {
+                    println!("Evaluated (30.0): {}", e);
+                    print_underlined(&s, e.span());
+                }
+            }
         }
     }
 
@@ -558,7 +1358,9 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_parsing() -> Result<(), String> {
+    // Clippy would write the baseline assertions below with is_err
+    #[allow(clippy::redundant_pattern_matching)]
+    fn test_parsing() -> Result<(), ParseError> {
         parse_expression("4*5")?;
         parse_expression("sin(x)")?;
         parse_expression("sin( x)")?;
@@ -598,4 +1400,835 @@ mod tests {
         println!("Errorring ok");
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_error_spans() {
+        assert_eq!(parse_expression("max(4").err(), Some(ParseError::UnbalancedParen('(', Span::new(3, 4))));
+        assert_eq!(parse_expression("4+5)").err(), Some(ParseError::UnbalancedParen(')', Span::new(3, 4))));
+        assert_eq!(parse_expression("2*exp").err(), Some(ParseError::UnexpectedIdentifier("exp".to_string(), Span::new(2, 5))));
+        assert_eq!(parse_expression("1+$").err(), Some(ParseError::UnknownIdentifier("$".to_string(), Span::new(2, 3))));
+        assert_eq!(parse_expression("sin()").err(), Some(ParseError::WrongArity("sin".to_string(), 1, 1, 0, Span::new(0, 5))));
+        assert_eq!(parse_expression("1 + ").err(), Some(ParseError::MissingArgument(Span::new(2, 3))));
+        assert_eq!(parse_expression("").err(), Some(ParseError::MissingArgument(Span::new(0, 0))));
+        assert!(matches!(parse_expression("(5,5)"), Err(ParseError::TrailingToken(_, _))));
+
+        // Spans are char offsets, not bytes
+        assert_eq!(parse_expression("2² + é$").err(), Some(ParseError::UnknownIdentifier("é$".to_string(), Span::new(5, 7))));
+        assert_eq!(parse_expression("max(1, 2) + x").map(|e| e.span()), Ok(Span::new(0, 13)));
+    }
+
+    #[test]
+    fn test_signs() -> Result<(), ParseError> {
+        assert_eq!(parse_expression("max(-5,-2)")?.simple_evaluate(0.0), -2.0);
+        assert_eq!(parse_expression("a=-3")?.simple_evaluate(0.0), -3.0);
+        assert_eq!(parse_expression("--3")?.simple_evaluate(0.0), 3.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_panic() -> Result<(), ParseError> {
+        let mut variables = HashMap::new();
+        assert_eq!(parse_expression("sum(1,5,i)")?.try_evaluate(0.0, &mut variables), Err(EvalError::InvalidIterator(Span::new(4, 5))));
+        assert_eq!(parse_expression("4=5")?.try_evaluate(0.0, &mut variables), Err(EvalError::InvalidAssignment(Span::new(0, 3))));
+        assert!(matches!(parse_expression("sum(i=-2147483648,2147483647,i)")?.try_evaluate(0.0, &mut variables), Err(EvalError::TooManyIterations(_))));
+        assert_eq!(parse_expression("(10^30)!")?.simple_evaluate(0.0), f32::INFINITY);
+        assert!(matches!(parse_expression(&("-".repeat(1000) + "x")), Err(ParseError::NestingTooDeep(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_arity() -> Result<(), ParseError> {
+        let message = |s : &str| parse_expression(s).err().map(|e| e.to_string());
+        assert_eq!(message("pow(2)"), Some("pow expects 2 arguments, got 1".to_string()));
+        assert_eq!(message("max()"), Some("max expects at least 1 argument, got 0".to_string()));
+        assert_eq!(message("if(1,2)"), Some("if expects 3 arguments, got 2".to_string()));
+        assert_eq!(message("sum(i=1,5)"), Some("sum expects 3 arguments, got 2".to_string()));
+        assert_eq!(message("sin(1,2)"), Some("sin expects 1 argument, got 2".to_string()));
+        assert_eq!(message("pi(2)"), Some("pi expects 0 arguments, got 1".to_string()));
+        // An empty call must not take the operand of the surrounding expression
+        assert_eq!(parse_expression("1+max()").err().map(|e| e.span()), Some(Span::new(2, 7)));
+
+        assert_eq!(parse_expression("max(1,2,3)")?.simple_evaluate(0.0), 3.0);
+        assert_eq!(parse_expression("pow(2,3)")?.simple_evaluate(0.0), 8.0);
+        // A call after a comma keeps its own arguments
+        assert_eq!(parse_expression("max(1, pow(2, 3), 2)")?.simple_evaluate(0.0), 8.0);
+        assert_eq!(builtin_from_string("POW").map(|b| b.signature), Some("pow(base, exponent)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_numeric_literals() -> Result<(), ParseError> {
+        assert_eq!(parse_expression("1e-3")?.simple_evaluate(0.0), 1e-3);
+        assert_eq!(parse_expression("2.5E+5")?.simple_evaluate(0.0), 2.5e5);
+        assert_eq!(parse_expression("0x1F")?.simple_evaluate(0.0), 31.0);
+        assert_eq!(parse_expression("0b101")?.simple_evaluate(0.0), 5.0);
+        assert_eq!(parse_expression("1_000_000")?.simple_evaluate(0.0), 1e6);
+        assert_eq!(parse_expression(".5")?.simple_evaluate(0.0), 0.5);
+        // Without exponent digits e stays Euler's constant
+        assert_eq!(parse_expression("2e")?.simple_evaluate(0.0), 2.0 * f32::consts::E);
+        assert_eq!(parse_expression("2e3x")?.simple_evaluate(2.0), 4000.0);
+        assert_eq!(parse_expression("0 x")?.simple_evaluate(2.0), 0.0);
+        assert_eq!(parse_expression("1_0.2_5e1_0")?.simple_evaluate(0.0), 10.25e10);
+
+        assert_eq!(parse_expression("1.2.3").err(), Some(ParseError::MalformedNumber("1.2.3".to_string(), Span::new(0, 5))));
+        assert_eq!(parse_expression("2+0b102").err(), Some(ParseError::MalformedNumber("0b102".to_string(), Span::new(2, 7))));
+        assert!(matches!(parse_expression("0x1Fg"), Err(ParseError::MalformedNumber(_, _))));
+        // A prefix needs digits
+        assert_eq!(parse_expression("0x").err(), Some(ParseError::MalformedNumber("0x".to_string(), Span::new(0, 2))));
+        assert_eq!(parse_expression("0b+1").err(), Some(ParseError::MalformedNumber("0b".to_string(), Span::new(0, 2))));
+        assert_eq!(parse_expression("0xG").err(), Some(ParseError::MalformedNumber("0xG".to_string(), Span::new(0, 3))));
+        assert_eq!(parse_expression("0b2").err(), Some(ParseError::MalformedNumber("0b2".to_string(), Span::new(0, 3))));
+        assert_eq!(parse_expression("0x_1").err(), Some(ParseError::MalformedNumber("0x_1".to_string(), Span::new(0, 4))));
+        // `_` only goes between two digits
+        assert_eq!(parse_expression("1_").err(), Some(ParseError::MalformedNumber("1_".to_string(), Span::new(0, 2))));
+        assert_eq!(parse_expression("1__0").err(), Some(ParseError::MalformedNumber("1__0".to_string(), Span::new(0, 4))));
+        assert_eq!(parse_expression("1_.5").err(), Some(ParseError::MalformedNumber("1_.5".to_string(), Span::new(0, 4))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_functions() -> Result<(), ParseError> {
+        let mut functions = UserFunctions::new();
+        let mut variables = HashMap::new();
+        let mut evaluate = |s : &str, functions : &mut UserFunctions| parse_with_functions(s, functions).map(|e| e.try_evaluate_with(0.0, &mut variables, functions));
+
+        // A definition gives back a call of the function on x
+        assert_eq!(parse_with_functions("f(x) = x^2 + 1", &mut functions)?.evaluate_with(3.0, &mut HashMap::new(), &functions), 10.0);
+        assert_eq!(evaluate("g(x) = f(x-1)*2", &mut functions)?, Ok(4.0));
+        assert_eq!(evaluate("g(3)", &mut functions)?, Ok(10.0));
+        assert_eq!(evaluate("h(a, b) = a*b", &mut functions)?, Ok(0.0));
+        assert_eq!(evaluate("h(2, 3) + f(2)", &mut functions)?, Ok(11.0));
+        assert_eq!(evaluate("fact(n) = if(n > 0, n*fact(n-1), 1)", &mut functions)?, Ok(1.0));
+        assert_eq!(evaluate("fact(5)", &mut functions)?, Ok(120.0));
+
+        // Redefining changes the callers
+        evaluate("f(x) = x", &mut functions)?.ok();
+        assert_eq!(evaluate("g(3)", &mut functions)?, Ok(4.0));
+
+        assert_eq!(evaluate("loop(x) = loop(x)", &mut functions)?, Err(EvalError::RecursionTooDeep(Span::new(0, 7))));
+        assert_eq!(evaluate("1 + loop(2)", &mut functions)?, Err(EvalError::RecursionTooDeep(Span::new(4, 11))));
+        assert_eq!(parse_with_functions("f(1, 2)", &mut functions).err().map(|e| e.to_string()), Some("f expects 1 argument, got 2".to_string()));
+        assert!(matches!(parse_with_functions("k(t, t) = t", &mut functions), Err(ParseError::UnexpectedIdentifier(_, _))));
+        // Without the functions `f(x)` is still f * x
+        assert_eq!(parse_expression("f(3)")?.simple_evaluate(0.0), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compiled() -> Result<(), ParseError> {
+        let mut functions = UserFunctions::new();
+        for definition in ["f(x) = x^2 + 1", "h(a, b) = a*b + x", "g(t) = t + f(t) + (b = t)", "fact(n) = if(n > 0, n*fact(n-1), 1)", "s(x) = sum(i=1, x, i)"] {
+            parse_with_functions(definition, &mut functions)?;
+        }
+        let inputs = [
+            "4*5", "-x^2 + 3x - 1", "max(x, 2, -x) + min(1)", "sin(x)cos(x) + sqrt(abs(x))", "x!", "x²", "pi*e",
+            "if(x > 0, ln(x), exp(x))", "if(x == 1, 1, if(x != 2, 2, 3))", "x >= 1 + (x < 2)",
+            "sum(i=1, 10, x^i/i!)", "prod(i=1, x, i)", "sum(i=1, 3, sum(j=i, 3, i*j))", "sum(i=x, 1, i)", "sum(i=1, 3, a = a + i) + a",
+            "a = 2", "a = a + x", "b = (a = 3) + a", "sum(i = (c = 5), c + 1, c)", "c + a",
+            "f(x) + h(2, 3) + g(x)", "b", "s(x)", "h(x, f(x))",
+            "sum(1, 5, i)", "4 = 5", "1 + sum(i=-2147483648, 2147483647, i)", "if(x > 0, 1, 4 = 5)", "(a = 7) + (4 = 5)", "a",
+        ];
+        let mut tree_variables = HashMap::new();
+        let mut compiled_variables = HashMap::new();
+        for s in inputs {
+            let expression = parse_with_functions(s, &mut functions)?;
+            let program = expression.compile(&functions).expect("not recursive");
+            for x in [-2.0, 0.0, 0.5, 1.0, 2.0, 3.0] {
+                let tree = expression.try_evaluate_with(x, &mut tree_variables, &functions);
+                let compiled = program.try_evaluate(x, &mut compiled_variables);
+                assert!(tree.as_ref().is_ok_and(|v| v.is_nan()) && compiled.as_ref().is_ok_and(|v| v.is_nan()) || tree == compiled, "{} at {} : {:?} != {:?}", s, x, tree, compiled);
+                for (name, value) in &tree_variables {
+                    assert_eq!(compiled_variables.get(name), Some(value), "{} at {}", s, x);
+                }
+            }
+        }
+
+        // Registers are reused between evaluations, assignments stay in them
+        let program = parse_expression("n = n + x")?.compile(&functions).expect("not recursive");
+        let mut registers = program.registers(&HashMap::new());
+        assert_eq!((1..=4).map(|x| program.run(x as f32, &mut registers)).last(), Some(Ok(10.0)));
+
+        // Recursive functions cannot be inlined
+        assert!(parse_with_functions("fact(4)", &mut functions)?.compile(&functions).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_derive() -> Result<(), ParseError> {
+        let mut functions = UserFunctions::new();
+        parse_with_functions("f(t) = t^3 - 2t", &mut functions)?;
+        parse_with_functions("g(x, a) = a*sin(x)", &mut functions)?;
+        let inputs = [
+            "3x^2 - 2x + 1", "-x", "+x", "x²", "x/(1+x²)", "2^x", "x^x", "pow(x, 3)", "(x+1)^(1/2)",
+            "sin(x)", "cos(2x)", "tan(x)", "cot(x)", "sec(x)", "csc(x)", "abs(x - 1)", "exp(-x)", "ln(x)", "log(x)", "sqrt(x)",
+            "asin(x/4)", "acos(x/4)", "atan(x)", "sinh(x)", "cosh(x)", "tanh(x)", "asinh(x)", "acosh(x+2)", "atanh(x/4)",
+            "max(x, 1, x²)", "min(x, 2 - x)", "if(x > 1, x^2, -x)", "floor(x) + x!", "x > 1",
+            "sum(i=1, 4, x^i)", "prod(i=1, 3, x + i)", "prod(i=1, 3, i*x)", "f(x) + f(2x)", "g(x, 3)", "a = x^2",
+        ];
+        let mut variables = HashMap::new();
+        for s in inputs {
+            let expression = parse_with_functions(s, &mut functions)?;
+            let derivative = derive_with(&expression, "x", &functions);
+            for x in [0.3, 0.7, 1.3] {
+                let h = 1e-2;
+                let numeric = (expression.evaluate_with(x + h, &mut variables, &functions) - expression.evaluate_with(x - h, &mut variables, &functions)) / (2.0 * h);
+                let symbolic = derivative.evaluate_with(x, &mut variables, &functions);
+                assert!((numeric - symbolic).abs() <= 1e-2 * numeric.abs().max(1.0), "d({}) at {} : {} != {}", s, x, symbolic, numeric);
+            }
+        }
+
+        // d(expression, variable) is replaced while parsing
+        assert_eq!(parse_with_functions("d(f(x), x)", &mut functions)?.evaluate_with(2.0, &mut variables, &functions), 10.0);
+        assert_eq!(parse_expression("d(d(x^3, x), x)")?.simple_evaluate(2.0), 12.0);
+        assert_eq!(parse_expression("d(a*x + a^2, a)")?.evaluate(3.0, &mut [("a".to_string(), 2.0)].into_iter().collect()), 7.0);
+        assert_eq!(parse_expression("d(sum(a=1, 3, a*x), a)")?.simple_evaluate(2.0), 0.0);
+        assert_eq!(parse_expression("d(x, 2)").err(), Some(ParseError::ExpectedVariable(Span::new(5, 6))));
+        assert_eq!(parse_expression("2 d").err(), Some(ParseError::UnexpectedIdentifier("d".to_string(), Span::new(2, 3))));
+        assert!(matches!(parse_expression(&("d(".repeat(16) + "x^x*sin(x)" + &",x)".repeat(16))), Err(ParseError::TooLarge(_))));
+        // Malformed nodes keep their error
+        assert!(matches!(parse_expression("d(sum(1, 5, x), x)")?.try_evaluate(0.0, &mut variables), Err(EvalError::InvalidIterator(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_simplify() -> Result<(), ParseError> {
+        let cases = [
+            ("0*x + x*1", "x"), ("2*3*x", "6*x"), ("4 max(1)", "4"), ("2x + 3x", "5*x"), ("x - x", "0"), ("x*x*2", "2*x^2"),
+            ("x + 1 + x - 3", "2*x - 2"), ("-x + 2*x", "x"), ("x^1 + x^0", "x + 1"), ("x/1", "x"), ("x*x² / 1", "x^3"),
+            ("if(1 > 0, x, 1/x)", "x"), ("sum(i=1, 4, i)", "10"), ("sin(2*0) + x", "x"), ("sin(x) - 2sin(x)", "-sin(x)"),
+            // Terms that can be undefined are kept
+            ("ln(x) - ln(x)", "ln(x) - ln(x)"), ("0*sqrt(x) + x", "0*sqrt(x) + x"), ("x^0.5*x^0.5", "x^0.5*x^0.5"),
+            // Assignments and their values are kept
+            ("0*(a = x) + a", "0*(a = x) + a"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_simplified(input)?, parse_expression(expected)?, "{}", input);
+        }
+
+        let mut functions = UserFunctions::new();
+        parse_with_functions("f(t) = t^2 - 2t + 1", &mut functions)?;
+        let inputs = [
+            "3x^2 - 2x*x + 1 - x²", "x*(2 - 2) + 4 max(1, 2) x", "-(-x) - 2 - -x", "pow(x, 2)*x/x^2", "sum(i=1, 3, i*x) + x",
+            "prod(i=1, 3, x + i) - prod(i=1, 3, x + i)", "f(x) + f(x)", "if(x > 1, 2*3*x, x*0)", "(a = x) * 0 + a * a", "x^2^0.5",
+            // Reads on both sides of an assignment are not merged
+            "a + (a = x) + a", "a + (a = x) - a", "a*(a = x)*a",
+        ];
+        for input in inputs {
+            let expression = parse_with_functions(input, &mut functions)?;
+            let simplified = simplify(&expression);
+            for x in [-1.5, 0.3, 2.0] {
+                let expected = expression.evaluate_with(x, &mut HashMap::new(), &functions);
+                let value = simplified.evaluate_with(x, &mut HashMap::new(), &functions);
+                assert!((expected - value).abs() <= 1e-5 * expected.abs().max(1.0), "{} at {} : {} != {}", input, x, value, expected);
+            }
+        }
+
+        // Where the original is undefined the simplified expression is too
+        let inputs = ["x^0.5*x^0.5", "x*x^-1", "x^-1*x^2", "ln(x) - ln(x)", "0*ln(x)", "ln(x) - 2ln(x)", "0/x + 1", "x*0*sqrt(x)"];
+        for input in inputs {
+            let expression = parse_expression(input)?;
+            let simplified = simplify(&expression);
+            for x in [-1.0, 0.0] {
+                let (expected, value) = (expression.simple_evaluate(x), simplified.simple_evaluate(x));
+                assert!(expected == value || (expected.is_nan() && value.is_nan()), "{} at {} : {} != {}", input, x, value, expected);
+            }
+        }
+        Ok(())
+    }
+
+    // Random nested input using every construct, parsed with f(t, u) defined
+    fn random_input(seed : &mut u64, depth : usize) -> String {
+        *seed ^= *seed << 13; *seed ^= *seed >> 7; *seed ^= *seed << 17;
+        let (choice, pick) = (if depth == 0 { 0 } else { (*seed >> 8) % 8 }, (*seed >> 16) as usize);
+        let leaves = ["x", "2", "0.5", "1e39", "a", "pi"];
+        let operators = ["+", "-", "*", "/", "^", " ", "==", "<", "="];
+        let (a, b) = if choice == 0 { (String::new(), String::new()) } else { (random_input(seed, depth - 1), random_input(seed, depth - 1)) };
+        match choice {
+            0 => leaves[pick % leaves.len()].to_string(),
+            1 => format!("-{}", a),
+            2 => format!("({}){}", a, ["!", "²"][pick % 2]),
+            3 => format!("({})", a),
+            4 => format!("{}({}, {})", ["max", "f", "d"][pick % 3], a, ["x", "a"][pick / 3 % 2]),
+            5 => format!("if(a > 1, {}, {})", a, b),
+            6 => format!("sum(i=1, 3, i*({}))", a),
+            _ => format!("{}{}{}", a, operators[pick % operators.len()], b),
+        }
+    }
+
+    #[test]
+    fn test_display() -> Result<(), ParseError> {
+        let cases = [
+            ("2x + 1", "2*x + 1"), ("(x + 1)(x - 1)", "(x + 1)*(x - 1)"), ("-x^2", "-x^2"), ("(-x)^2", "(-x)^2"), ("2^-x", "2^(-x)"),
+            ("2^3^x", "2^3^x"), ("2^(3^x)", "2^(3^x)"), ("a - (b - c)", "a - (b - c)"), ("(a - b) - c", "a - b - c"), ("x - -1", "x - -1"),
+            ("(x + 1)!", "(x + 1)!"), ("x²!", "x²!"), ("sum(i=1, 4, i*x)", "sum(i = 1, 4, i*x)"), ("max(1, ln(x))", "max(1, ln(x))"),
+            ("a=(b=2)", "a = (b = 2)"), ("0*(a=x)", "0*(a = x)"), ("1e30 + 0.5e-6 + 0x10 + pi", "1e30 + 5e-7 + 16 + pi"), ("x > -1 == 1", "x > -1 == 1"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_expression(input)?.to_string(), expected);
+        }
+        assert_eq!(simplify(&parse_expression("x*x*2 - 3x")?).to_string(), "2*x^2 - 3*x");
+
+        // Printing then parsing gives back the same tree
+        let mut functions = UserFunctions::new();
+        parse_with_functions("f(t, u) = t*u", &mut functions)?;
+        let mut seed = 0x2545F4914F6CDD1Du64;
+        let mut checked = 0;
+        for _ in 0..5000 {
+            let input = random_input(&mut seed, 5);
+            let Ok(expression) = parse_with_functions(&input, &mut functions) else { continue; };
+            // Derivatives and simplified trees have negative constants read back as negations
+            if !input.contains("d(") {
+                assert_eq!(parse_with_functions(&expression.to_string(), &mut functions)?, expression, "{} printed as {}", input, expression);
+            }
+            for e in [&expression, &simplify(&expression)] {
+                let printed = e.to_string();
+                assert_eq!(parse_with_functions(&printed, &mut functions)?.to_string(), printed, "{}", input);
+            }
+            checked += 1;
+        }
+        assert!(checked > 1000, "{}", checked);
+        Ok(())
+    }
+
+    #[test]
+    fn test_latex() -> Result<(), ParseError> {
+        let mut functions = UserFunctions::new();
+        parse_with_functions("f(t, u) = t*u", &mut functions)?;
+        let cases = [
+            ("x/(1+x)", r"\frac{x}{1 + x}"), ("sqrt(x^2+1)", r"\sqrt{x^{2} + 1}"), ("(x+1)^2", r"\left(x + 1\right)^{2}"),
+            ("x^2^3", r"\left(x^{2}\right)^{3}"), ("(1/x)²", r"\left(\frac{1}{x}\right)^{2}"), ("2^-x", "2^{-x}"),
+            ("sum(i=1, n, i^2)", r"\sum_{i=1}^{n} i^{2}"), ("prod(i=1, 3, x+i)", r"\prod_{i=1}^{3} \left(x + i\right)"),
+            ("if(x > 0, x, -x)", r"\begin{cases} x & \text{if } x > 0 \\ -x & \text{otherwise} \end{cases}"),
+            ("sin(x)^2 + asin(x)", r"\sin\left(x\right)^{2} + \arcsin\left(x\right)"), ("abs(x - 1)", r"\left|x - 1\right|"),
+            ("floor(x)", r"\left\lfloor x\right\rfloor"), ("max(1, x)", r"\max\left(1, x\right)"), ("f(x, 2)", r"f\left(x, 2\right)"),
+            ("(a/b)!", r"\left(\frac{a}{b}\right)!"), ("1e30 + 1.5e-7 + pi", r"10^{30} + 1.5 \cdot 10^{-7} + \pi"),
+            ("x != 2", r"x \neq 2"), ("a = 3x", r"a := 3 \cdot x"), ("alpha*my_var", r"\alpha \cdot \mathrm{my\_var}"),
+            ("-(-x)", r"-\left(-x\right)"), ("-(-x^2)", r"-\left(-x^{2}\right)"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_with_functions(input, &mut functions)?.to_latex(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_latex() -> Result<(), ParseError> {
+        let cases = [
+            (r"\frac{1}{2}\rho v^{2}", "1/2*rho*v^2"), (r"\sqrt{x^2+1}", "sqrt(x^2+1)"), (r"2 \cdot 3 \times x", "2*3*x"),
+            (r"\pi r^2", "pi*r^2"), (r"\sin x + \cos(2x)", "sin(x) + cos(2x)"), (r"\sin^2 x", "sin(x)^2"), (r"\sin 2x y + 1", "sin(2x*y) + 1"),
+            (r"\sin\left(x\right) y", "sin(x)*y"), (r"x_1 + x_{max} + \alpha_0", "x_1 + x_max + alpha_0"), (r"\frac12 + \frac{x}\pi", "1/2 + x/pi"),
+            (r"\sum_{i=1}^{n} i^2 + 1", "sum(i=1, n, i^2) + 1"), (r"\prod_{k=1}^{3} \left(x + k\right)", "prod(k=1, 3, x+k)"),
+            (r"\sqrt[3]{x}", "x^(1/3)"), (r"\left|x\right| + \lfloor x \rfloor + |y|", "abs(x) + floor(x) + abs(y)"), (r"e^{-x}", "e^(-x)"),
+            (r"2(x+1) e(x) \pi(x)", "2*(x+1)*e*(x)*pi*(x)"), (r"{x + 1}[y]", "(x + 1)*(y)"), (r"x \leq 2 \neq 1", "x <= 2 != 1"), (r"a := 2b", "a = 2b"),
+            (r"x = 1", "x == 1"), (r"\mathrm{my\_var} \cdot \operatorname{max}(1, x)", "my_var * max(1, x)"), (r"3! - \infty", "3! - inf"),
+            (r"\begin{cases} x, & x > 0 \\ -x & \text{otherwise} \end{cases}", "if(x > 0, x, -x)"),
+            (r"\begin{cases} 1 & \text{if } x < 0 \\ 2 & x < 1 \end{cases}", "if(x < 0, 1, if(x < 1, 2, NaN))"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_latex(input)?, parse_expression(expected)?, "{}", input);
+        }
+
+        let errors = [
+            (r"\frac{1}", ParseError::MissingArgument(Span::new(0, 5))), (r"\foo x", ParseError::UnknownIdentifier(r"\foo".to_string(), Span::new(0, 4))),
+            (r"{x + 1", ParseError::UnbalancedParen('{', Span::new(0, 1))), (r"x}", ParseError::UnbalancedParen('}', Span::new(1, 2))),
+            (r"x & y", ParseError::UnexpectedOperator("&".to_string(), Span::new(2, 3))), (r"x_", ParseError::UnexpectedOperator("_".to_string(), Span::new(1, 2))),
+        ];
+        for (input, expected) in errors {
+            assert_eq!(parse_latex(input), Err(expected), "{}", input);
+        }
+        assert!(matches!(parse_latex(&r"\sqrt{".repeat(300)), Err(ParseError::NestingTooDeep(_))));
+
+        // The LaTeX of an expression reads back to the same values
+        let mut functions = UserFunctions::new();
+        parse_with_functions("f(t, u) = t*u", &mut functions)?;
+        let mut seed = 0x2545F4914F6CDD1Du64;
+        let mut checked = 0;
+        // Signs of signs are written with parenthesis
+        let inputs = ["-(-x)", "-(-x^2)", "-(-2)", "+(-x)"].map(String::from).into_iter().chain((0..5000).map(|_| random_input(&mut seed, 5)));
+        for input in inputs {
+            // A definition gives a call with x as a variable, read back as the input
+            if split_operator(&input).is_ok_and(|tokens| match_definition(&tokens).is_some()) { continue; }
+            let Ok(expression) = parse_with_functions(&input, &mut functions) else { continue; };
+            let latex = expression.to_latex();
+            assert!(!latex.contains("--"), "{} is written {}", input, latex);
+            let read = parse_latex_with_functions(&latex, &functions)?;
+            for x in [-1.5, 0.5, 2.0] {
+                let mut variables = HashMap::from([("a".to_string(), 1.5)]);
+                let Ok(expected) = expression.try_evaluate_with(x, &mut variables.clone(), &functions) else { continue; };
+                let value = read.try_evaluate_with(x, &mut variables, &functions).unwrap_or(f32::NAN);
+                // x² is read back as x^{2}, computed with powf
+                let close = value == expected || (value - expected).abs() <= 1e-5 * expected.abs() || (value.is_nan() && expected.is_nan());
+                assert!(close, "{} read from {} gives {} instead of {}", input, latex, value, expected);
+            }
+            checked += 1;
+        }
+        assert!(checked > 1000, "{}", checked);
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_f64() -> Result<(), ParseError> {
+        let mut variables = HashMap::new();
+        assert_eq!(parse_expression("0.1 + 0.2")?.evaluate_f64(0.0, &mut variables), 0.1 + 0.2);
+        assert_eq!(parse_expression("101325.37 - 101325.12")?.evaluate_f64(0.0, &mut variables), 101325.37 - 101325.12);
+        assert_eq!(parse_expression("x - 1")?.evaluate_f64(1.0 + 1e-12, &mut variables), 1e-12 + 1.0 - 1.0);
+        assert_eq!(parse_expression("pi - 3.141592653589793")?.evaluate_f64(0.0, &mut variables), 0.0);
+        assert_eq!(parse_expression("170! > 1e306")?.evaluate_f64(0.0, &mut variables), 1.0);
+        assert_eq!(parse_expression("a = 2^0.5")?.evaluate_f64(0.0, &mut variables), 2f64.sqrt());
+        assert_eq!(variables["a"], 2f64.sqrt());
+
+        // Same values as in f32 up to its precision
+        let mut functions = UserFunctions::new();
+        parse_with_functions("f(t, u) = t*u", &mut functions)?;
+        let inputs = [
+            "sin(x)^2 + cos(x)^2", "sum(i=1, 10, 1/i) - ln(x)", "if(x > 1, f(x, 3), max(x, 2, -1))", "prod(i=1, 4, x + i) / 4!",
+            "atanh(x/4) + sec(x) - round(x) == 1", "pow(2, x) + 0x10 + 1e3 + e", "(b = x) * b",
+        ];
+        for input in inputs {
+            let expression = parse_with_functions(input, &mut functions)?;
+            for x in [0.3, 2.0, 3.5] {
+                let expected = expression.try_evaluate_with(x, &mut HashMap::new(), &functions).map_err(|e| e.span());
+                let value = expression.try_evaluate_f64_with(x as f64, &mut HashMap::new(), &functions).map_err(|e| e.span());
+                let close = expected.is_ok_and(|expected| (value.unwrap_or(f64::NAN) - expected as f64).abs() <= 1e-5 * expected.abs().max(1.0) as f64);
+                assert!(close, "{} at {} : {:?} != {:?}", input, x, value, expected);
+            }
+        }
+        assert_eq!(parse_with_functions("f(2, 3)", &mut functions)?.try_evaluate_f64(0.0, &mut variables), Err(EvalError::UnknownFunction(Span::new(0, 7))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact() -> Result<(), ParseError> {
+        let mut variables = HashMap::new();
+        let mut exact = |input : &str| parse_expression(input).map(|e| e.evaluate_exact(Exact::from_f64(3.0), &mut variables).to_string());
+        assert_eq!(exact("1/3*3")?, "1");
+        assert_eq!(exact("0.1 + 0.2")?, "3/10");
+        assert_eq!(exact("-7/2")?, "-7/2");
+        assert_eq!(exact("x/6 - 1/4")?, "1/4");
+        assert_eq!(exact("25!")?, "15511210043330985984000000");
+        assert_eq!(exact("2^100")?, "1267650600228229401496703205376");
+        assert_eq!(exact("(2/3)^-3")?, "27/8");
+        assert_eq!(exact("sum(i=1, 10, 1/i)")?, "7381/2520");
+        assert_eq!(exact("prod(i=1, 30, i) / 28!")?, "870");
+        // Long division with several digits in the divisor
+        assert_eq!(exact("(2^200 - 1) / (2^100 - 1)")?, "1267650600228229401496703205377");
+        assert_eq!(exact("floor(-(10^30 + 1)/3)")?, "-333333333333333333333333333334");
+        assert_eq!(exact("round(-5/2) + round(5/2) + ceil(1/3)")?, "1");
+        assert_eq!(exact("if(1/3 < 0.34, max(1/2, 2/3), 0)")?, "2/3");
+        assert_eq!(exact("a = 1/8")?, "1/8");
+        assert_eq!(exact("a * 8 == 1")?, "1");
+
+        // Functions without exact result and divisions by zero give floats
+        assert_eq!(exact("sin(0.5) + 1/2")?, format!("{}", 0.5f64.sin() + 0.5));
+        assert_eq!(exact("2^0.5")?, format!("{}", 2f64.sqrt()));
+        assert_eq!(exact("1/0")?, "inf");
+        assert_eq!(exact("pi")?, "pi");
+        assert_eq!(parse_expression("1/3")?.evaluate_exact(Exact::from_f64(0.0), &mut HashMap::new()).to_f64(), 1.0 / 3.0);
+        assert_eq!(Rational::from_decimal("-12.50").map(|r| r.to_string()), Some("-25/2".to_string()));
+        assert_eq!(Rational::from_decimal("1e3"), None);
+
+        // Denominators too large to reduce quickly give floats and long exact loops stop, both in a few seconds at most
+        let start = std::time::Instant::now();
+        let harmonic = parse_expression("sum(i=1, 10000, 1/i)")?.try_evaluate_exact(Exact::from_f64(0.0), &mut HashMap::new());
+        assert!(matches!(harmonic, Ok(Exact::Float(h)) if (h - 9.787606036044348).abs() < 1e-9), "{:?}", harmonic);
+        let factorial = parse_expression("prod(i=1, 1000000, i)")?.try_evaluate_exact(Exact::from_f64(0.0), &mut HashMap::new());
+        assert!(matches!(factorial, Err(EvalError::TooManyIterations(_))), "{:?}", factorial);
+        assert!(start.elapsed().as_secs() < 20, "{:?}", start.elapsed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_complex() -> Result<(), ParseError> {
+        let mut variables = HashMap::new();
+        let mut complex = |input : &str| parse_expression(input).map(|e| e.evaluate_complex(Complex::real(2.0), &mut variables));
+        assert_eq!(complex("sqrt(-1)")?, Complex::I);
+        assert_eq!(complex("i^2")?, Complex::real(-1.0));
+        assert_eq!(complex("(3 + 4i)(1 - 2i)")?, Complex::new(11.0, -2.0));
+        assert_eq!(complex("(1 + i)/(1 - i)")?, Complex::I);
+        assert_eq!(complex("ln(-2)")?, Complex::new(2f64.ln(), std::f64::consts::PI));
+        assert_eq!(complex("re(3 - 4i) + im(3 - 4i) + abs(3 - 4i)")?, Complex::real(4.0));
+        assert_eq!(complex("conj(x + i) * arg(-1)")?, Complex::new(2.0 * std::f64::consts::PI, -std::f64::consts::PI));
+        assert_eq!(complex("sqrt(-4) == 2i")?, Complex::real(1.0));
+        let close = |a : Complex, b : Complex| (a.re - b.re).abs() < 1e-12 && (a.im - b.im).abs() < 1e-12;
+        assert!(close(complex("e^(i*pi)")?, Complex::real(-1.0)));
+        assert!(close(complex("sin(asin(2 + i)) + cosh(acosh(3i)) + tan(atan(i/2))")?, Complex::new(2.0, 4.5)));
+        // Roots of x² + 2x + 5
+        assert!(close(complex("(-2 + sqrt(2^2 - 4*5))/2")?, Complex::new(-1.0, 2.0)));
+        // Real values are the ones of evaluate_f64, orders only exist between them
+        assert_eq!(complex("sin(x) + 170!")?, Complex::real(2f64.sin() + number::factorial(170.0)));
+        assert!(complex("i < 1")?.re.is_nan());
+        // i is a variable the iterator of a sum can use
+        assert_eq!(complex("sum(i=1, 4, i) + i")?, Complex::new(10.0, 1.0));
+        assert_eq!(complex("i = 3")?, Complex::real(3.0));
+        assert_eq!(complex("i")?, Complex::real(3.0));
+
+        // Outside of complex numbers i is a variable like the others
+        assert_eq!(parse_expression("i")?.simple_evaluate(0.0), 0.0);
+        assert_eq!(parse_expression("i + 1")?.evaluate_f64(0.0, &mut HashMap::new()), 1.0);
+        assert_eq!(parse_expression("i + 1/3")?.evaluate_exact(Exact::from_f64(0.0), &mut HashMap::new()).to_string(), "1/3");
+        assert_eq!(parse_expression("x*i")?.try_evaluate_dual(Dual::variable(2.0), &mut HashMap::new()), Ok(Dual::constant(0.0)));
+        assert_eq!(parse_expression("i")?.try_evaluate_interval(Interval::new(1.0, 2.0), &mut HashMap::new()), Ok(Interval::point(0.0)));
+        // Followed by parenthesis it is a product, not a call
+        assert_eq!(parse_expression("i(x+1)")?.evaluate_complex(Complex::real(2.0), &mut HashMap::new()), Complex::new(0.0, 3.0));
+        assert_eq!(parse_expression("sum(i=1, 5, i(i+1))")?.simple_evaluate(0.0), 70.0);
+        assert_eq!(parse_expression("sum(i=1, 5, i(i+1))")?.evaluate_complex(Complex::real(0.0), &mut HashMap::new()), Complex::real(70.0));
+        assert_eq!(parse_expression("sum(i=1, 4, i)")?.simple_evaluate(0.0), 10.0);
+        assert_eq!(parse_expression("re(x) + im(x) + arg(x) + conj(x)")?.simple_evaluate(-1.0), std::f32::consts::PI - 2.0);
+        assert_eq!(Complex::new(0.5, -2.0).to_string(), "0.5 - 2i");
+        assert_eq!(Complex::new(0.0, std::f64::consts::PI).to_string(), "pi*i");
+        assert_eq!(parse_latex("\\Re(3 + 2i) + \\arg(-1)")?.to_string(), "re(3 + 2*i) + arg(-1)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dual() -> Result<(), ParseError> {
+        let mut functions = UserFunctions::new();
+        parse_with_functions("f(t) = t^3 - 2t", &mut functions)?;
+        let inputs = [
+            "3x^2 - 2x + 1", "x/(1+x²)", "x^x", "2^x", "sqrt(x) * ln(x)", "sin(x) + cos(2x) + tan(x) + cot(x) + sec(x) + csc(x)",
+            "asin(x/4) + acos(x/4) + atan(x) + asinh(x) + acosh(x+2) + atanh(x/4)", "sinh(x) * cosh(x) / tanh(x)", "abs(x - 1) + log(x)",
+            "max(x, 1, x²) - min(x, 2 - x)", "if(x > 1, x^2, -x)", "floor(x) + x! + (x > 1)", "sum(i=1, 4, x^i)", "prod(i=1, 3, x + i)", "f(x) + f(2x)",
+        ];
+        for s in inputs {
+            let expression = parse_with_functions(s, &mut functions)?;
+            let derivative = derive_with(&expression, "x", &functions);
+            for x in [0.3, 0.7, 1.3] {
+                let dual = expression.evaluate_dual_with(Dual::variable(x), &mut HashMap::new(), &functions);
+                assert_eq!(dual.value, expression.evaluate_f64_with(x, &mut HashMap::new(), &functions));
+                let symbolic = derivative.evaluate_f64_with(x, &mut HashMap::new(), &functions);
+                assert!((dual.deriv - symbolic).abs() <= 1e-12 * symbolic.abs().max(1.0), "d({}) at {} : {} != {}", s, x, dual.deriv, symbolic);
+            }
+        }
+
+        // Assigned variables keep their derivative, derive takes them as constants
+        assert_eq!(parse_expression("(a = x^2) * a")?.evaluate_dual(Dual::variable(3.0), &mut HashMap::new()), Dual::new(81.0, 108.0));
+        // Derivative with respect to another variable, x being constant
+        let mut variables = [("a".to_string(), Dual::variable(3.0))].into_iter().collect();
+        assert_eq!(parse_expression("a^2 * x")?.evaluate_dual(Dual::constant(2.0), &mut variables), Dual::new(18.0, 12.0));
+        // Constant parts do not spoil the derivative even where their own is infinite
+        assert_eq!(parse_expression("sqrt(0) + x")?.evaluate_dual(Dual::variable(2.0), &mut HashMap::new()), Dual::new(2.0, 1.0));
+        assert!(parse_expression("sqrt(x)")?.evaluate_dual(Dual::variable(0.0), &mut HashMap::new()).deriv.is_infinite());
+        assert!(parse_expression("abs(x)")?.evaluate_dual(Dual::variable(0.0), &mut HashMap::new()).deriv.is_nan());
+
+        // Built-ins computed in double precision take their derivative from the values around x
+        let numeric = [
+            ("roots(x^2 - 2, 0, x)", 2f64.sqrt(), 0.0), ("roots(x^2 - 2, 0, 3) + x", 2f64.sqrt() + 2.0, 1.0), ("intersect(x^2, 2, -x, 0)", -(2f64.sqrt()), 0.0),
+            ("integral(x*t, t, 0, 1)", 1.0, 0.5), ("deriv(x*t^2, t, 1)", 4.0, 2.0), ("limit(x*sin(t)/t, t, 0)", 2.0, 1.0),
+            ("solve(t*x = 1, t)", 0.5, -0.25), ("integral(t, t, 0, 1) + x", 2.5, 1.0),
+        ];
+        for (input, value, deriv) in numeric {
+            let dual = parse_expression(input)?.evaluate_dual(Dual::variable(2.0), &mut HashMap::new());
+            assert!((dual.value - value).abs() <= 1e-9 && (dual.deriv - deriv).abs() <= 1e-6, "{} : {:?}", input, dual);
+        }
+        // Unknown when a variable they read moves with x
+        let dual = parse_expression("(a = x) + roots(x - a, 0, 5)")?.evaluate_dual(Dual::variable(2.0), &mut HashMap::new());
+        assert!((dual.value - 4.0).abs() <= 1e-9 && dual.deriv.is_nan());
+        let dual = parse_expression("(a = x) + roots(x - 1, 0, 5)")?.evaluate_dual(Dual::variable(2.0), &mut HashMap::new());
+        assert!((dual.value - 3.0).abs() <= 1e-9 && (dual.deriv - 1.0).abs() <= 1e-6);
+        let dual = parse_expression("(a = x) + integral(a*t, t, 0, 1)")?.evaluate_dual(Dual::variable(2.0), &mut HashMap::new());
+        assert!(dual.value == 3.0 && dual.deriv.is_nan());
+        assert_eq!(parse_expression("(a = x) + integral(t, t, 0, 1)")?.evaluate_dual(Dual::variable(2.0), &mut HashMap::new()), Dual::new(2.5, 1.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_curve() -> Result<(), ParseError> {
+        let sample = |input : &str| parse_expression(input).map(|e| sample_curve(&e, -5.0, 5.0, -3.0, 3.0, 1e-3));
+        assert_eq!(sample("sin(x)")?.len(), 1);
+        // Cut at the poles and jumps, not joined across them
+        let tan = sample("tan(x)")?;
+        assert_eq!(tan.len(), 3);
+        for (polyline, pole) in tan.iter().zip([-std::f64::consts::FRAC_PI_2, std::f64::consts::FRAC_PI_2, 3.0 * std::f64::consts::FRAC_PI_2]) {
+            assert!(polyline.iter().all(|&(x, y)| x < pole && (-3.0..=3.0).contains(&y)));
+        }
+        assert_eq!(sample("1/x")?.len(), 2);
+        assert_eq!(sample("floor(x)")?.len(), 7);
+        assert_eq!(sample("if(x > 1, 2, 0)")?.len(), 2);
+        // Ends where the values stop, clipped to the window
+        let sqrt = &sample("sqrt(x)")?[0];
+        assert!(sqrt[0].0 >= 0.0 && sqrt[0].0 < 1e-4);
+        assert_eq!(*sqrt.last().unwrap(), (5.0, 5f64.sqrt()));
+        let square = &sample("x^2")?[0];
+        assert!((square[0].0 + 3f64.sqrt()).abs() < 1e-3 && square[0].1 == 3.0);
+        assert!(sample("sqrt(-1 - x^2)")?.is_empty());
+        // Refined where it bends, every segment stays within the tolerance
+        let polyline = &sample("sin(20x)")?[0];
+        for segment in polyline.windows(2) {
+            let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+            let middle = (20.0 * (x0 + x1) / 2.0).sin();
+            assert!((middle - (y0 + y1) / 2.0).abs() <= 6e-3);
+        }
+        assert!(sample_function(f64::sin, 1.0, 1.0, -1.0, 1.0, 1e-3).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_roots() -> Result<(), ParseError> {
+        let find = |input : &str, a : f64, b : f64| parse_expression(input).map(|e| roots(&e, a, b));
+        let sqrt2 = find("x^2 - 2", 0.0, 3.0)?;
+        assert_eq!(sqrt2.len(), 1);
+        assert!((sqrt2[0].x - 2f64.sqrt()).abs() < 1e-12 && sqrt2[0].multiplicity == 1 && sqrt2[0].converged);
+        // Touching 0 without crossing it, and roots that are exactly on the grid
+        let square = find("(x - 0.3)^2", -1.0, 1.0)?;
+        assert!(square.len() == 1 && (square[0].x - 0.3).abs() < 1e-6 && square[0].multiplicity == 2);
+        assert_eq!(find("x^2", -1.0, 1.0)?.iter().map(|r| (r.x, r.multiplicity)).collect::<Vec<_>>(), vec![(0.0, 2)]);
+        let cube = find("x^3", -1.0, 2.0)?;
+        assert!(cube.len() == 1 && cube[0].x.abs() < 1e-12 && cube[0].multiplicity == 3);
+        let sin : Vec<f64> = find("sin(x)", -1.0, 7.0)?.iter().map(|r| r.x).collect();
+        assert_eq!(sin.len(), 3);
+        for (x, expected) in sin.iter().zip([0.0, std::f64::consts::PI, 2.0 * std::f64::consts::PI]) {
+            assert!((x - expected).abs() < 1e-12);
+        }
+        // Poles and jumps change sign without a root
+        assert!(find("1/x", -1.0, 2.0)?.is_empty());
+        assert!(find("floor(x) - 0.5", -2.0, 2.0)?.is_empty());
+        assert!(find("x^2 + 1", -2.0, 2.0)?.is_empty());
+        // Where the values start
+        let edge = find("sqrt(x)", -1.0, 1.0)?;
+        assert!(edge.len() == 1 && edge[0].x.abs() < 1e-12);
+        assert!(find("x - 1", 2.0, 0.0)?.is_empty());
+        // In the language x is bound in the first argument, the n-th root is asked for
+        assert!((parse_expression("roots(x^2 - 2, 0, 3)")?.evaluate_f64(10.0, &mut HashMap::new()) - 2f64.sqrt()).abs() < 1e-12);
+        assert!((parse_expression("roots(sin(x), -1, 7, 2)")?.evaluate_f64(0.0, &mut HashMap::new()) - std::f64::consts::PI).abs() < 1e-12);
+        assert!(parse_expression("roots(sin(x), -1, 7, 4)")?.evaluate_f64(0.0, &mut HashMap::new()).is_nan());
+        assert!((parse_expression("roots(x - a, 0, x)")?.evaluate_f64(5.0, &mut HashMap::from([("a".to_string(), 2.0)])) - 2.0).abs() < 1e-12);
+        assert_eq!(parse_expression("roots(x^2 - 2, 0, 3)")?.to_string(), "roots(x^2 - 2, 0, 3)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_intersections() -> Result<(), ParseError> {
+        // A falling fan curve against a rising resistance
+        let (fan, system) = (parse_expression("600 - 20x^2")?, parse_expression("40x^2")?);
+        let points = intersections(&fan, &system, 0.0, 10.0);
+        assert_eq!(points.len(), 1);
+        assert!((points[0].x - 10f64.sqrt()).abs() < 1e-12 && (points[0].y - 400.0).abs() < 1e-9 && points[0].multiplicity == 1);
+        let line = parse_expression("2x - 1")?;
+        let tangent = intersections(&parse_expression("x^2")?, &line, -3.0, 3.0);
+        assert!(tangent.len() == 1 && (tangent[0].x - 1.0).abs() < 1e-6 && tangent[0].multiplicity == 2);
+        assert!(intersections(&parse_expression("x^2 + 1")?, &parse_expression("x - 1")?, -3.0, 3.0).is_empty());
+        let sin : Vec<f64> = intersections(&parse_expression("sin(x)")?, &parse_expression("cos(x)")?, 0.0, 7.0).iter().map(|p| p.x).collect();
+        assert_eq!(sin.len(), 2);
+        assert!((sin[0] - std::f64::consts::FRAC_PI_4).abs() < 1e-12 && (sin[1] - 5.0 * std::f64::consts::FRAC_PI_4).abs() < 1e-12);
+        // In the language x is bound in both curves
+        let mut variables = HashMap::from([("r".to_string(), 40.0)]);
+        assert!((parse_expression("intersect(600 - 20x^2, r*x^2, 0, 10)")?.evaluate_f64(0.0, &mut variables) - 10f64.sqrt()).abs() < 1e-12);
+        assert!((parse_expression("intersect(sin(x), cos(x), 0, 7, 2)")?.evaluate_f64(0.0, &mut variables) - 5.0 * std::f64::consts::FRAC_PI_4).abs() < 1e-12);
+        assert!(parse_expression("intersect(x, x + 1, 0, 1)")?.evaluate_f64(0.0, &mut variables).is_nan());
+        assert_eq!(parse_expression("intersect(x, 1 - x, 0, 1)")?.to_string(), "intersect(x, 1 - x, 0, 1)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_integral() -> Result<(), ParseError> {
+        let mut variables = HashMap::new();
+        let mut integral = |input : &str| parse_expression(input).map(|e| e.try_evaluate_f64(2.0, &mut variables));
+        let close = |value : Result<f64, EvalError>, expected : f64| value.is_ok_and(|v| (v - expected).abs() <= 1e-9 * expected.abs().max(1.0));
+        assert!(close(integral("integral(t^2, t, 0, 3)")?, 9.0));
+        assert!(close(integral("integral(x^2, x, 3, 0)")?, -9.0));
+        assert!(close(integral("integral(sin(t)/t, t, 0, 100)")?, 1.5622254668890563));
+        // The variable is bound inside, x outside is still the input
+        assert!(close(integral("integral(x*t, t, 0, 1)")?, 1.0));
+        assert!(close(integral("integral(abs(t - 0.3), t, -1, 1)")?, 1.09));
+        // Improper integrals, at infinite bounds and at poles on the ends
+        assert!(close(integral("integral(exp(-t^2), t, -inf, inf)")?, std::f64::consts::PI.sqrt()));
+        assert!(close(integral("integral(1/t^2, t, 1, inf)")?, 1.0));
+        assert!(close(integral("integral(exp(t), t, -inf, 0)")?, 1.0));
+        assert!(close(integral("integral(1/sqrt(t), t, 0, 1)")?, 2.0));
+        assert!(close(integral("integral(ln(t), t, 0, 1)")?, -1.0));
+        // Divergent integrals give the reason, undefined values NaN
+        for input in ["integral(1/t, t, 0, 1)", "integral(1/t, t, 1, inf)", "integral(sin(t), t, 0, inf)", "integral(1/t, t, -1, 1)"] {
+            assert_eq!(integral(input)?, Err(EvalError::NotConverged(Span::new(0, input.len()))), "{}", input);
+        }
+        assert!(integral("integral(sqrt(t), t, -1, 1)")?.is_ok_and(f64::is_nan));
+        assert!(matches!(integral("integral(t, 2, 0, 1)")?, Err(EvalError::InvalidVariable(_))));
+        // Differentiated under the integral sign, with the moving bound
+        assert_eq!(derive(&parse_expression("integral(t*x, t, 0, x)")?, "x").to_string(), "integral(t, t, 0, x) + x*x");
+        assert_eq!(derive(&parse_expression("integral(t^2, t, 0, 1)")?, "x").to_string(), "0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_limit() -> Result<(), ParseError> {
+        let mut variables = HashMap::new();
+        let mut value = |input : &str| parse_expression(input).map(|e| e.try_evaluate_f64(2.0, &mut variables));
+        let close = |value : Result<f64, EvalError>, expected : f64| value.is_ok_and(|v| (v - expected).abs() <= 1e-6 * expected.abs().max(1.0));
+        assert!(close(value("limit(sin(t)/t, t, 0)")?, 1.0));
+        assert!(close(value("limit((t^2 - 1)/(t - 1), t, 1)")?, 2.0));
+        assert!(close(value("limit((1 - cos(t))/t^2, t, 0)")?, 0.5));
+        assert!(close(value("limit((exp(t) - 1)/t, t, 0)")?, 1.0));
+        assert!(close(value("limit((1 + 1/t)^t, t, inf)")?, std::f64::consts::E));
+        assert!(close(value("limit(t/(t + 1), t, -inf)")?, 1.0));
+        assert!(close(value("limit(t*ln(t), t, 0, 1)")?, 0.0));
+        // Only the side with values counts, and x outside is still the input
+        assert!(close(value("limit(sqrt(t), t, 0)")?, 0.0));
+        assert!(close(value("limit(x + t, t, 1)")?, 3.0));
+        // One-sided limits, a different value on each side or none at all are errors
+        assert!(close(value("limit(abs(t)/t, t, 0, -1)")?, -1.0));
+        assert!(close(value("limit(floor(t), t, 1, 1)")?, 1.0));
+        let mismatch = "limit(abs(t)/t, t, 0)";
+        assert_eq!(value(mismatch)?, Err(EvalError::OneSidedMismatch(Span::new(0, mismatch.len()))));
+        for input in ["limit(1/t, t, 0)", "limit(sin(1/t), t, 0)", "limit((1 - cos(t))/t^3, t, 0, 1)", "limit(t, t, inf)"] {
+            assert_eq!(value(input)?, Err(EvalError::NotConverged(Span::new(0, input.len()))), "{}", input);
+        }
+
+        assert!(close(value("deriv(t^3, t, 2)")?, 12.0));
+        assert!(close(value("deriv(x*t^2, t, 3)")?, 12.0));
+        assert!(close(value("deriv(exp(t), t, 10)")?, 10f64.exp()));
+        assert!(close(value("deriv(tan(t), t, 1.5)")?, 1.0 / 1.5f64.cos().powi(2)));
+        assert!(matches!(value("deriv(1/t, t, 0)")?, Err(EvalError::NotConverged(_))));
+        assert!(value("deriv(sqrt(t), t, -1)")?.is_ok_and(f64::is_nan));
+        // Unknown how they move with what they depend on outside
+        assert_eq!(derive(&parse_expression("deriv(t^3, t, 2) + limit(t*a, t, 0)")?, "x").to_string(), "0");
+        assert!(derive(&parse_expression("deriv(x*t, t, 2)")?, "x").value().is_some_and(f64::is_nan));
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve() -> Result<(), ParseError> {
+        let equation = |lhs : &str, rhs : &str, around : f64| Ok::<_, ParseError>(solve(&parse_expression(lhs)?, &parse_expression(rhs)?, around));
+        assert_eq!(equation("x^2", "4", 0.0)?, Solutions::Exact(vec![-2.0, 2.0]));
+        assert_eq!(equation("3x + 1", "x", 0.0)?, Solutions::Exact(vec![-0.5]));
+        assert_eq!(equation("(x - 0.1)^2", "0", 0.0)?, Solutions::Exact(vec![0.1]));
+        assert_eq!(equation("x^2 + 1", "0", 0.0)?, Solutions::Exact(vec![]));
+        assert_eq!(equation("2x", "x + x", 0.0)?, Solutions::All);
+        // Not a polynomial of degree 2 at most, the roots of the first search that has some
+        let Solutions::Numeric(xs) = equation("x^3", "2x + 1", 3.0)? else { panic!("x^3 is solved numerically") };
+        assert_eq!(xs.len(), 1);
+        assert!((xs[0] - 1.618033988749895).abs() <= 1e-12);
+        assert!((equation("x^3", "2x + 1", 0.0)?.closest(0.0) + 0.6180339887498949).abs() <= 1e-12);
+        assert_eq!(polynomial_degree(&parse_expression("(a*t + 1)^2/2 - t")?, "t"), Some(2));
+        assert_eq!(polynomial_degree(&parse_expression("t^-1")?, "t"), None);
+
+        let mut functions = UserFunctions::new();
+        parse_with_functions("p(q) = 20q^1.5 + 3", &mut functions)?;
+        let mut value = |input : &str| parse_with_functions(input, &mut functions).map(|e| e.try_evaluate_f64_with(2.0, &mut HashMap::from([("a".to_string(), 3.0)]), &functions));
+        let close = |value : Result<f64, EvalError>, expected : f64| value.is_ok_and(|v| (v - expected).abs() <= 1e-9 * expected.abs().max(1.0));
+        assert!(close(value("solve(p(q) = 500, q)")?, (497.0f64 / 20.0).powf(2.0 / 3.0)));
+        // The solution closest to the guess, 0 by default
+        assert!(close(value("solve(t^2 = 4, t)")?, -2.0));
+        assert!(close(value("solve(t^2 = 4, t, 1)")?, 2.0));
+        assert!(close(value("solve(a*t = 6, t)")?, 2.0));
+        assert!(close(value("solve(t*x - 6, t)")?, 3.0));
+        assert!(close(value("solve(cos(t) = t, t)")?, 0.7390851332151607));
+        assert!(close(value("solve(exp(t) == 1e5, t, 10)")?, 1e5f64.ln()));
+        assert!(close(value("solve(t = t, t, 5)")?, 5.0));
+        assert!(value("solve(t^2 = -1, t)")?.is_ok_and(f64::is_nan));
+        assert!(matches!(value("solve(t^2 = 4, 2)")?, Err(EvalError::InvalidVariable(_))));
+
+        let e = parse_expression("solve(t^2 = 4, t, 1)")?;
+        assert_eq!(e.to_string(), "solve(t^2 = 4, t, 1)");
+        assert_eq!(e.to_latex(), "\\operatorname{solve}\\left(t^{2} = 4, t, 1\\right)");
+        assert_eq!(parse_latex(&e.to_latex())?.try_evaluate_f64(0.0, &mut HashMap::new()), Ok(2.0));
+        assert_eq!(derive(&e, "x").to_string(), "0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_critical_points() -> Result<(), ParseError> {
+        let find = |input : &str, a : f64, b : f64| parse_expression(input).map(|e| critical_points(&e, a, b));
+        let kinds = |points : &[CriticalPoint]| points.iter().map(|p| p.kind).collect::<Vec<_>>();
+        let cubic = find("x^3 - 3x", -3.0, 3.0)?;
+        assert_eq!(kinds(&cubic), vec![PointKind::Maximum, PointKind::Inflection, PointKind::Minimum]);
+        for (point, (x, y)) in cubic.iter().zip([(-1.0, 2.0), (0.0, 0.0), (1.0, -2.0)]) {
+            assert!((point.x - x).abs() < 1e-12 && (point.y - y).abs() < 1e-12);
+        }
+        let bell = find("exp(-x^2)", -2.9, 3.0)?;
+        assert_eq!(kinds(&bell), vec![PointKind::Inflection, PointKind::Maximum, PointKind::Inflection]);
+        assert!((bell[0].x + 0.5f64.sqrt()).abs() < 1e-12 && bell[1].x.abs() < 1e-12 && (bell[2].x - 0.5f64.sqrt()).abs() < 1e-12);
+        // Flat without a sign change of the second derivative, and a kink
+        assert_eq!(kinds(&find("x^4", -1.0, 1.3)?), vec![PointKind::Minimum]);
+        let kink = find("abs(x - 0.5)", -1.0, 1.3)?;
+        assert!(kink.len() == 1 && kink[0].kind == PointKind::Minimum && (kink[0].x - 0.5).abs() < 1e-12);
+        // Poles and jumps are neither, the ends of the interval are not extrema
+        assert_eq!(kinds(&find("tan(x)", -2.0, 2.0)?), vec![PointKind::Inflection]);
+        assert!(find("1/x^2", -2.0, 2.0)?.is_empty());
+        assert!(find("floor(x)", -2.0, 2.0)?.is_empty());
+        assert!(find("x", -2.0, 2.0)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interval() -> Result<(), ParseError> {
+        let mut variables = HashMap::new();
+        let mut interval = |input : &str, lo : f64, hi : f64| parse_expression(input).map(|e| e.evaluate_interval(Interval::new(lo, hi), &mut variables));
+        assert_eq!(interval("x + 1", 1.0, 2.0)?, Interval::new(2.0, 3.0));
+        // Each x is taken on its own, so the bounds are wider than the range of the function
+        let bounds = interval("x^2 - 2x", -1.0, 3.0)?;
+        assert!(bounds.lo <= -6.0 && bounds.hi >= 11.0 && bounds.width() < 17.1);
+        assert_eq!(interval("x²", -1.0, 3.0)?, Interval::new(0.0, 9.0));
+        assert_eq!(interval("sin(x)", 0.0, 10.0)?, Interval::new(-1.0, 1.0));
+        assert_eq!(interval("cos(x)", -1.0, 1.0)?.hi, 1.0);
+        assert_eq!(interval("sqrt(x)", -4.0, 4.0)?, Interval::new(0.0, 2.0));
+        assert!(interval("ln(x)", -2.0, -1.0)?.is_empty());
+        // Poles give infinite bounds
+        assert!(!interval("1/x", -1.0, 1.0)?.is_bounded());
+        assert_eq!(interval("1/x", 0.0, 2.0)?, Interval::new(0.5, f64::INFINITY));
+        assert!(!interval("tan(x)", 1.0, 2.0)?.is_bounded());
+        assert!(interval("tan(x)", -1.0, 1.0)?.is_bounded());
+        // Both branches when the condition changes over the interval
+        assert_eq!(interval("if(x > 0, 1, -1)", -1.0, 1.0)?, Interval::new(-1.0, 1.0));
+        assert_eq!(interval("if(x > 0, 1, -1)", 0.5, 1.0)?, Interval::point(1.0));
+        assert_eq!(interval("x < 2", 0.0, 3.0)?, Interval::new(0.0, 1.0));
+        assert_eq!(interval("sum(i=1, 3, x*i)", 0.0, 1.0)?, Interval::new(0.0, 6.0));
+        assert_eq!(parse_expression("sum(i=1, x, i)")?.try_evaluate_interval(Interval::new(1.0, 3.0), &mut HashMap::new()), Err(EvalError::UndeterminedBound(Span::new(9, 10))));
+
+        // Every value of the f64 evaluation is inside
+        let inputs = ["x^3 - 2x + 1", "sin(3x) / (1 + x^2)", "exp(-x) * cos(x)", "abs(x - 1) + floor(x)", "atan(x) + tanh(x) + cosh(x) - asinh(x)", "0.1 * x^-2", "max(x, 2 - x) * min(1, x)"];
+        for input in inputs {
+            let expression = parse_expression(input)?;
+            for (lo, hi) in [(-3.0, -2.5), (-0.4, 0.7), (0.1, 0.1 + 1e-9), (1.0, 9.0)] {
+                let bounds = expression.evaluate_interval(Interval::new(lo, hi), &mut HashMap::new());
+                for k in 0..=100 {
+                    let x = lo + (hi - lo) * k as f64 / 100.0;
+                    let y = expression.evaluate_f64(x, &mut HashMap::new());
+                    assert!(bounds.contains(y) || y.is_infinite() && !bounds.is_bounded(), "{} at {} : {} not in {}", input, x, y, bounds);
+                }
+            }
+        }
+        // Built-ins computed in double precision have no bound on their error
+        for input in ["roots(x^2 - 2, 0, x)", "roots(x^2 - 2, 0, 3)", "intersect(x^2, 2, -x, 0)", "integral(x*t, t, 0, 1)", "deriv(x*t^2, t, 1)", "limit(x*sin(t)/t, t, 0)", "solve(t*x = 1, t)", "integral(t, t, 0, 1)"] {
+            assert_eq!(interval(input, 1.0, 2.0)?, Interval::ENTIRE, "{}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_linear_parsing() {
+        // Arguments of one call are not nested, the input can grow past MAX_DEPTH
+        let input = |args : usize| format!("max({})", (0..args).map(|i| format!("{}.25*x^{}", i + 1, i % 5)).collect::<Vec<_>>().join(", "));
+        let fastest = |input : &str| (0..5).map(|_| {
+            let start = std::time::Instant::now();
+            parse_expression(input).unwrap();
+            start.elapsed()
+        }).min().unwrap();
+        let (short, long) = (input(1000), input(8000));
+        // 8 times the input takes about 8 times longer, a quadratic parser 64 times
+        let ratio = fastest(&long).as_secs_f64() / fastest(&short).as_secs_f64();
+        assert!(ratio < 24.0, "parsing 8 times the input took {:.1} times longer", ratio);
+    }
+
+    // Replays the checked-in fuzz corpus of a target, same body as its file in fuzz/fuzz_targets
+    fn replay(target : &str, parse : fn(&str) -> Result<Expression, ParseError>) {
+        let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus").join(target);
+        let mut count = 0;
+        for entry in std::fs::read_dir(corpus).unwrap() {
+            let data = std::fs::read(entry.unwrap().path()).unwrap();
+            let Ok(input) = std::str::from_utf8(&data) else { continue; };
+            if let Ok(expression) = parse(input) {
+                let mut variables = HashMap::new();
+                for x in [0.0, -1.0, 30.0, f32::NAN, f32::INFINITY] {
+                    let _ = expression.evaluate(x, &mut variables);
+                }
+            }
+            count += 1;
+        }
+        assert!(count > 0, "Fuzz corpus of {} is empty", target);
+    }
+
+    #[test]
+    fn test_fuzz_corpus() {
+        replay("parse_evaluate", parse_expression);
+        replay("parse_latex", parse_latex);
+    }
+}
@@ -0,0 +1,153 @@
+use std::ops::{Add, Mul};
+
+use super::{Multi, MultiFunction, Simple, SimpleFunction, MAX_ITERATIONS};
+
+/// Number type the tree is evaluated with. f32 calls the functions stored in the tree,
+/// the others have their own version of the operators and built-ins.
+pub trait Number : Clone + Add<Output = Self> + Mul<Output = Self> {
+    fn from_f64(c : f64) -> Self;
+    fn to_f64(&self) -> f64;
+    // Condition of an if, positive is true. None when it can be both, then both branches are taken.
+    fn is_true(&self) -> Option<bool>;
+    // Value covering both branches of an if whose condition is None
+    fn union(self, _ : Self) -> Self {
+        self
+    }
+    // Bound of a sum or prod, None when it is not a single integer
+    fn to_bound(&self) -> Option<i64> {
+        // Float to int casts saturate, computing in i64 keeps `current + 1` from overflowing
+        Some(self.to_f64().round() as i32 as i64)
+    }
+    // Whether the value cannot vary, false for a Dual carrying a derivative
+    fn is_constant(&self) -> bool {
+        true
+    }
+    // Value of a built-in computed in double precision, `value` at x and `at` giving it at other values of x.
+    // `varies` tells a variable it reads changes with x, which `at` does not follow.
+    fn from_numeric(value : f64, _x : &Self, _at : impl FnMut(f64) -> f64, _varies : bool) -> Self {
+        Self::from_f64(value)
+    }
+    // Value of `i` when nothing assigned it, 0 like any other variable for the real types
+    fn imaginary_unit() -> Self {
+        Self::from_f64(0.0)
+    }
+    fn simple(name : Simple, f : SimpleFunction, x : Self) -> Self;
+    fn multi(name : Multi, f : MultiFunction, x : &[Self]) -> Self;
+
+    // Limit of the iterations of a sum or prod, lowered by the slower types
+    fn max_iterations() -> i64 {
+        MAX_ITERATIONS
+    }
+}
+
+impl Number for f32 {
+    fn from_f64(c : f64) -> f32 {
+        c as f32
+    }
+
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+
+    fn is_true(&self) -> Option<bool> {
+        Some(*self > 0.0)
+    }
+
+    fn simple(_ : Simple, f : SimpleFunction, x : f32) -> f32 {
+        f(x)
+    }
+
+    fn multi(_ : Multi, f : MultiFunction, x : &[f32]) -> f32 {
+        f(x)
+    }
+}
+
+impl Number for f64 {
+    fn from_f64(c : f64) -> f64 {
+        c
+    }
+
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+
+    fn is_true(&self) -> Option<bool> {
+        Some(*self > 0.0)
+    }
+
+    fn simple(name : Simple, f : SimpleFunction, x : f64) -> f64 {
+        match name {
+            Simple::Plus => x,
+            Simple::Minus => -x,
+            Simple::Square => x * x,
+            Simple::Factorial => factorial(x),
+            Simple::Sin => x.sin(),
+            Simple::Cos => x.cos(),
+            Simple::Tan => x.tan(),
+            Simple::Cot => 1.0 / x.tan(),
+            Simple::Sec => 1.0 / x.cos(),
+            Simple::Csc => 1.0 / x.sin(),
+            Simple::Abs => x.abs(),
+            Simple::Ceil => x.ceil(),
+            Simple::Floor => x.floor(),
+            Simple::Round => x.round(),
+            Simple::Exp => x.exp(),
+            Simple::Ln => x.ln(),
+            Simple::Log => x.log10(),
+            Simple::Sqrt => x.sqrt(),
+            Simple::Asin => x.asin(),
+            Simple::Acos => x.acos(),
+            Simple::Atan => x.atan(),
+            Simple::Sinh => x.sinh(),
+            Simple::Cosh => x.cosh(),
+            Simple::Tanh => x.tanh(),
+            Simple::Asinh => x.asinh(),
+            Simple::Acosh => x.acosh(),
+            Simple::Atanh => x.atanh(),
+            Simple::Re | Simple::Conj => x,
+            Simple::Im => 0.0 * x,
+            Simple::Arg => 0f64.atan2(x),
+            // Functions given from outside, like by create_from_function, only exist in f32
+            Simple::External => f(x as f32) as f64
+        }
+    }
+
+    fn multi(name : Multi, _ : MultiFunction, x : &[f64]) -> f64 {
+        let binary = |g : fn(f64, f64) -> f64| match x {
+            [a, b] => g(*a, *b),
+            _ => f64::NAN
+        };
+        match name {
+            Multi::Add => binary(|a, b| a + b),
+            Multi::Sub => binary(|a, b| a - b),
+            Multi::Mul => binary(|a, b| a * b),
+            Multi::Div => binary(|a, b| a / b),
+            Multi::Pow | Multi::PowCall => binary(f64::powf),
+            Multi::Equal => binary(|a, b| if a == b {1.0} else {0.0}),
+            Multi::NotEqual => binary(|a, b| if a != b {1.0} else {0.0}),
+            Multi::LessEqual => binary(|a, b| if a <= b {1.0} else {0.0}),
+            Multi::Less => binary(|a, b| if a <  b {1.0} else {0.0}),
+            Multi::GreaterEqual => binary(|a, b| if a >= b {1.0} else {0.0}),
+            Multi::Greater => binary(|a, b| if a >  b {1.0} else {0.0}),
+            Multi::Max => x.iter().fold(f64::MIN, |max, next| max.max(*next)),
+            Multi::Min => x.iter().fold(f64::MAX, |min, next| min.min(*next)),
+        }
+    }
+}
+
+pub(super) fn factorial(x : f64) -> f64 {
+    if x < 0.0 {
+        return f64::NAN;
+    }
+    // 171! is already out of f64 range
+    if x >= 171.0 {
+        return f64::INFINITY;
+    }
+    let mut result = 1.0;
+    let mut n = x as u32;
+    while n > 1 {
+        result *= n as f64;
+        n -= 1;
+    }
+    result
+}
@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+
+use super::{EvalError, Expression, Function, MultiFunction, Operator, SimpleFunction, Span, UserFunctions, MAX_CALL_DEPTH, MAX_ITERATIONS, unassigned};
+
+/// Most instructions in a program, inlining user functions can make it grow exponentially
+const MAX_PROGRAM_SIZE : usize = 1 << 16;
+
+// Slot holding the x input, a parameter named x is stored in it
+const X_SLOT : usize = 0;
+
+enum Instruction {
+    Const(f32),
+    Load(usize),
+    // Moves the top of the stack to a slot
+    Pop(usize),
+    Drop(usize),
+    Simple(SimpleFunction),
+    // Function and number of arguments taken from the top of the stack
+    Multi(MultiFunction, usize),
+    Jump(usize),
+    // Pops the condition of an `if` and jumps to the else branch unless it is positive
+    JumpUnless(usize),
+    Copy(usize, usize),
+    // Saves and restores the slots of a scope, see `Program::scopes`
+    Enter(usize),
+    Exit(usize),
+    // Pops the bounds of a sum or product and pushes the accumulator, jumps to `exit` when there is nothing to iterate
+    LoopStart { counter : usize, slot : usize, init : f32, exit : usize, span : Span },
+    // Folds the value of the body into the accumulator and jumps back to `body` while iterations remain
+    LoopNext { counter : usize, slot : usize, fold : fn(f32, f32) -> f32, body : usize },
+    Fail(EvalError),
+}
+
+/// Expression compiled to a flat stack machine by `Expression::compile`.
+/// Variables are resolved to slots and user functions are inlined, running it does not allocate.
+pub struct Program {
+    code : Vec<Instruction>,
+    // Slots loaded from the variables
+    variables : Vec<(String, usize)>,
+    // Slots assigned outside of any sum or call, stored back in the variables
+    assigned : Vec<(String, usize)>,
+    // Slots a sum or a call saves on entering and restores on leaving, with the slot they are saved in
+    scopes : Vec<Vec<(usize, usize)>>,
+    slot_count : usize,
+    counter_count : usize,
+    stack_size : usize,
+    scope_depth : usize,
+}
+
+/// Memory of a running program, reuse it between evaluations to avoid allocating
+pub struct Registers {
+    slots : Vec<f32>,
+    stack : Vec<f32>,
+    // Current and last iteration of every sum and product
+    counters : Vec<(i64, i64)>,
+    // Scopes entered so far, restored when an error stops the program
+    scopes : Vec<usize>,
+}
+
+impl Expression {
+    /// Compiles the expression with the user functions as they are now, a later redefinition needs a new compilation.
+    /// Returns None when a user function calls itself, the tree has to be evaluated instead.
+    pub fn compile(&self, functions : &UserFunctions) -> Option<Program> {
+        let mut compiler = Compiler {
+            functions,
+            program : Program {
+                code : vec![],
+                variables : vec![],
+                assigned : vec![],
+                scopes : vec![],
+                slot_count : 1,
+                counter_count : 0,
+                stack_size : 0,
+                scope_depth : 0,
+            },
+            slots : HashMap::new(),
+            inlining : vec![],
+            call_span : None,
+            stack : 0,
+            scope_depth : 0,
+        };
+        compiler.expression(self)?;
+        if compiler.program.code.len() > MAX_PROGRAM_SIZE { return None; }
+
+        let mut names = vec![];
+        assigned_names(self, &mut names);
+        for name in names {
+            let slot = compiler.slot(name);
+            compiler.program.assigned.push((name.to_string(), slot));
+        }
+        Some(compiler.program)
+    }
+}
+
+// Variables an expression may assign in the scope it is evaluated in,
+// the body of a sum or of a call keeps its assignments to itself
+fn assigned_names<'a>(e : &'a Expression, names : &mut Vec<&'a str>) {
+    match (&e.function, &e.params[..]) {
+        (Function::Assign, [target, ..]) => {
+            if let Function::Variable(name) = &target.function { names.push(name); }
+            e.params.iter().for_each(|p| assigned_names(p, names));
+        },
+        (Function::Iterator(_), [start, end, _]) => {
+            if let [_, first] = &start.params[..] { assigned_names(first, names); }
+            assigned_names(end, names);
+        },
+        _ => e.params.iter().for_each(|p| assigned_names(p, names))
+    }
+}
+
+struct Compiler<'a> {
+    functions : &'a UserFunctions,
+    program : Program,
+    slots : HashMap<&'a str, usize>,
+    // User functions being inlined, a function calling itself cannot be
+    inlining : Vec<&'a str>,
+    // Span of the outermost inlined call, errors in a function body point at the call like in the tree
+    call_span : Option<Span>,
+    stack : usize,
+    scope_depth : usize,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, instruction : Instruction) {
+        let (pop, push) = match &instruction {
+            Instruction::Const(_) | Instruction::Load(_) => (0, 1),
+            Instruction::Pop(_) | Instruction::JumpUnless(_) | Instruction::LoopNext { .. } => (1, 0),
+            Instruction::Drop(n) => (*n, 0),
+            Instruction::Simple(_) => (1, 1),
+            Instruction::Multi(_, n) => (*n, 1),
+            Instruction::LoopStart { .. } => (2, 1),
+            Instruction::Jump(_) | Instruction::Copy(_, _) | Instruction::Enter(_) | Instruction::Exit(_) | Instruction::Fail(_) => (0, 0),
+        };
+        self.stack = self.stack.saturating_sub(pop) + push;
+        self.program.stack_size = self.program.stack_size.max(self.stack);
+        self.program.code.push(instruction);
+    }
+
+    // Points the jump at `index` to the next instruction
+    fn patch(&mut self, index : usize) {
+        let next = self.program.code.len();
+        match &mut self.program.code[index] {
+            Instruction::Jump(target) | Instruction::JumpUnless(target) | Instruction::LoopStart { exit : target, .. } => *target = next,
+            _ => ()
+        }
+    }
+
+    // The failing node takes `consumed` values and stands for one
+    fn fail(&mut self, error : EvalError, consumed : usize) {
+        let span = self.span(error.span());
+        self.emit(Instruction::Fail(error.with_span(span)));
+        self.stack = self.stack.saturating_sub(consumed) + 1;
+    }
+
+    fn span(&self, span : Span) -> Span {
+        self.call_span.unwrap_or(span)
+    }
+
+    fn slot(&mut self, name : &'a str) -> usize {
+        if let Some(slot) = self.slots.get(name) { return *slot; }
+        let slot = self.new_slot();
+        self.slots.insert(name, slot);
+        self.program.variables.push((name.to_string(), slot));
+        slot
+    }
+
+    fn new_slot(&mut self) -> usize {
+        self.program.slot_count += 1;
+        self.program.slot_count - 1
+    }
+
+    fn enter(&mut self, mut slots : Vec<usize>) -> usize {
+        slots.sort_unstable();
+        slots.dedup();
+        let saved = slots.into_iter().map(|slot| (slot, self.new_slot())).collect();
+        self.program.scopes.push(saved);
+        let scope = self.program.scopes.len() - 1;
+        self.emit(Instruction::Enter(scope));
+        self.scope_depth += 1;
+        self.program.scope_depth = self.program.scope_depth.max(self.scope_depth);
+        scope
+    }
+
+    fn exit(&mut self, scope : usize) {
+        self.emit(Instruction::Exit(scope));
+        self.scope_depth -= 1;
+    }
+
+    fn arguments(&mut self, e : &'a Expression) -> Option<usize> {
+        for param in &e.params { self.expression(param)?; }
+        Some(e.params.len())
+    }
+
+    fn drop(&mut self, n : usize) {
+        if n > 0 { self.emit(Instruction::Drop(n)); }
+    }
+
+    // Follows `Expression::eval`, every node leaves exactly one value on the stack
+    fn expression(&mut self, e : &'a Expression) -> Option<()> {
+        if self.program.code.len() > MAX_PROGRAM_SIZE { return None; }
+        match &e.function {
+            Function::If => return self.condition(e),
+            Function::Iterator(o) => return self.iterator(e, o),
+            Function::Call(name) => return self.call(e, name),
+            // Evaluates its first argument at other values of x, left to the tree
+            Function::Numeric(_) => return None,
+            Function::Assign => {
+                let n = self.arguments(e)?;
+                match (e.params.first().map(|p| &p.function), n) {
+                    (Some(Function::Variable(name)), 2..) => {
+                        // Only the assigned value stays, the target is evaluated below it like in the tree
+                        self.drop(n - 2);
+                        let slot = self.slot(name);
+                        self.emit(Instruction::Pop(slot));
+                        self.emit(Instruction::Drop(1));
+                        self.emit(Instruction::Load(slot));
+                    },
+                    _ => self.fail(EvalError::InvalidAssignment(e.span), n)
+                }
+            },
+            Function::SimpleFunction(_, f) => {
+                match self.arguments(e)? {
+                    0 => self.fail(EvalError::MissingArgument(e.span), 0),
+                    n => {
+                        self.drop(n - 1);
+                        self.emit(Instruction::Simple(*f));
+                    }
+                }
+            },
+            Function::MultiFunction(_, f) => {
+                let n = self.arguments(e)?;
+                self.emit(Instruction::Multi(*f, n));
+            },
+            Function::Constant(c) => {
+                let n = self.arguments(e)?;
+                self.drop(n);
+                self.emit(Instruction::Const(*c as f32));
+            },
+            Function::InputX => {
+                let n = self.arguments(e)?;
+                self.drop(n);
+                self.emit(Instruction::Load(X_SLOT));
+            },
+            Function::Variable(name) => {
+                let n = self.arguments(e)?;
+                self.drop(n);
+                let slot = self.slot(name);
+                self.emit(Instruction::Load(slot));
+            },
+        }
+        Some(())
+    }
+
+    fn condition(&mut self, e : &'a Expression) -> Option<()> {
+        let [condition, if_true, if_false] = &e.params[..] else {
+            self.fail(EvalError::MissingArgument(e.span), 0);
+            return Some(());
+        };
+        self.expression(condition)?;
+        let jump_else = self.program.code.len();
+        self.emit(Instruction::JumpUnless(0));
+        let stack = self.stack;
+        self.expression(if_true)?;
+        let jump_end = self.program.code.len();
+        self.emit(Instruction::Jump(0));
+        self.stack = stack;
+        self.patch(jump_else);
+        self.expression(if_false)?;
+        self.patch(jump_end);
+        Some(())
+    }
+
+    fn iterator(&mut self, e : &'a Expression, o : &Operator) -> Option<()> {
+        let [start, end, body] = &e.params[..] else { self.fail(EvalError::InvalidIterator(e.span), 0); return Some(()); };
+        let (Function::Assign, [iterator, first]) = (&start.function, &start.params[..]) else { self.fail(EvalError::InvalidIterator(start.span), 0); return Some(()); };
+        let Function::Variable(it) = &iterator.function else { self.fail(EvalError::InvalidIterator(iterator.span), 0); return Some(()); };
+
+        // The body sees the variables as they were before the bounds, like the copy made by the tree
+        let mut names = vec![];
+        assigned_names(end, &mut names);
+        assigned_names(first, &mut names);
+        let before : Vec<(usize, usize)> = names.iter().map(|name| (self.slot(name), self.new_slot())).collect();
+        for (slot, saved) in &before { self.emit(Instruction::Copy(*slot, *saved)); }
+
+        self.expression(end)?;
+        self.expression(first)?;
+
+        assigned_names(body, &mut names);
+        names.push(it);
+        let slots = names.into_iter().map(|name| self.slot(name)).collect();
+        let scope = self.enter(slots);
+        for (slot, saved) in &before { self.emit(Instruction::Copy(*saved, *slot)); }
+
+        let counter = self.program.counter_count;
+        self.program.counter_count += 1;
+        let slot = self.slot(it);
+        let (init, fold) : (f32, fn(f32, f32) -> f32) = match o {
+            Operator::Add => (0.0, |a, b| a + b),
+            _ => (1.0, |a, b| a * b)
+        };
+        let loop_start = self.program.code.len();
+        self.emit(Instruction::LoopStart { counter, slot, init, exit : 0, span : self.span(e.span) });
+        self.expression(body)?;
+        self.emit(Instruction::LoopNext { counter, slot, fold, body : loop_start + 1 });
+        self.patch(loop_start);
+        self.exit(scope);
+        Some(())
+    }
+
+    fn call(&mut self, e : &'a Expression, name : &'a str) -> Option<()> {
+        let n = self.arguments(e)?;
+        let Some(function) = self.functions.get(name) else { self.fail(EvalError::UnknownFunction(e.span), n); return Some(()); };
+        if function.params.len() != n { self.fail(EvalError::WrongArity(e.span), n); return Some(()); }
+        if self.inlining.len() >= MAX_CALL_DEPTH { self.fail(EvalError::RecursionTooDeep(e.span), n); return Some(()); }
+        if self.inlining.contains(&name) { return None; }
+
+        // Parameters are scoped like the iterator of a sum, a parameter named x replaces the input
+        let params : Vec<usize> = function.params.iter().map(|param| if param == "x" { X_SLOT } else { self.slot(param) }).collect();
+        let mut names = vec![];
+        assigned_names(&function.body, &mut names);
+        let mut slots : Vec<usize> = names.into_iter().map(|name| self.slot(name)).collect();
+        slots.extend(&params);
+        let scope = self.enter(slots);
+        for slot in params.iter().rev() { self.emit(Instruction::Pop(*slot)); }
+
+        let outer_span = self.call_span;
+        self.call_span = Some(self.span(e.span));
+        self.inlining.push(name);
+        self.expression(&function.body)?;
+        self.inlining.pop();
+        self.call_span = outer_span;
+        self.exit(scope);
+        Some(())
+    }
+}
+
+impl Program {
+    /// Registers holding the current value of the variables
+    pub fn registers(&self, variables : &HashMap<String, f32>) -> Registers {
+        let mut slots = vec![0.0; self.slot_count];
+        for (name, slot) in &self.variables {
+            slots[*slot] = variables.get(name).copied().unwrap_or_else(|| unassigned(name));
+        }
+        Registers {
+            slots,
+            stack : Vec::with_capacity(self.stack_size),
+            counters : vec![(0, 0); self.counter_count],
+            scopes : Vec::with_capacity(self.scope_depth),
+        }
+    }
+
+    /// Writes the variables assigned by the program back
+    pub fn store(&self, registers : &Registers, variables : &mut HashMap<String, f32>) {
+        for (name, slot) in &self.assigned {
+            variables.insert(name.clone(), registers.slots[*slot]);
+        }
+    }
+
+    pub fn evaluate(&self, x : f32, variables : &mut HashMap<String, f32>) -> f32 {
+        self.try_evaluate(x, variables).unwrap_or(f32::NAN)
+    }
+
+    /// Same result as evaluating the tree, allocates its registers so prefer `run` when evaluating many times
+    pub fn try_evaluate(&self, x : f32, variables : &mut HashMap<String, f32>) -> Result<f32, EvalError> {
+        let mut registers = self.registers(variables);
+        let res = self.run(x, &mut registers);
+        self.store(&registers, variables);
+        res
+    }
+
+    /// Evaluates at x with the variables held by the registers, assignments stay in the registers until `store`
+    pub fn run(&self, x : f32, registers : &mut Registers) -> Result<f32, EvalError> {
+        // Registers made for another program
+        if registers.slots.len() < self.slot_count || registers.counters.len() < self.counter_count {
+            registers.slots.resize(self.slot_count.max(registers.slots.len()), 0.0);
+            registers.counters.resize(self.counter_count.max(registers.counters.len()), (0, 0));
+        }
+        let Registers { slots, stack, counters, scopes } = registers;
+        slots[X_SLOT] = x;
+        stack.clear();
+        scopes.clear();
+
+        let mut pc = 0;
+        while let Some(instruction) = self.code.get(pc) {
+            pc += 1;
+            match instruction {
+                Instruction::Const(c) => stack.push(*c),
+                Instruction::Load(slot) => stack.push(slots[*slot]),
+                Instruction::Pop(slot) => slots[*slot] = stack.pop().unwrap_or(f32::NAN),
+                Instruction::Drop(n) => stack.truncate(stack.len().saturating_sub(*n)),
+                Instruction::Simple(f) => if let Some(value) = stack.last_mut() { *value = f(*value); },
+                Instruction::Multi(f, n) => {
+                    let start = stack.len().saturating_sub(*n);
+                    let value = f(&stack[start..]);
+                    stack.truncate(start);
+                    stack.push(value);
+                },
+                Instruction::Jump(target) => pc = *target,
+                Instruction::JumpUnless(target) => if !stack.pop().is_some_and(|condition| condition > 0.0) { pc = *target; },
+                Instruction::Copy(from, to) => slots[*to] = slots[*from],
+                Instruction::Enter(scope) => {
+                    for (slot, saved) in &self.scopes[*scope] { slots[*saved] = slots[*slot]; }
+                    scopes.push(*scope);
+                },
+                Instruction::Exit(scope) => {
+                    for (slot, saved) in &self.scopes[*scope] { slots[*slot] = slots[*saved]; }
+                    scopes.pop();
+                },
+                Instruction::LoopStart { counter, slot, init, exit, span } => {
+                    let first = stack.pop().unwrap_or(f32::NAN);
+                    let end = stack.pop().unwrap_or(f32::NAN);
+                    // Same saturating casts as the tree
+                    let max = end.round() as i32 as i64;
+                    let current = first.round() as i32 as i64;
+                    if max - current >= MAX_ITERATIONS {
+                        self.unwind(slots, scopes);
+                        return Err(EvalError::TooManyIterations(*span));
+                    }
+                    stack.push(*init);
+                    counters[*counter] = (current, max);
+                    if current <= max { slots[*slot] = current as f32; } else { pc = *exit; }
+                },
+                Instruction::LoopNext { counter, slot, fold, body } => {
+                    let value = stack.pop().unwrap_or(f32::NAN);
+                    if let Some(accumulator) = stack.last_mut() { *accumulator = fold(*accumulator, value); }
+                    let (current, max) = &mut counters[*counter];
+                    *current += 1;
+                    if *current <= *max {
+                        slots[*slot] = *current as f32;
+                        pc = *body;
+                    }
+                },
+                Instruction::Fail(error) => {
+                    self.unwind(slots, scopes);
+                    return Err(error.clone());
+                },
+            }
+        }
+        Ok(stack.pop().unwrap_or(f32::NAN))
+    }
+
+    // Leaves the scopes an error stopped in, the registers keep only what the tree would have kept
+    fn unwind(&self, slots : &mut [f32], scopes : &mut Vec<usize>) {
+        while let Some(scope) = scopes.pop() {
+            for (slot, saved) in &self.scopes[scope] { slots[*slot] = slots[*saved]; }
+        }
+    }
+}
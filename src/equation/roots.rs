@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use super::{EvalError, Expression, UserFunctions};
+
+/// Segments of [a, b] checked for a sign change, roots closer than one of them to another can be missed
+const SCAN_SEGMENTS : usize = 1000;
+/// Most steps of Brent's method or of the bisection of a domain edge, both need less than 100 to reach the precision of a double
+const MAX_ITERATIONS : u32 = 200;
+/// Largest |f| at a root relative to |f| at the ends of its segment, jumps and poles change sign too but stay far from 0
+const MAX_RESIDUAL : f64 = 1e-6;
+/// Same for a root where the curve only touches 0, found at a minimum of |f| so its value is compared with the neighbours
+const MAX_TOUCHING_RESIDUAL : f64 = 1e-10;
+
+/// Zero of a function found by `find_roots`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Root {
+    pub x : f64,
+    /// Value at x, 0 or close to it for a root and the value of both curves for an intersection
+    pub y : f64,
+    /// Hint estimated from how fast |f| grows away from x, like 1 for x - 1, 2 for x² and 3 for x³.
+    /// Odd where the curve crosses 0 and even where it only touches it.
+    pub multiplicity : u32,
+    pub iterations : u32,
+    /// Whether x reached the precision of a double before the iterations ran out
+    pub converged : bool,
+}
+
+/// Roots of `expression` for x in [a, b] sorted by x, evaluated in double precision
+pub fn roots(e : &Expression, a : f64, b : f64) -> Vec<Root> {
+    roots_with(e, &HashMap::new(), &UserFunctions::new(), a, b).unwrap_or_default()
+}
+
+/// Same as `roots` with variables and user functions, the first failed evaluation is the error.
+/// Assignments in the expression are not kept.
+pub fn roots_with(e : &Expression, variables : &HashMap<String, f64>, functions : &UserFunctions, a : f64, b : f64) -> Result<Vec<Root>, EvalError> {
+    let mut scratch = variables.clone();
+    let mut error = None;
+    let res = find_roots(|x| match e.try_evaluate_f64_with(x, &mut scratch, functions) {
+        Ok(y) => y,
+        Err(e) => { error.get_or_insert(e); f64::NAN }
+    }, a, b);
+    match error {
+        Some(error) => Err(error),
+        None => Ok(res)
+    }
+}
+
+/// Points where the curves of `a` and `b` meet for x in [xmin, xmax] sorted by x, evaluated in double precision.
+/// They are the roots of a - b, crossings have an odd multiplicity and the places where the curves only touch an even one.
+pub fn intersections(a : &Expression, b : &Expression, xmin : f64, xmax : f64) -> Vec<Root> {
+    intersections_with(a, b, &HashMap::new(), &UserFunctions::new(), xmin, xmax).unwrap_or_default()
+}
+
+/// Same as `intersections` with variables and user functions, the first failed evaluation is the error.
+/// Assignments in the expressions are not kept.
+pub fn intersections_with(a : &Expression, b : &Expression, variables : &HashMap<String, f64>, functions : &UserFunctions, xmin : f64, xmax : f64) -> Result<Vec<Root>, EvalError> {
+    let mut scratch = variables.clone();
+    let mut error = None;
+    let mut f = |e : &Expression, x : f64| match e.try_evaluate_f64_with(x, &mut scratch, functions) {
+        Ok(y) => y,
+        Err(e) => { error.get_or_insert(e); f64::NAN }
+    };
+    let mut res = find_roots(|x| f(a, x) - f(b, x), xmin, xmax);
+    for root in &mut res {
+        root.y = f(a, root.x);
+    }
+    match error {
+        Some(error) => Err(error),
+        None => Ok(res)
+    }
+}
+
+/// Roots of f in [a, b] sorted by x. Segments where f changes sign are refined with Brent's method,
+/// the minima of |f| reaching 0 with the sign change of their central difference and the ends of the domain of f by bisection.
+/// Sign changes at a pole or a jump are not roots, neither are roots closer to each other than [a, b] / 1000
+/// nor the points of a part where f is 0 all along.
+pub fn find_roots(mut f : impl FnMut(f64) -> f64, a : f64, b : f64) -> Vec<Root> {
+    if !(a.is_finite() && b.is_finite() && a <= b) { return vec![]; }
+    if a == b {
+        let y = f(a);
+        return if y == 0.0 { vec![Root { x : a, y, multiplicity : 1, iterations : 0, converged : true }] } else { vec![] };
+    }
+    let step = (b - a) / SCAN_SEGMENTS as f64;
+    let xs : Vec<f64> = (0..=SCAN_SEGMENTS).map(|i| if i == SCAN_SEGMENTS { b } else { a + step * i as f64 }).collect();
+    let ys : Vec<f64> = xs.iter().map(|x| f(*x)).collect();
+    let tolerance = f64::EPSILON * a.abs().max(b.abs());
+
+    let mut res = vec![];
+    for i in 0..=SCAN_SEGMENTS {
+        let (x0, y0) = (xs[i], ys[i]);
+        if y0 == 0.0 {
+            // Isolated from the other zeros of the grid
+            let flat = (i > 0 && ys[i - 1] == 0.0) || (i < SCAN_SEGMENTS && ys[i + 1] == 0.0);
+            if !flat { res.push(Root { x : x0, y : y0, multiplicity : 0, iterations : 0, converged : true }); }
+            continue;
+        }
+        if i == SCAN_SEGMENTS { break; }
+        let (x1, y1) = (xs[i + 1], ys[i + 1]);
+        let root = if y0.is_finite() && y1.is_finite() && (y0 < 0.0) != (y1 < 0.0) && y1 != 0.0 {
+            brent(&mut f, x0, x1, y0, y1, tolerance).filter(|root| root.y.abs() <= MAX_RESIDUAL * y0.abs().max(y1.abs()))
+        } else if y0.is_nan() != y1.is_nan() && y1 != 0.0 {
+            domain_edge(&mut f, (x0, y0), (x1, y1), tolerance)
+        } else if i > 0 {
+            touching(&mut f, (xs[i - 1], ys[i - 1]), (x0, y0), (x1, y1), tolerance)
+        } else {
+            None
+        };
+        res.extend(root);
+    }
+
+    for root in &mut res {
+        if root.multiplicity == 0 {
+            // Exactly 0 on the grid, nothing tells whether it crosses. Neither does it at the end of the domain.
+            let sides = f(root.x - step / 64.0) * f(root.x + step / 64.0);
+            root.multiplicity = multiplicity(&mut f, root.x, step, (!sides.is_nan()).then_some(sides < 0.0));
+        }
+    }
+    res
+}
+
+// Brent's method on [a, b] where f changes sign, keeps a bracket while taking secant and inverse quadratic steps
+fn brent(f : &mut impl FnMut(f64) -> f64, mut a : f64, mut b : f64, mut fa : f64, mut fb : f64, tolerance : f64) -> Option<Root> {
+    let step = b - a;
+    let (mut c, mut fc) = (b, fb);
+    let (mut d, mut e) = (b - a, b - a);
+    for iterations in 1..=MAX_ITERATIONS {
+        // c is the other end of the bracket, b the best guess
+        if (fb > 0.0) == (fc > 0.0) {
+            (c, fc) = (a, fa);
+            (d, e) = (b - a, b - a);
+        }
+        if fc.abs() < fb.abs() {
+            (a, fa) = (b, fb);
+            (b, fb) = (c, fc);
+            (c, fc) = (a, fa);
+        }
+        let tol = 2.0 * f64::EPSILON * b.abs() + 0.5 * tolerance;
+        let middle = 0.5 * (c - b);
+        if middle.abs() <= tol || fb == 0.0 {
+            return Some(Root { x : b, y : fb, multiplicity : multiplicity(f, b, step, Some(true)), iterations, converged : true });
+        }
+        if e.abs() >= tol && fa.abs() > fb.abs() {
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * middle * s, 1.0 - s)
+            } else {
+                let (q, r) = (fa / fc, fb / fc);
+                (s * (2.0 * middle * q * (q - r) - (b - a) * (r - 1.0)), (q - 1.0) * (r - 1.0) * (s - 1.0))
+            };
+            if p > 0.0 { q = -q; } else { p = -p; }
+            // Interpolation only when it stays well inside the bracket and shrinks it fast enough, else bisection
+            if 2.0 * p < (3.0 * middle * q - (tol * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                (d, e) = (middle, middle);
+            }
+        } else {
+            (d, e) = (middle, middle);
+        }
+        (a, fa) = (b, fb);
+        b += if d.abs() > tol { d } else { tol.copysign(middle) };
+        fb = f(b);
+    }
+    Some(Root { x : b, y : fb, multiplicity : multiplicity(f, b, step, Some(true)), iterations : MAX_ITERATIONS, converged : false })
+}
+
+// Where f starts having values between a and b, a root when the value there is 0 like sqrt(x) at 0
+fn domain_edge(f : &mut impl FnMut(f64) -> f64, a : (f64, f64), b : (f64, f64), tolerance : f64) -> Option<Root> {
+    let (step, reference) = (b.0 - a.0, if a.1.is_nan() { b.1 } else { a.1 });
+    // `inside` has a value, `outside` does not
+    let (mut inside, mut outside) = if a.1.is_nan() { (b, a) } else { (a, b) };
+    let mut iterations = 0;
+    while iterations < MAX_ITERATIONS {
+        if (inside.0 - outside.0).abs() <= 2.0 * f64::EPSILON * inside.0.abs() + 0.5 * tolerance { break; }
+        let middle = (inside.0 + outside.0) / 2.0;
+        let y = f(middle);
+        if y.is_nan() { outside = (middle, y); } else { inside = (middle, y); }
+        iterations += 1;
+    }
+    (reference.is_finite() && inside.1.abs() <= MAX_RESIDUAL * reference.abs()).then(|| Root {
+        x : inside.0,
+        y : inside.1,
+        multiplicity : multiplicity(f, inside.0, step, None),
+        iterations,
+        converged : iterations < MAX_ITERATIONS
+    })
+}
+
+// Root where |f| has a minimum at the middle sample without changing sign, like x² at 0
+fn touching(f : &mut impl FnMut(f64) -> f64, before : (f64, f64), at : (f64, f64), after : (f64, f64), tolerance : f64) -> Option<Root> {
+    let ys = [before.1, at.1, after.1];
+    let minimum = at.1.abs() < before.1.abs() && at.1.abs() <= after.1.abs();
+    if !(minimum && ys.iter().all(|y| y.is_finite() && *y != 0.0 && (*y > 0.0) == (at.1 > 0.0))) {
+        return None;
+    }
+    // The central difference changes sign at the minimum, exactly there for a parabola
+    let delta = (after.0 - before.0) * 1e-3;
+    let mut slope = |x : f64| f(x + delta) - f(x - delta);
+    let (s0, s1) = (slope(before.0), slope(after.0));
+    if !((s0 < 0.0) != (s1 < 0.0) && s0 != 0.0 && s1 != 0.0) { return None; }
+    let minimum = brent(&mut slope, before.0, after.0, s0, s1, tolerance)?;
+    let y = f(minimum.x);
+    (y.abs() <= MAX_TOUCHING_RESIDUAL * before.1.abs().min(after.1.abs())).then(|| Root {
+        x : minimum.x,
+        y,
+        multiplicity : multiplicity(f, minimum.x, after.0 - at.0, Some(false)),
+        iterations : minimum.iterations,
+        converged : minimum.converged
+    })
+}
+
+// f grows like |x - root|^m, compared a bit away from the root on both sides.
+// `crosses` gives the parity when known, odd for a sign change.
+fn multiplicity(f : &mut impl FnMut(f64) -> f64, root : f64, step : f64, crosses : Option<bool>) -> u32 {
+    let h = step / 64.0;
+    let mut growth = |h : f64| (f(root + 2.0 * h) / f(root + h)).abs().log2();
+    let estimates : Vec<f64> = [growth(h), growth(-h)].into_iter().filter(|m| m.is_finite()).collect();
+    let m = if estimates.is_empty() { 1.0 } else { estimates.iter().sum::<f64>() / estimates.len() as f64 };
+    match crosses {
+        Some(true) => (2.0 * ((m - 1.0) / 2.0).round() + 1.0).max(1.0) as u32,
+        Some(false) => (2.0 * (m / 2.0).round()).max(2.0) as u32,
+        None => m.round().max(1.0) as u32
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use super::{Expression, Function, Multi, Operator, Simple, Span};
+
+/// Folds constant subtrees, removes identities like x*1 or x+0 and annihilators like 0*x, and combines like terms like 2x + 3x.
+/// Assignments, calls and sums are never dropped nor merged, and terms on both sides of them are not merged together.
+/// Terms that can be undefined like ln(x) or 1/x are not dropped nor merged either, and only powers with non-negative integer
+/// exponents are merged, so ln(x) - ln(x), x*x^-1 or x^0.5*x^0.5 stay NaN where the original is.
+/// Constants are folded in double precision, so the result can differ from an f32 evaluation in the last bits
+/// or where f32 overflows in between, and where the original gives NaN like 0*x with x infinite.
+pub fn simplify(e : &Expression) -> Expression {
+    let span = e.span;
+    let e = Expression::node(e.function.clone(), e.params.iter().map(simplify).collect(), span);
+    if e.value().is_none() && is_closed(&e, &mut vec![]) {
+        // Errors like too many iterations are kept for the evaluation to report,
+        // NaN too since it can have a complex value like sqrt(-1)
+        if let Ok(c) = e.try_evaluate_f64(0.0, &mut HashMap::new()) && !c.is_nan() { return Expression::constant(c, span); }
+    }
+
+    match (&e.function, &e.params[..]) {
+        (Function::If, [condition, if_true, if_false]) => match condition.value() {
+            Some(c) if c > 0.0 => if_true.clone(),
+            Some(_) => if_false.clone(),
+            None => e
+        },
+        (Function::MultiFunction(Multi::Add | Multi::Sub | Multi::Mul, _), [_, _]) | (Function::SimpleFunction(Simple::Plus | Simple::Minus | Simple::Square, _), [_]) => sum(&e),
+        (Function::MultiFunction(Multi::Pow | Multi::PowCall, _), [u, v]) => match (u.value(), v.value()) {
+            (_, Some(1.0)) => u.clone(),
+            // Also 1 for NaN
+            (_, Some(0.0)) if is_pure(u) => Expression::constant(1.0, span),
+            (Some(1.0), _) if is_pure(v) => Expression::constant(1.0, span),
+            // A power of a product is kept, its factors may be merged with others
+            (_, Some(_)) => sum(&e),
+            _ => e
+        },
+        (Function::MultiFunction(Multi::Div, _), [u, v]) => match v.value() {
+            Some(1.0) => u.clone(),
+            Some(-1.0) => Expression::operator(Operator::USub, vec![u.clone()], span),
+            _ => e
+        },
+        _ => e
+    }
+}
+
+// Can be changed or dropped without changing what the evaluation does besides its value
+fn is_pure(e : &Expression) -> bool {
+    !matches!(e.function, Function::Assign | Function::Call(_) | Function::Iterator(_)) && e.params.iter().all(is_pure)
+}
+
+// Finite for any finite x and variables short of overflowing, so it can be dropped when multiplied by 0 or cancelled
+fn is_defined(e : &Expression) -> bool {
+    let params = e.params.iter().all(is_defined);
+    match &e.function {
+        Function::Constant(c) => c.is_finite(),
+        Function::InputX | Function::Variable(_) => params,
+        Function::MultiFunction(Multi::Add | Multi::Sub | Multi::Mul | Multi::Max | Multi::Min | Multi::Equal | Multi::NotEqual | Multi::LessEqual | Multi::Less | Multi::GreaterEqual | Multi::Greater, _) | Function::If => params,
+        Function::SimpleFunction(Simple::Plus | Simple::Minus | Simple::Square | Simple::Sin | Simple::Cos | Simple::Abs | Simple::Ceil | Simple::Floor | Simple::Round | Simple::Atan | Simple::Tanh, _) => params,
+        Function::MultiFunction(Multi::Pow | Multi::PowCall, _) => params && e.params.get(1).and_then(|n| n.value()).is_some_and(is_natural),
+        _ => false
+    }
+}
+
+fn is_natural(n : f64) -> bool {
+    n >= 0.0 && n.fract() == 0.0
+}
+
+// Only depends on constants and the iterators of the sums in `bound`
+fn is_closed<'a>(e : &'a Expression, bound : &mut Vec<&'a str>) -> bool {
+    match (&e.function, &e.params[..]) {
+        (Function::Iterator(_), [start, end, body]) => {
+            let (Function::Assign, [iterator, first]) = (&start.function, &start.params[..]) else { return false; };
+            let Function::Variable(it) = &iterator.function else { return false; };
+            if !is_closed(first, bound) || !is_closed(end, bound) { return false; }
+            bound.push(it);
+            let closed = is_closed(body, bound);
+            bound.pop();
+            closed
+        },
+        // The variable is bound in the leading arguments
+        (Function::Numeric(method), params) => {
+            let Some((bodies, variable, params)) = method.parts(params) else { return false; };
+            if !params.iter().all(|p| is_closed(p, bound)) { return false; }
+            bound.push(variable);
+            let closed = bodies.iter().all(|body| is_closed(body, bound));
+            bound.pop();
+            closed
+        },
+        (Function::Variable(name), params) => bound.contains(&name.as_str()) && params.iter().all(|p| is_closed(p, bound)),
+        (Function::InputX, _) => bound.contains(&"x"),
+        (Function::Call(_) | Function::Assign | Function::Iterator(_), _) => false,
+        (_, params) => params.iter().all(|p| is_closed(p, bound)),
+    }
+}
+
+// Rewrites a sum as its terms with their coefficient, like terms being merged, and the constant last
+fn sum(e : &Expression) -> Expression {
+    let span = e.span;
+    let mut terms = vec![];
+    let mut constant = 0.0;
+    collect_terms(e, 1.0, &mut terms, &mut constant);
+    // Overflowing coefficients would give NaN where the original order did not
+    if !constant.is_finite() || terms.iter().any(|(c, _)| !c.is_finite()) { return e.clone(); }
+
+    let mut res : Option<Expression> = None;
+    let pieces = terms.into_iter()
+        .filter(|(c, term)| *c != 0.0 || !is_defined(term))
+        .map(|(c, term)| (c, Some(term)))
+        .chain((constant != 0.0 || constant.is_nan()).then_some((constant, None)));
+    for (c, term) in pieces {
+        res = Some(match res {
+            // The first term carries its sign
+            None => match term {
+                None => Expression::constant(c, span),
+                Some(term) if c == 1.0 => term,
+                Some(term) if c == -1.0 => Expression::operator(Operator::USub, vec![term], span),
+                Some(term) => Expression::operator(Operator::Mul, vec![Expression::constant(c, span), term], span),
+            },
+            Some(acc) => {
+                let piece = match term {
+                    None => Expression::constant(c.abs(), span),
+                    Some(term) if c.abs() == 1.0 => term,
+                    Some(term) => Expression::operator(Operator::Mul, vec![Expression::constant(c.abs(), span), term], span),
+                };
+                let o = if c < 0.0 { Operator::Sub } else { Operator::Add };
+                Expression::operator(o, vec![acc, piece], span)
+            }
+        });
+    }
+    res.unwrap_or_else(|| Expression::constant(0.0, span))
+}
+
+fn collect_terms(e : &Expression, sign : f64, terms : &mut Vec<(f64, Expression)>, constant : &mut f64) {
+    match (&e.function, &e.params[..]) {
+        (Function::MultiFunction(Multi::Add, _), [a, b]) => {
+            collect_terms(a, sign, terms, constant);
+            collect_terms(b, sign, terms, constant);
+        },
+        (Function::MultiFunction(Multi::Sub, _), [a, b]) => {
+            collect_terms(a, sign, terms, constant);
+            collect_terms(b, -sign, terms, constant);
+        },
+        (Function::SimpleFunction(Simple::Plus, _), [a]) => collect_terms(a, sign, terms, constant),
+        (Function::SimpleFunction(Simple::Minus, _), [a]) => collect_terms(a, -sign, terms, constant),
+        (Function::Constant(c), []) => *constant += sign * c,
+        _ => {
+            let (c, term) = product(e);
+            match term {
+                None => *constant += sign * c,
+                // Only merged with the terms since the last impure one, a read does not move across an assignment
+                Some(term) => match terms.iter_mut().rev().take_while(|(_, other)| is_pure(other)).find(|(_, other)| is_defined(&term) && *other == term) {
+                    Some((other_c, _)) => *other_c += sign * c,
+                    None => terms.push((sign * c, term))
+                }
+            }
+        }
+    }
+}
+
+// Coefficient and other factors of a product, powers of the same pure factor being merged
+fn product(e : &Expression) -> (f64, Option<Expression>) {
+    let span = e.span;
+    let mut coefficient = 1.0;
+    let mut factors = vec![];
+    collect_factors(e, &mut coefficient, &mut factors);
+    if !coefficient.is_finite() { return (1.0, Some(e.clone())); }
+    if coefficient == 0.0 && factors.iter().all(|(base, exponent)| is_defined(base) && is_natural(*exponent)) { return (0.0, None); }
+
+    let mut res : Option<Expression> = None;
+    for (base, exponent) in factors {
+        if exponent == 0.0 && is_pure(&base) { continue; }
+        let factor = if exponent == 1.0 { base } else { power(base, exponent, span) };
+        res = Some(match res {
+            None => factor,
+            Some(acc) => Expression::operator(Operator::Mul, vec![acc, factor], span)
+        });
+    }
+    (coefficient, res)
+}
+
+fn collect_factors(e : &Expression, coefficient : &mut f64, factors : &mut Vec<(Expression, f64)>) {
+    match (&e.function, &e.params[..]) {
+        (Function::MultiFunction(Multi::Mul, _), [a, b]) => {
+            collect_factors(a, coefficient, factors);
+            collect_factors(b, coefficient, factors);
+        },
+        (Function::Constant(c), []) => *coefficient *= c,
+        (Function::SimpleFunction(Simple::Minus, _), [a]) => {
+            *coefficient = -*coefficient;
+            collect_factors(a, coefficient, factors);
+        },
+        (Function::SimpleFunction(Simple::Square, _), [a]) => add_factor(a, 2.0, factors),
+        (Function::MultiFunction(Multi::Pow | Multi::PowCall, _), [a, b]) if b.value().is_some() => add_factor(a, b.value().unwrap_or(1.0), factors),
+        _ => add_factor(e, 1.0, factors)
+    }
+}
+
+fn add_factor(base : &Expression, exponent : f64, factors : &mut Vec<(Expression, f64)>) {
+    // Exponents too far apart would lose the parity of the smaller one
+    let exact = |other_exponent : f64| (other_exponent + exponent) - exponent == other_exponent && (other_exponent + exponent) - other_exponent == exponent;
+    // Same for the factors since the last impure one, and x^-1*x is NaN at 0 where x^0 is 1
+    let merged = |other_exponent : f64| is_natural(exponent) && is_natural(other_exponent) && exact(other_exponent);
+    match factors.iter_mut().rev().take_while(|(other, _)| is_pure(other)).find(|(other, other_exponent)| is_pure(base) && other == base && merged(*other_exponent)) {
+        Some((_, other_exponent)) => *other_exponent += exponent,
+        None => factors.push((base.clone(), exponent))
+    }
+}
+
+fn power(base : Expression, exponent : f64, span : Span) -> Expression {
+    Expression::operator(Operator::Pow, vec![base, Expression::constant(exponent, span)], span)
+}
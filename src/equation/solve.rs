@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use super::{find_roots, EvalError, Expression, Function, Multi, Simple, UserFunctions};
+
+/// Widest search for the solutions of an equation that is not linear nor quadratic, relative to max(1, |around|)
+const MAX_SEARCH_WIDTH : f64 = 1e6;
+/// Ratio between the widths of two searches, the first one spans [around - 1, around + 1] for |around| <= 1
+const SEARCH_GROWTH : f64 = 10.0;
+
+/// Solutions of an equation found by `find_solutions`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Solutions {
+    /// From the formula of a linear or quadratic equation, sorted and empty when there are none
+    Exact(Vec<f64>),
+    /// From a search widening around a value until it finds some, sorted. Farther ones can be missed.
+    Numeric(Vec<f64>),
+    /// Every value is one, like for x = x
+    All,
+}
+
+impl Solutions {
+    /// Solution closest to `around`, the smaller one of a tie and NaN when there are none
+    pub fn closest(&self, around : f64) -> f64 {
+        match self {
+            Solutions::Exact(xs) | Solutions::Numeric(xs) => xs.iter().copied().min_by(|a, b| (a - around).abs().total_cmp(&(b - around).abs())).unwrap_or(f64::NAN),
+            Solutions::All => around,
+        }
+    }
+}
+
+/// Solutions x of `lhs = rhs` evaluated in double precision, searched from `around` when they cannot be computed exactly
+pub fn solve(lhs : &Expression, rhs : &Expression, around : f64) -> Solutions {
+    solve_with(lhs, rhs, &HashMap::new(), &UserFunctions::new(), around).unwrap_or(Solutions::Exact(vec![]))
+}
+
+/// Same as `solve` with variables and user functions, the first failed evaluation is the error.
+/// Assignments in the expressions are not kept.
+pub fn solve_with(lhs : &Expression, rhs : &Expression, variables : &HashMap<String, f64>, functions : &UserFunctions, around : f64) -> Result<Solutions, EvalError> {
+    let mut scratch = variables.clone();
+    let mut error = None;
+    let mut f = |e : &Expression, x : f64| match e.try_evaluate_f64_with(x, &mut scratch, functions) {
+        Ok(y) => y,
+        Err(e) => { error.get_or_insert(e); f64::NAN }
+    };
+    let degree = polynomial_degree(lhs, "x").zip(polynomial_degree(rhs, "x")).map(|(a, b)| a.max(b));
+    let res = find_solutions(|x| f(lhs, x) - f(rhs, x), degree, around);
+    match error {
+        Some(error) => Err(error),
+        None => Ok(res)
+    }
+}
+
+/// Solutions of f(x) = 0. When f is a polynomial of `degree` up to 2 its coefficients are read from f(-1), f(0) and f(1)
+/// and all the solutions come from the formula, otherwise they are the roots found in [around - w, around + w]
+/// for w growing tenfold from max(1, |around|) up to a million times that.
+pub fn find_solutions(mut f : impl FnMut(f64) -> f64, degree : Option<u32>, around : f64) -> Solutions {
+    if let Some(degree) = degree && degree <= 2 {
+        let (before, at, after) = (f(-1.0), f(0.0), f(1.0));
+        let (mut a, mut b, c) = ((after + before) / 2.0 - at, (after - before) / 2.0, at);
+        // Terms that cancel out like x² - x² leave a coefficient lost in the rounding of the values
+        let noise = 4.0 * f64::EPSILON * before.abs().max(at.abs()).max(after.abs());
+        if a.abs() <= noise { a = 0.0; }
+        if b.abs() <= noise { b = 0.0; }
+        if a.is_finite() && b.is_finite() && c.is_finite() {
+            return quadratic(a, b, c);
+        }
+    }
+    if !around.is_finite() { return Solutions::Numeric(vec![]); }
+    let scale = around.abs().max(1.0);
+    let mut width = scale;
+    while width <= MAX_SEARCH_WIDTH * scale {
+        let roots = find_roots(&mut f, around - width, around + width);
+        if !roots.is_empty() {
+            return Solutions::Numeric(roots.iter().map(|root| root.x).collect());
+        }
+        width *= SEARCH_GROWTH;
+    }
+    Solutions::Numeric(vec![])
+}
+
+/// Highest power of `var` in `e` when it is a polynomial in it, "x" being the input. Other variables are constants.
+/// A call of a user function is not one when it has an argument, its body can read any variable.
+pub fn polynomial_degree(e : &Expression, var : &str) -> Option<u32> {
+    let constant = |p : &Expression| polynomial_degree(p, var) == Some(0);
+    match (&e.function, &e.params[..]) {
+        (Function::InputX | Function::Variable(_), []) => Some(u32::from(e.variable_name() == Some(var))),
+        (Function::MultiFunction(Multi::Add | Multi::Sub, _), [a, b]) => Some(polynomial_degree(a, var)?.max(polynomial_degree(b, var)?)),
+        (Function::MultiFunction(Multi::Mul, _), [a, b]) => Some(polynomial_degree(a, var)?.saturating_add(polynomial_degree(b, var)?)),
+        (Function::MultiFunction(Multi::Div, _), [a, b]) if constant(b) => polynomial_degree(a, var),
+        (Function::SimpleFunction(Simple::Plus | Simple::Minus, _), [a]) => polynomial_degree(a, var),
+        (Function::SimpleFunction(Simple::Square, _), [a]) => Some(polynomial_degree(a, var)?.saturating_mul(2)),
+        (Function::MultiFunction(Multi::Pow | Multi::PowCall, _), [a, b]) => match (polynomial_degree(a, var)?, b.value()) {
+            (0, _) if constant(b) => Some(0),
+            (degree, Some(n)) if n >= 0.0 && n.fract() == 0.0 => Some(degree.saturating_mul(n.min(u32::MAX as f64) as u32)),
+            _ => None
+        },
+        (Function::Call(_), [_, ..]) => None,
+        // Anything else is a constant as long as the variable is not in it
+        (_, params) => params.iter().all(constant).then_some(0),
+    }
+}
+
+// Solutions of a x² + b x + c = 0, a discriminant within the rounding of the coefficients gives a double root like (x - 0.1)²
+fn quadratic(a : f64, b : f64, c : f64) -> Solutions {
+    if a == 0.0 {
+        return match (b, c) {
+            (0.0, 0.0) => Solutions::All,
+            (0.0, _) => Solutions::Exact(vec![]),
+            _ => Solutions::Exact(vec![-c / b])
+        };
+    }
+    let mut discriminant = b * b - 4.0 * a * c;
+    if discriminant.abs() <= 16.0 * f64::EPSILON * (b * b).max((4.0 * a * c).abs()) {
+        discriminant = 0.0;
+    }
+    if discriminant < 0.0 { return Solutions::Exact(vec![]); }
+    if discriminant == 0.0 { return Solutions::Exact(vec![-b / (2.0 * a)]); }
+    // The root away from 0 first, the other one from their product avoids the cancellation of -b + sqrt
+    let q = -(b + b.signum() * discriminant.sqrt()) / 2.0;
+    let (x1, x2) = (q / a, c / q);
+    Solutions::Exact(if x1 <= x2 { vec![x1, x2] } else { vec![x2, x1] })
+}
@@ -1,15 +1,28 @@
+// Public only for the fuzz targets and the benches, the exports below use part of the module shared with the command line
+#[cfg(feature = "internals")]
+pub mod equation;
+#[cfg(not(feature = "internals"))]
+#[allow(dead_code, unused_imports)]
 mod equation;
 use std::sync::{OnceLock, Mutex};
 
 use std::os::raw::{c_char, c_int};
 use std::ptr;
 
-use crate::equation::Expression;
+use crate::equation::{Complex, Dual, Exact, Expression, Interval, ParseError, Rational, UserFunctions};
 
 struct AppState {
-    variables: std::collections::HashMap<String, f32>,
+    // Kept in double precision, the f32 evaluations work on a narrowed copy
+    variables: std::collections::HashMap<String, f64>,
+    // Values evaluate_exact assigned, used by it while the double precision one is still theirs
+    exact_variables: std::collections::HashMap<String, Exact>,
+    functions: UserFunctions,
     expressions: Vec<Expression>,
-    last_error: Option<String>
+    last_error: Option<String>,
+    // With the input it was found in, its spans count chars
+    last_parse_error: Option<(ParseError, String)>,
+    // Parsed and derived expressions go through equation::simplify
+    simplify: bool
 }
 
 static STATE: OnceLock<Mutex<AppState>> = OnceLock::new();
@@ -17,41 +30,541 @@ static STATE: OnceLock<Mutex<AppState>> = OnceLock::new();
 fn get_state() -> &'static Mutex<AppState> {
     STATE.get_or_init(|| Mutex::new(AppState {
         variables: Default::default(),
+        exact_variables: Default::default(),
+        functions: Default::default(),
         expressions: vec![],
-        last_error: None
+        last_error: None,
+        last_parse_error: None,
+        simplify: false
     }))
 }
 
+/// Parses the expression and stores it, returns its index or -1 with the error in get_last_error.
+///
+/// # Safety
+/// `expression` must be null or point to a null-terminated string.
 #[unsafe(no_mangle)]
-pub extern "C" fn parse(expression: *const std::os::raw::c_char) -> i32 {
+pub unsafe extern "C" fn parse(expression: *const std::os::raw::c_char) -> i32 {
     use std::ffi::CStr;
+    if expression.is_null() {
+        return -1;
+    }
+    let input = unsafe { CStr::from_ptr(expression) }.to_string_lossy().into_owned();
+    let mut state = get_state().lock().unwrap();
+    let res = equation::parse_with_functions(&input, &mut state.functions);
+    store_parsed(&mut state, res, input)
+}
+
+/// Same as parse for a LaTeX formula like \frac{1}{2}\rho v^{2}.
+///
+/// # Safety
+/// `expression` must be null or point to a null-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn parse_latex(expression: *const std::os::raw::c_char) -> i32 {
+    use std::ffi::CStr;
+    if expression.is_null() {
+        return -1;
+    }
     let input = unsafe { CStr::from_ptr(expression) }.to_string_lossy().into_owned();
     let mut state = get_state().lock().unwrap();
-    match equation::parse_expression(&input) {
-        Ok(res) => {state.expressions.push(res); state.last_error = None; (state.expressions.len()-1) as i32},
-        Err(e) => {state.last_error = Some(e); -1}
+    let res = equation::parse_latex_with_functions(&input, &state.functions);
+    store_parsed(&mut state, res, input)
+}
+
+// Index of the stored expression, or -1 with the error and its input kept for last_error
+fn store_parsed(state: &mut AppState, res: Result<Expression, ParseError>, input: String) -> i32 {
+    match res {
+        Ok(res) => {let res = if state.simplify { equation::simplify(&res) } else { res }; state.expressions.push(res); state.last_error = None; state.last_parse_error = None; (state.expressions.len()-1) as i32},
+        Err(e) => {state.last_error = Some(e.to_string()); state.last_parse_error = Some((e, input)); -1}
+    }
+}
+
+// Simplifies the expressions parsed or derived afterwards when enabled is not 0
+#[unsafe(no_mangle)]
+pub extern "C" fn set_simplify(enabled: c_int) {
+    get_state().lock().unwrap().simplify = enabled != 0;
+}
+
+// Evaluates the expression at every x under a single lock, failed samples are NaN and flagged in errors
+// Returns the number of failed samples or -1 when the index is not found
+fn evaluate_samples(in_index: i32, xs: impl Iterator<Item = f32>, out: &mut [f32], mut errors: Option<&mut [u8]>) -> c_int {
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return -1;
+    };
+
+    // Compiling is only worth it for more than one sample, recursive functions still need the tree
+    let program = if out.len() > 1 { expression.compile(&state.functions) } else { None };
+    let mut variables = state.variables.iter().map(|(name, value)| (name.clone(), *value as f32)).collect();
+    let mut registers = program.as_ref().map(|program| program.registers(&variables));
+
+    let mut failed = 0;
+    let mut first_error = None;
+    for (i, (x, value)) in xs.zip(out.iter_mut()).enumerate() {
+        let res = match (&program, &mut registers) {
+            (Some(program), Some(registers)) => program.run(x, registers),
+            _ => expression.try_evaluate_with(x, &mut variables, &state.functions)
+        };
+        if let Some(errors) = errors.as_deref_mut() {
+            errors[i] = res.is_err() as u8;
+        }
+        *value = match res {
+            Ok(v) => v,
+            Err(e) => {failed += 1; first_error.get_or_insert(e); f32::NAN}
+        };
+    }
+    if let (Some(program), Some(registers)) = (&program, &registers) {
+        program.store(registers, &mut variables);
+    }
+    // Only the variables the evaluation changed lose their double precision
+    for (name, value) in variables {
+        if state.variables.get(&name).map(|old| *old as f32) != Some(value) {
+            state.variables.insert(name, value as f64);
+        }
     }
+    state.last_error = first_error.map(|e| e.to_string());
+    failed
 }
 
 #[unsafe(no_mangle)]
 pub extern "C" fn evaluate(in_index: i32, x: f32) -> f32 {
-    let index = in_index as usize;
-    let mut state = get_state().lock().unwrap();
+    let mut out = [0.0];
+    evaluate_samples(in_index, std::iter::once(x), &mut out, None);
+    out[0]
+}
+
+// Same as evaluate in double precision, the variables keep it between calls
+#[unsafe(no_mangle)]
+pub extern "C" fn evaluate_f64(in_index: i32, x: f64) -> f64 {
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return f64::NAN;
+    };
+
+    let res = expression.try_evaluate_f64_with(x, &mut state.variables, &state.functions);
+    match res {
+        Ok(value) => {state.last_error = None; value},
+        Err(e) => {state.last_error = Some(e.to_string()); f64::NAN}
+    }
+}
+
+// The shortest decimal of the value, 0.1 is 1/10 and not the nearest binary fraction
+fn exact_from_f64(value: f64) -> Exact {
+    Rational::from_decimal(&value.to_string()).map_or(Exact::Float(value), Exact::Rational)
+}
+
+/// Evaluates the expression at in_index with exact fractions, writes its value to value when not null
+/// and the fraction like 7/2 into buf. Returns the length of the fraction as write_expression does,
+/// 0 when a function like sin made the result a float and -1 with the error in get_last_error when
+/// the evaluation failed. The expression is evaluated once so assignments only happen once.
+/// Variables it assigns keep their fraction for the next exact evaluations, until another evaluation changes them.
+///
+/// # Safety
+/// `value` must be null or valid for writes, `buf` null or valid for writes of `buf_len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn evaluate_exact(in_index: i32, x: f64, value: *mut f64, buf: *mut c_char, buf_len: c_int) -> c_int {
+    if buf.is_null() || buf_len <= 0 {
+        return -1;
+    }
+
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return -1;
+    };
+
+    let mut variables = state.variables.iter().map(|(name, value)| {
+        let exact = state.exact_variables.get(name).filter(|exact| exact.to_f64() == *value);
+        (name.clone(), exact.cloned().unwrap_or_else(|| exact_from_f64(*value)))
+    }).collect();
+    let res = expression.try_evaluate_exact_with(Exact::from_f64(x), &mut variables, &state.functions);
+    for (name, value) in variables {
+        state.variables.insert(name.clone(), value.to_f64());
+        state.exact_variables.insert(name, value);
+    }
+    let res = match res {
+        Ok(res) => {state.last_error = None; res},
+        Err(e) => {state.last_error = Some(e.to_string()); return -1;}
+    };
+    if !value.is_null() {
+        unsafe { *value = res.to_f64(); }
+    }
+    match res {
+        Exact::Rational(r) => unsafe { write_text(&r.to_string(), buf, buf_len) },
+        Exact::Float(_) => 0
+    }
+}
+
+/// Evaluates the expression at in_index with complex numbers and writes the result into re and im.
+/// Assignments are not kept, so it can follow another evaluation of the same expression.
+/// Returns 0, or -1 with the error in get_last_error when the evaluation failed.
+///
+/// # Safety
+/// `re` and `im` must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn evaluate_complex(in_index: i32, x: f64, re: *mut f64, im: *mut f64) -> c_int {
+    if re.is_null() || im.is_null() {
+        return -1;
+    }
+
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return -1;
+    };
+
+    let mut variables = state.variables.iter().map(|(name, value)| (name.clone(), Complex::real(*value))).collect();
+    match expression.try_evaluate_complex_with(Complex::real(x), &mut variables, &state.functions) {
+        Ok(value) => {
+            state.last_error = None;
+            unsafe {
+                *re = value.re;
+                *im = value.im;
+            }
+            0
+        },
+        Err(e) => {state.last_error = Some(e.to_string()); -1}
+    }
+}
+
+/// Evaluates the expression at in_index and its derivative at x, writing them into out_value and out_deriv.
+/// The stored variables are constants and assignments are not kept.
+/// Returns 0, or -1 with the error in get_last_error when the evaluation failed.
+///
+/// # Safety
+/// `out_value` and `out_deriv` must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn evaluate_with_derivative(in_index: i32, x: f64, out_value: *mut f64, out_deriv: *mut f64) -> c_int {
+    if out_value.is_null() || out_deriv.is_null() {
+        return -1;
+    }
+
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return -1;
+    };
+
+    let mut variables = state.variables.iter().map(|(name, value)| (name.clone(), Dual::constant(*value))).collect();
+    match expression.try_evaluate_dual_with(Dual::variable(x), &mut variables, &state.functions) {
+        Ok(value) => {
+            state.last_error = None;
+            unsafe {
+                *out_value = value.value;
+                *out_deriv = value.deriv;
+            }
+            0
+        },
+        Err(e) => {state.last_error = Some(e.to_string()); -1}
+    }
+}
+
+/// Evaluates the expression for every x between lo and hi and writes bounds of the values into out_lo and out_hi.
+/// The bounds are NaN when the expression has no real value there. Assignments are not kept.
+/// Returns 0, or -1 with the error in get_last_error when the evaluation failed.
+///
+/// # Safety
+/// `out_lo` and `out_hi` must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn evaluate_interval(in_index: i32, lo: f64, hi: f64, out_lo: *mut f64, out_hi: *mut f64) -> c_int {
+    if out_lo.is_null() || out_hi.is_null() {
+        return -1;
+    }
+
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return -1;
+    };
+
+    let mut variables = state.variables.iter().map(|(name, value)| (name.clone(), Interval::point(*value))).collect();
+    match expression.try_evaluate_interval_with(Interval::new(lo, hi), &mut variables, &state.functions) {
+        Ok(value) => {
+            state.last_error = None;
+            unsafe {
+                *out_lo = value.lo;
+                *out_hi = value.hi;
+            }
+            0
+        },
+        Err(e) => {state.last_error = Some(e.to_string()); -1}
+    }
+}
+
+/// Evaluates len samples from xs into out, errors can be null or receive 1 for every failed sample.
+///
+/// # Safety
+/// `xs` and `out` must be null or valid for `len` values, `errors` null or valid for writes of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn evaluate_many(in_index: i32, xs: *const f32, out: *mut f32, errors: *mut u8, len: c_int) -> c_int {
+    if xs.is_null() || out.is_null() || len < 0 {
+        return -1;
+    }
+    let len = len as usize;
+    let xs = unsafe { std::slice::from_raw_parts(xs, len) };
+    let out = unsafe { std::slice::from_raw_parts_mut(out, len) };
+    let errors = (!errors.is_null()).then(|| unsafe { std::slice::from_raw_parts_mut(errors, len) });
+    evaluate_samples(in_index, xs.iter().copied(), out, errors)
+}
+
+/// Same as evaluate_many on count x evenly spaced from start to end included.
+///
+/// # Safety
+/// `out` must be null or valid for writes of `count` values, `errors` null or valid for writes of `count` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn evaluate_range(in_index: i32, start: f32, end: f32, count: c_int, out: *mut f32, errors: *mut u8) -> c_int {
+    if out.is_null() || count < 0 {
+        return -1;
+    }
+    let len = count as usize;
+    let out = unsafe { std::slice::from_raw_parts_mut(out, len) };
+    let errors = (!errors.is_null()).then(|| unsafe { std::slice::from_raw_parts_mut(errors, len) });
+    let step = (end - start) / (len.max(2) - 1) as f32;
+    evaluate_samples(in_index, (0..len).map(|i| start + step * i as f32), out, errors)
+}
+
+/// Samples the expression at in_index for x in [xmin, xmax] into polylines inside [ymin, ymax], see equation::sample_function.
+/// Writes x, y pairs into out with a NaN pair between two polylines, truncated to out_len values.
+/// Returns the number of values of the whole result so a larger buffer can be given, -1 when the index is not found.
+/// Samples that fail leave a gap and the first error is kept for get_last_error.
+///
+/// # Safety
+/// `out` must be null or valid for writes of `out_len` values.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn sample_curve(in_index: i32, xmin: f64, xmax: f64, ymin: f64, ymax: f64, tolerance: f64, out: *mut f64, out_len: c_int) -> c_int {
+    if out.is_null() || out_len < 0 {
+        return -1;
+    }
+
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return -1;
+    };
+
+    // Every sample starts from the stored variables, they are not visited in order
+    let mut first_error = None;
+    let polylines = equation::sample_function(|x| match expression.try_evaluate_f64_with(x, &mut state.variables.clone(), &state.functions) {
+        Ok(y) => y,
+        Err(e) => {first_error.get_or_insert(e); f64::NAN}
+    }, xmin, xmax, ymin, ymax, tolerance);
+    state.last_error = first_error.map(|e| e.to_string());
+
+    let mut values = vec![];
+    for polyline in &polylines {
+        if !values.is_empty() { values.extend([f64::NAN, f64::NAN]); }
+        values.extend(polyline.iter().flat_map(|&(x, y)| [x, y]));
+    }
+    let out = unsafe { std::slice::from_raw_parts_mut(out, out_len as usize) };
+    let written = values.len().min(out.len());
+    out[..written].copy_from_slice(&values[..written]);
+    values.len() as c_int
+}
+
+/// Finds the roots of the expression at in_index for x in [a, b], see equation::find_roots.
+/// Writes x, y, multiplicity, iterations and 1 or 0 for converged per root into out, truncated to out_len values.
+/// Returns the number of roots so a larger buffer can be given, -1 when the index is not found or an evaluation fails.
+///
+/// # Safety
+/// `out` must be valid for writes of `out_len` values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn roots(in_index: i32, a: f64, b: f64, out: *mut f64, out_len: c_int) -> c_int {
+    if out.is_null() || out_len < 0 {
+        return -1;
+    }
+
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return -1;
+    };
+
+    let roots = match equation::roots_with(expression, &state.variables, &state.functions, a, b) {
+        Ok(roots) => roots,
+        Err(e) => {
+            state.last_error = Some(e.to_string());
+            return -1;
+        }
+    };
+    state.last_error = None;
+    unsafe { write_roots(&roots, out, out_len) }
+}
+
+/// Finds where the curves of the expressions at index_a and index_b meet for x in [xmin, xmax], see equation::intersections.
+/// Writes the same values as roots per intersection, with y the value of both curves.
+/// Returns the number of intersections so a larger buffer can be given, -1 when an index is not found or an evaluation fails.
+///
+/// # Safety
+/// `out` must be valid for writes of `out_len` values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn intersections(index_a: i32, index_b: i32, xmin: f64, xmax: f64, out: *mut f64, out_len: c_int) -> c_int {
+    if out.is_null() || out_len < 0 {
+        return -1;
+    }
+
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let (Some(a), Some(b)) = (state.expressions.get(index_a as usize), state.expressions.get(index_b as usize)) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return -1;
+    };
+
+    let intersections = match equation::intersections_with(a, b, &state.variables, &state.functions, xmin, xmax) {
+        Ok(intersections) => intersections,
+        Err(e) => {
+            state.last_error = Some(e.to_string());
+            return -1;
+        }
+    };
+    state.last_error = None;
+    unsafe { write_roots(&intersections, out, out_len) }
+}
+
+// Writes x, y, multiplicity, iterations and 1 or 0 for converged per root, returns the number of roots.
+// `out` must be valid for writes of `out_len` values.
+unsafe fn write_roots(roots: &[equation::Root], out: *mut f64, out_len: c_int) -> c_int {
+    let values : Vec<f64> = roots.iter().flat_map(|r| [r.x, r.y, r.multiplicity as f64, r.iterations as f64, if r.converged { 1.0 } else { 0.0 }]).collect();
+    let out = unsafe { std::slice::from_raw_parts_mut(out, out_len as usize) };
+    let written = values.len().min(out.len());
+    out[..written].copy_from_slice(&values[..written]);
+    roots.len() as c_int
+}
+
+/// Finds the local minima, maxima and inflection points of the expression at in_index for x in [a, b], see equation::find_critical_points.
+/// Writes the kind, 0 for a minimum, 1 for a maximum and 2 for an inflection point, then x and y per point into out, truncated to out_len values.
+/// Returns the number of points so a larger buffer can be given, -1 when the index is not found or an evaluation fails.
+///
+/// # Safety
+/// `out` must be valid for writes of `out_len` values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn critical_points(in_index: i32, a: f64, b: f64, out: *mut f64, out_len: c_int) -> c_int {
+    if out.is_null() || out_len < 0 {
+        return -1;
+    }
 
-    if matches!(state.expressions.get(index), Some(_)){
-        // need to remove it from the state to un borrow it
-        let droped = state.expressions.remove(index);
-        let res = droped.evaluate(x, &mut state.variables);
-        state.expressions.insert(index, droped);
-        state.last_error = None;
-        res
-    }else{
-        state.last_error = Some("Index of evaluated expression not found".to_string()); 0.0
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of evaluated expression not found".to_string());
+        return -1;
+    };
+
+    let points = match equation::critical_points_with(expression, &state.variables, &state.functions, a, b) {
+        Ok(points) => points,
+        Err(e) => {
+            state.last_error = Some(e.to_string());
+            return -1;
+        }
+    };
+    state.last_error = None;
+
+    let values : Vec<f64> = points.iter().flat_map(|p| {
+        let kind = match p.kind {
+            equation::PointKind::Minimum => 0.0,
+            equation::PointKind::Maximum => 1.0,
+            equation::PointKind::Inflection => 2.0,
+        };
+        [kind, p.x, p.y]
+    }).collect();
+    let out = unsafe { std::slice::from_raw_parts_mut(out, out_len as usize) };
+    let written = values.len().min(out.len());
+    out[..written].copy_from_slice(&values[..written]);
+    points.len() as c_int
+}
+
+/// Stores the derivative of the expression at in_index with respect to variable, x when null.
+/// Returns the index of the derivative or -1.
+///
+/// # Safety
+/// `variable` must be null or point to a null-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn derive(in_index: i32, variable: *const c_char) -> i32 {
+    use std::ffi::CStr;
+    let var = if variable.is_null() { "x".to_string() } else { unsafe { CStr::from_ptr(variable) }.to_string_lossy().into_owned() };
+    let mut guard = get_state().lock().unwrap();
+    let state = &mut *guard;
+    state.last_parse_error = None;
+    let Some(expression) = state.expressions.get(in_index as usize) else {
+        state.last_error = Some("Index of derived expression not found".to_string());
+        return -1;
+    };
+    let derivative = equation::derive_with(expression, &var, &state.functions);
+    let derivative = if state.simplify { equation::simplify(&derivative) } else { derivative };
+    state.expressions.push(derivative);
+    state.last_error = None;
+    (state.expressions.len()-1) as i32
+}
+
+// Writes the text of the expression at in_index, truncated to buf_len - 1 bytes.
+// Returns the length of the whole text so a larger buffer can be given, -1 when the index is not found.
+unsafe fn write_expression(in_index: i32, buf: *mut c_char, buf_len: c_int, to_text: fn(&Expression) -> String) -> c_int {
+    if buf.is_null() || buf_len <= 0 {
+        return -1;
+    }
+
+    let state = get_state().lock().unwrap();
+    let Some(expression) = state.expressions.get(in_index as usize) else { return -1; };
+    unsafe { write_text(&to_text(expression), buf, buf_len) }
+}
+
+// Copies text truncated to buf_len - 1 bytes and returns its whole length
+unsafe fn write_text(text: &str, buf: *mut c_char, buf_len: c_int) -> c_int {
+    let bytes = text.as_bytes();
+    let len = bytes.len().min((buf_len - 1) as usize);
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), buf as *mut u8, len);
+        *buf.add(len) = 0;
     }
+
+    bytes.len() as c_int
+}
+
+/// Writes the expression at in_index as normalised text, see write_expression.
+///
+/// # Safety
+/// `buf` must be null or valid for writes of `buf_len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn expression_to_string(in_index: i32, buf: *mut c_char, buf_len: c_int) -> c_int {
+    unsafe { write_expression(in_index, buf, buf_len, Expression::to_string) }
 }
 
+/// Writes the LaTeX source of the expression at in_index, see write_expression.
+///
+/// # Safety
+/// `buf` must be null or valid for writes of `buf_len` bytes.
 #[unsafe(no_mangle)]
-pub extern "C" fn get_last_error_buff(buf: *mut c_char, buf_len: c_int) -> c_int {
+pub unsafe extern "C" fn expression_to_latex(in_index: i32, buf: *mut c_char, buf_len: c_int) -> c_int {
+    unsafe { write_expression(in_index, buf, buf_len, Expression::to_latex) }
+}
+
+/// Copies the last error into buf and returns its length.
+///
+/// # Safety
+/// `buf` must be null or valid for writes of `buf_len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn get_last_error_buff(buf: *mut c_char, buf_len: c_int) -> c_int {
     if buf.is_null() || buf_len <= 0 {
         return -1;
     }
@@ -71,15 +584,47 @@ pub extern "C" fn get_last_error_buff(buf: *mut c_char, buf_len: c_int) -> c_int
     len as c_int // return actual written length
 }
 
+/// Gives the code and UTF-16 offsets of the last parse error, the ones of Dart strings, so the app can underline it.
+/// Returns 0 when the last error is not a parse error, -1 on null pointers.
+///
+/// # Safety
+/// `start` and `end` must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn get_last_error_span(start: *mut c_int, end: *mut c_int) -> c_int {
+    if start.is_null() || end.is_null() {
+        return -1;
+    }
+
+    let state = get_state().lock().unwrap();
+    let (code, first, last) = match &state.last_parse_error {
+        Some((e, input)) => {
+            let utf16_offset = |chars : usize| input.chars().take(chars).map(char::len_utf16).sum::<usize>();
+            (e.code(), utf16_offset(e.span().start), utf16_offset(e.span().end))
+        },
+        None => (0, 0, 0)
+    };
+
+    unsafe {
+        *start = first as c_int;
+        *end = last as c_int;
+    }
+
+    code
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn get_last_error() -> *const std::os::raw::c_char {
     let state = get_state().lock().unwrap();
-    let msg = state.last_error.clone().unwrap_or_else(|| "".to_string());
+    let msg = state.last_error.clone().unwrap_or_default();
     std::ffi::CString::new(msg).unwrap().into_raw()
 }
 
+/// Frees a string returned by get_last_error.
+///
+/// # Safety
+/// `ptr` must be null or come from get_last_error, and not be freed twice.
 #[unsafe(no_mangle)]
-pub extern "C" fn free_last_error(ptr: *mut std::os::raw::c_char) {
+pub unsafe extern "C" fn free_last_error(ptr: *mut std::os::raw::c_char) {
     if !ptr.is_null() {
         unsafe { drop(std::ffi::CString::from_raw(ptr)) }
     }